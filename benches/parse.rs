@@ -0,0 +1,99 @@
+//! Compares parsing many short expressions with a fresh `Parser` for
+//! each one against reusing a single `Parser` with `Parser::reset()`,
+//! then times the work on long expressions of increasing length.
+//!
+//! Run with `cargo bench`. Alongside the time taken, the number of
+//! allocations made per expression is counted by a wrapping allocator.
+//! For the long expressions, the time per term should stay level as
+//! the length grows, as anything slower than linear shows up as a
+//! rising time per term.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use simple_expression_parser::{Parser, Tokeniser, Environment};
+
+/// Counts every allocation made through the system allocator.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const EXPRESSIONS: [&str; 4] = ["1 + 2", "x * (y - 3)", "sin(pi / 2)", "2^10 - 24"];
+
+const ITERATIONS: usize = 200_000;
+
+/// The number of terms in each long expression, ten times the last.
+const TERMS: [usize; 3] = [1_000, 10_000, 100_000];
+
+/// Parses every expression `ITERATIONS` times over with `parse_one`,
+/// then prints the time and allocations taken per expression.
+fn bench(name: &str, mut parse_one: impl FnMut(&str)) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for i in 0..ITERATIONS {
+        parse_one(EXPRESSIONS[i % EXPRESSIONS.len()]);
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+
+    println!(
+        "{:<12} {:>10.1?}/expr {:>8.2} allocations/expr",
+        name,
+        elapsed / ITERATIONS as u32,
+        allocations as f64 / ITERATIONS as f64
+    );
+}
+
+/// Builds a long expression of `1` followed by `term` repeated for each
+/// number of `TERMS`, readies it with `prepare`, then prints the time
+/// `run` takes per term.
+fn scaling<T>(name: &str, term: &str, prepare: impl Fn(String) -> T, mut run: impl FnMut(&T)) {
+    for terms in TERMS {
+        let prepared = prepare(format!("1{}", term.repeat(terms)));
+        let start = Instant::now();
+        run(&prepared);
+        let elapsed = start.elapsed();
+
+        println!("{:<12} {:>8} terms {:>10.1?}/term", name, terms, elapsed / terms as u32);
+    }
+}
+
+fn main() {
+    bench("new", |source| {
+        let mut parser = Parser::new(source);
+        black_box(parser.parse().unwrap());
+    });
+
+    let mut parser = Parser::new(String::new());
+    bench("reset", |source| {
+        parser.reset(source);
+        black_box(parser.parse().unwrap());
+    });
+
+    scaling("tokenise", " + 1", |source| source, |source| {
+        black_box(Tokeniser::new(source.as_str()).count());
+    });
+
+    let mut env = Environment::new();
+    env.insert("x", 2.0);
+    env.define_function("slow", |arguments| Ok(arguments[0] * 10.0));
+    scaling("memoized", " + slow(x)", |source| Parser::new(source).parse().unwrap(), |tree| {
+        black_box(tree.evaluate_memoized(&env).unwrap());
+    });
+}
@@ -1,144 +1,4309 @@
+use core::any::Any;
+use core::cell::{Cell, RefCell};
+use core::{cmp::Ordering, fmt, mem};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec
+};
+
+use crate::{
+    error::EvalError,
+    math,
+    parser,
+    tokeniser::Position
+};
+#[cfg(feature = "rational")]
+use crate::rational::Rational;
+
+/// The numeric type used throughout evaluation.
+/// 
+/// Changing this alias is enough to switch the whole
+/// evaluator between `f32` and `f64`.
+pub type Number = f64;
+
+/// A function defined by the caller with `Environment::define_function()`.
+/// 
+/// It's given the values of its arguments, and any error it returns
+/// is reported as `EvalError::FunctionFailed`.
+pub type CustomFunction = Box<dyn Fn(&[Number]) -> Result<Number, String>>;
+
+/// The variables and custom functions available during evaluation.
+#[derive(Default)]
+pub struct Environment {
+    // Assignments bind variables while the environment
+    // is only borrowed, hence the RefCell.
+    variables: RefCell<BTreeMap<String, Number>>,
+    functions: BTreeMap<String, CustomFunction>,
+    step_limit: Option<usize>,
+    division_mode: DivisionMode,
+    // The state of the generator used by `rand()` and `randint()`,
+    // which changes with every number generated.
+    random_state: Cell<u64>,
+    // How many nodes have been evaluated since the limit was set.
+    // Evaluation only borrows the environment, hence the Cell.
+    steps: Cell<usize>,
+    // The subtrees evaluated so far by `Node::evaluate_memoized()`,
+    // or `None` when not memoizing.
+    memo: RefCell<Option<Memo>>,
+    // The operations counted so far by `Node::evaluate_with_stats()`,
+    // or `None` when not counting.
+    stats: RefCell<Option<EvalStats>>,
+    // The steps narrated so far by `explain()`, or `None` when not narrating.
+    narration: RefCell<Option<Vec<String>>>
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Binds the variable `name` to `value`, returning the
+    /// value it was previously bound to, if any.
+    pub fn insert(&mut self, name: impl Into<String>, value: Number) -> Option<Number> {
+        self.variables.get_mut().insert(name.into(), value)
+    }
+
+    /// The value bound to the variable `name`.
+    pub fn get(&self, name: &str) -> Option<Number> {
+        self.variables.borrow().get(name).copied()
+    }
+
+    /// Binds a variable during evaluation, see `Assignment`.
+    fn assign(&self, name: &str, value: Number) {
+        self.variables.borrow_mut().insert(name.to_string(), value);
+    }
+
+    /// Defines a function which can be called by `name`, taking
+    /// priority over any built-in function of the same name.
+    /// 
+    /// The function is responsible for checking its own arguments.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Parser, Environment};
+    /// 
+    /// let mut env = Environment::new();
+    /// env.define_function("double", |arguments| match arguments {
+    ///     [x] => Ok(2.0 * x),
+    ///     _ => Err("expected one argument".to_string())
+    /// });
+    /// 
+    /// let tree = Parser::new("double(21)".to_string()).parse().unwrap();
+    /// assert_eq!(tree.evaluate_with(&env), Ok(42.0));
+    /// ```
+    pub fn define_function(
+        &mut self,
+        name: impl Into<String>,
+        function: impl Fn(&[Number]) -> Result<Number, String> + 'static
+    ) {
+        self.functions.insert(name.into(), Box::new(function));
+    }
+
+    /// Limits how many nodes can be evaluated with this environment,
+    /// after which evaluation fails with `EvalError::StepLimitExceeded`.
+    /// There's no limit by default.
+    /// 
+    /// This guards against untrusted expressions which would take too long
+    /// to evaluate. Each node counts as one step, so `1 + 2` takes three.
+    /// The count is shared by every evaluation until the limit is set again.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Parser, Environment, EvalError};
+    /// 
+    /// let mut env = Environment::new();
+    /// env.set_step_limit(Some(3));
+    /// 
+    /// let tree = Parser::new("9!^9!".to_string()).parse().unwrap();
+    /// assert_eq!(tree.evaluate_with(&env), Err(EvalError::StepLimitExceeded { limit: 3 }));
+    /// ```
+    pub fn set_step_limit(&mut self, limit: Option<usize>) {
+        self.step_limit = limit;
+        self.steps.set(0);
+    }
+
+    /// Sets how `/` divides two integers during `Node::evaluate_with()`.
+    /// By default it always divides as floats, so `7/2` is `3.5`.
+    /// 
+    /// With `DivisionMode::Truncating`, dividing two integers truncates
+    /// towards zero, so `7/2` is `3` and `-7/2` is `-3`. The operands are
+    /// evaluated as in `Node::evaluate_exact_with()`, so an operand is only
+    /// an integer if it's made up of integer literals and operations whose
+    /// results are integers. Once a float appears, such as a float
+    /// literal, a constant, a variable or a function call, the division is
+    /// a float division, so `7.0/2` is still `3.5`.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Parser, Environment, DivisionMode};
+    /// 
+    /// let evaluate = |source: &str, env: &Environment| {
+    ///     Parser::new(source.to_string()).parse().unwrap().evaluate_with(env)
+    /// };
+    /// let mut env = Environment::new();
+    /// assert_eq!(evaluate("7/2", &env), Ok(3.5));
+    /// 
+    /// env.set_division_mode(DivisionMode::Truncating);
+    /// assert_eq!(evaluate("7/2", &env), Ok(3.0));
+    /// assert_eq!(evaluate("-7/2", &env), Ok(-3.0));
+    /// assert_eq!(evaluate("7.0/2", &env), Ok(3.5));
+    /// assert_eq!(evaluate("1 + 7/2 * 2.0", &env), Ok(7.0));
+    /// ```
+    pub fn set_division_mode(&mut self, mode: DivisionMode) {
+        self.division_mode = mode;
+    }
+
+    /// Seeds the generator used by the built-in functions `rand()` and
+    /// `randint()`, so that evaluating with the same seed always generates
+    /// the same numbers. Without a seed, the generator starts as if seeded
+    /// with `0`.
+    /// 
+    /// Every number generated advances the generator, so an environment
+    /// reused for another evaluation continues where the last one stopped.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Parser, Environment};
+    /// 
+    /// let tree = Parser::new("rand() + randint(1, 6)".to_string()).parse().unwrap();
+    /// let evaluate_seeded = |seed| {
+    ///     let mut env = Environment::new();
+    ///     env.set_seed(seed);
+    ///     tree.evaluate_with(&env).unwrap()
+    /// };
+    /// assert_eq!(evaluate_seeded(42), evaluate_seeded(42));
+    /// assert_ne!(evaluate_seeded(42), evaluate_seeded(43));
+    /// ```
+    pub fn set_seed(&mut self, seed: u64) {
+        self.random_state.set(seed);
+    }
+
+    /// The next number from the generator, in the range [0, 1).
+    fn random(&self) -> Number {
+        let mut state = self.random_state.get();
+        let random = math::random(&mut state);
+        self.random_state.set(state);
+        random
+    }
+
+    /// Counts the evaluation of a node against the step limit.
+    fn step(&self) -> Result<(), EvalError> {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        match self.step_limit {
+            Some(limit) if steps > limit => Err(EvalError::StepLimitExceeded { limit }),
+            _ => Ok(())
+        }
+    }
+
+    /// Evaluates `node` and everything below it, one operand at a time
+    /// with `Node::evaluate_step()`, keeping the nodes part way through
+    /// being evaluated on a stack rather than recursing. This is how
+    /// every node with children is evaluated by `evaluate_with()`, so
+    /// the depth of a tree is only limited by memory.
+    /// 
+    /// When memoizing, a subtree identical to one which has
+    /// already been evaluated isn't evaluated again.
+    fn evaluate(&self, node: &dyn Node) -> Result<Number, EvalError> {
+        self.evaluate_value(node, false).map(Number::from)
+    }
+
+    /// Evaluates `node` exactly like `evaluate()`, which is how every node
+    /// with children is evaluated by `evaluate_exact_with()`.
+    fn evaluate_exact(&self, node: &dyn Node) -> Result<Value, EvalError> {
+        self.evaluate_value(node, true)
+    }
+
+    /// Evaluates `node` on a stack, see `evaluate()`, either exactly
+    /// or as a float. Each operand is evaluated the same way as the
+    /// node asking for it, unless the node asks otherwise.
+    fn evaluate_value(&self, node: &dyn Node, exact: bool) -> Result<Value, EvalError> {
+        // The first node evaluated while memoizing is the root of the tree.
+        if let Some(memo) = self.memo.borrow_mut().as_mut() {
+            memo.keys.get_or_insert_with(|| Memo::keys(node));
+        }
+        let mut stack: Vec<Frame<'_>> = Vec::new();
+        let (mut next, mut next_exact) = (node, exact);
+        loop {
+            // Only floats are memoized, as an exact value may be an integer instead.
+            let key = match next_exact {
+                true => None,
+                false => self.memo.borrow().as_ref().and_then(|memo| memo.key(next))
+            };
+            let mut value = key
+                .and_then(|key| self.memo.borrow().as_ref().and_then(|memo| memo.values.get(&key).copied()))
+                .map(Value::Float);
+            if value.is_none() {
+                stack.push(Frame { node: next, operands: Vec::new(), key, exact: next_exact });
+            }
+            // Steps through the nodes on the stack,
+            // until one asks for another operand.
+            loop {
+                if let Some(value) = value.take() {
+                    match stack.last_mut() {
+                        Some(frame) => frame.operands.push(value),
+                        None => return Ok(value)
+                    }
+                }
+                let frame = stack.last().expect("the stack is only empty once the value is returned");
+                let (node, exact) = (frame.node, frame.exact);
+                match node.evaluate_step(&frame.operands, self)? {
+                    EvalStep::Evaluate(operand) => {
+                        (next, next_exact) = (operand, exact);
+                        break;
+                    },
+                    EvalStep::EvaluateExact(operand) => {
+                        (next, next_exact) = (operand, true);
+                        break;
+                    },
+                    EvalStep::EvaluateFloat(operand) => {
+                        (next, next_exact) = (operand, false);
+                        break;
+                    },
+                    EvalStep::Done(result) => {
+                        // A node evaluated as a float forgets whether it's an integer.
+                        let result = if exact { result } else { Value::Float(result.into()) };
+                        if let Some(key) = stack.pop().and_then(|frame| frame.key) {
+                            if let Some(memo) = self.memo.borrow_mut().as_mut() {
+                                memo.values.insert(key, result.into());
+                            }
+                        }
+                        value = Some(result);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs `evaluate` with every subtree it evaluates memoized.
+    fn memoize(&self, evaluate: impl FnOnce() -> Result<Number, EvalError>) -> Result<Number, EvalError> {
+        let previous = self.memo.replace(Some(Memo::default()));
+        let result = evaluate();
+        self.memo.replace(previous);
+        result
+    }
+
+    /// Runs `evaluate`, counting the operations it performs.
+    fn collect_stats(&self, evaluate: impl FnOnce() -> Result<Number, EvalError>) -> (Result<Number, EvalError>, EvalStats) {
+        let previous = self.stats.replace(Some(EvalStats::default()));
+        let result = evaluate();
+        let stats = self.stats.replace(previous).unwrap_or_default();
+        (result, stats)
+    }
+
+    /// Counts an operation if stats are being collected.
+    fn record(&self, count: impl FnOnce(&mut EvalStats)) {
+        if let Some(stats) = self.stats.borrow_mut().as_mut() {
+            count(stats);
+        }
+    }
+
+    /// Runs `evaluate`, narrating each step it carries out, see `explain()`.
+    fn collect_narration(&self, evaluate: impl FnOnce() -> Result<Number, EvalError>) -> (Result<Number, EvalError>, Vec<String>) {
+        let previous = self.narration.replace(Some(Vec::new()));
+        let result = evaluate();
+        let steps = self.narration.replace(previous).unwrap_or_default();
+        (result, steps)
+    }
+
+    /// Narrates a step if steps are being narrated.
+    fn narrate(&self, step: impl FnOnce() -> String) {
+        if let Some(steps) = self.narration.borrow_mut().as_mut() {
+            steps.push(step());
+        }
+    }
+
+    /// The custom function defined as `name`.
+    fn function(&self, name: &str) -> Option<&CustomFunction> {
+        self.functions.get(name)
+    }
+}
+
+/// A node part way through being evaluated by `Environment::evaluate()`,
+/// along with the values of its operands evaluated so far.
+struct Frame<'a> {
+    node: &'a dyn Node,
+    operands: Vec<Value>,
+    // The key the node's value is memoized under, if memoizing.
+    key: Option<usize>,
+    // Whether the node is evaluated exactly, see Node::evaluate_exact_with().
+    exact: bool
+}
+
+/// The subtrees of a tree being evaluated by `Node::evaluate_memoized()`,
+/// where identical subtrees share a key, and the values found for each key.
+#[derive(Default)]
+struct Memo {
+    // The key of each subtree by the address of its root,
+    // found once the root of the tree is known.
+    keys: Option<BTreeMap<usize, usize>>,
+    values: BTreeMap<usize, Number>
+}
+
+impl Memo {
+    /// Keys every subtree of `root` which can be memoized.
+    /// 
+    /// Subtrees are keyed bottom up, so that a node is identified by its
+    /// own operation and the keys of its children, and each node is only
+    /// looked at once. A node without children is identified by its JSON.
+    /// A node not defined by this crate which has children can't be
+    /// identified like this, so isn't memoized, nor is anything above it.
+    /// Neither is a call to a random function, whose value changes.
+    fn keys(root: &dyn Node) -> BTreeMap<usize, usize> {
+        let mut ids: BTreeMap<(String, Vec<usize>), usize> = BTreeMap::new();
+        let mut keys = BTreeMap::new();
+        // Each node is pushed again after its children,
+        // to be keyed once they've all been keyed.
+        let mut stack = vec![(root, false)];
+        while let Some((node, children_keyed)) = stack.pop() {
+            let children = node.children();
+            if !children_keyed {
+                stack.push((node, true));
+                stack.extend(children.into_iter().map(|child| (child, false)));
+                continue;
+            }
+            let label = if children.is_empty() { Some(node.to_json()) } else { operation(node) };
+            let children: Option<Vec<usize>> = children.into_iter()
+                .map(|child| keys.get(&address(child)).copied())
+                .collect();
+            // Every node without a size shares the same address.
+            let random = node.as_any().downcast_ref::<FunctionCall>().is_some_and(FunctionCall::is_random);
+            if let (Some(label), Some(children), false) = (label, children, random || mem::size_of_val(node) == 0) {
+                let next = ids.len();
+                let id = *ids.entry((label, children)).or_insert(next);
+                keys.insert(address(node), id);
+            }
+        }
+        keys
+    }
+
+    /// The key of the subtree rooted at `node`, if it can be memoized.
+    fn key(&self, node: &dyn Node) -> Option<usize> {
+        self.keys.as_ref()?.get(&address(node)).copied()
+    }
+}
+
+/// Identifies `node` by where it's stored.
+pub(crate) fn address(node: &dyn Node) -> usize {
+    node as *const dyn Node as *const () as usize
+}
+
+/// What happens next while evaluating a node, see `Node::evaluate_step()`.
+pub enum EvalStep<'a> {
+    /// Evaluates another of the node's operands, whose value is
+    /// then passed back to the node along with the others. It's
+    /// evaluated exactly if the node itself is.
+    Evaluate(&'a dyn Node),
+    /// Evaluates another operand exactly, as by `Node::evaluate_exact_with()`,
+    /// even if the node itself isn't, as for a truncating division.
+    EvaluateExact(&'a dyn Node),
+    /// Evaluates another operand as a float, even if
+    /// the node itself is exact, as for function arguments.
+    EvaluateFloat(&'a dyn Node),
+    /// Finishes the node with its value, which is only
+    /// kept as an integer if the node is evaluated exactly.
+    Done(Value)
+}
+
+/// How `/` divides two integers, see `Environment::set_division_mode()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DivisionMode {
+    /// Always divide as floats, so `7/2` is `3.5`.
+    #[default]
+    Float,
+    /// Truncate towards zero when both operands are integers,
+    /// so `7/2` is `3`, but `7.0/2` is still `3.5`.
+    Truncating
+}
+
+/// Counts of the operations performed while evaluating an expression,
+/// see `Node::evaluate_with_stats()`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EvalStats {
+    operations: BTreeMap<Op, usize>,
+    unary_operations: BTreeMap<UnaryKind, usize>,
+    function_calls: usize
+}
+
+impl EvalStats {
+    /// How many times `op` was applied, such as the number of additions
+    /// for `Op::Add`. A '-' sign is a negation rather than a subtraction,
+    /// see `count_unary()`.
+    pub fn count(&self, op: Op) -> usize {
+        self.operations.get(&op).copied().unwrap_or(0)
+    }
+
+    /// How many times `kind` was applied, such as the number of negations
+    /// for `UnaryKind::Neg`. A '+' sign does nothing, so isn't counted.
+    pub fn count_unary(&self, kind: UnaryKind) -> usize {
+        self.unary_operations.get(&kind).copied().unwrap_or(0)
+    }
+
+    /// How many functions were called, whether built-in or custom.
+    pub fn function_calls(&self) -> usize {
+        self.function_calls
+    }
+
+    /// The number of operations of every kind, including function calls.
+    pub fn total(&self) -> usize {
+        self.operations.values().sum::<usize>()
+            + self.unary_operations.values().sum::<usize>()
+            + self.function_calls
+    }
+
+    fn add(&mut self, op: Op) {
+        *self.operations.entry(op).or_insert(0) += 1;
+    }
+
+    fn add_unary(&mut self, kind: UnaryKind) {
+        *self.unary_operations.entry(kind).or_insert(0) += 1;
+    }
+}
+
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Environment")
+            .field("variables", &self.variables.borrow())
+            .field("functions", &self.functions.keys().collect::<Vec<_>>())
+            .field("step_limit", &self.step_limit)
+            .field("division_mode", &self.division_mode)
+            .finish()
+    }
+}
+
+/// The kind of number held by an operand, as reported by
+/// `EvalError::TypeMismatch`.
+/// 
+/// The kind depends only on the value, so `4/2` is an `Int`
+/// even though it's evaluated as a float.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    /// A whole number which fits in a 64-bit integer, such as `2`.
+    Int,
+    /// Any other number, such as `1.5`, `1e300` or `nan`.
+    Float
+}
+
+impl ValueKind {
+    /// The kind of `value`.
+    pub fn of(value: Number) -> Self {
+        // i64::MAX can't be represented exactly, so the upper bound
+        // is checked against 2^63 instead.
+        if math::fract(value) == 0.0 && value >= i64::MIN as Number && value < -(i64::MIN as Number) {
+            ValueKind::Int
+        } else {
+            ValueKind::Float
+        }
+    }
+}
+
+/// The result of evaluating with `Node::evaluate_exact_with()`,
+/// which keeps integers separate from floats.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(Number)
+}
+
+impl From<Value> for Number {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Int(value) => value as Number,
+            Value::Float(value) => value
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value)
+        }
+    }
+}
+
 // Constant value which defines how many spaces the Node::display()
-// function generates per indentation.
+// function generates per indentation by default.
 const DISPLAY_INDENTATION: usize = 4;
 
+/// Controls how `Node::display_with()` lays out a tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayOptions {
+    /// The number of chars making up each level of indentation.
+    pub indent: usize,
+    /// Whether each level of indentation is drawn as a `|-> `
+    /// connector rather than blank space. Connectors are always
+    /// at least 4 chars wide, so they're padded to `indent`.
+    pub connectors: bool
+}
+
+impl DisplayOptions {
+    /// The indentation at the start of a line which is `depth` levels deep.
+    pub fn indentation(&self, depth: usize) -> String {
+        if self.connectors {
+            format!("{:<1$}", "|->", self.indent.max(4)).repeat(depth)
+        } else {
+            " ".repeat(depth * self.indent)
+        }
+    }
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        Self {
+            indent: DISPLAY_INDENTATION,
+            connectors: false
+        }
+    }
+}
+
 /// Represents the mathematical operations used in nodes suffixed with 'Op'
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Op {
     Add,
     Sub,
     Mult,
-    Div
+    Div,
+    Mod,
+    Pow,
+    Factorial,
+    Percent,
+    /// `x + y%`, which adds `y` percent of `x` to `x`, so `200 + 10%`
+    /// is `220`. Its right hand side is the percentage, such as `10%`.
+    /// It's written with a '+', and only made by the parser in percent
+    /// mode, see `Parser::set_percent_mode()`.
+    AddPercent,
+    /// `x - y%`, which subtracts `y` percent of `x` from `x`,
+    /// see `Op::AddPercent`.
+    SubPercent,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    /// Whether the left hand side lies within the range
+    /// on the right, as in `x in 1..10`, see `Range`.
+    In,
+    /// `x and y`, which is `1` when both sides are true, meaning nonzero,
+    /// and `0` otherwise. Like `or`, it only evaluates its right hand
+    /// side when the left hand side doesn't already decide the result.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Parser, EvalError};
+    /// 
+    /// let evaluate = |source: &str| Parser::new(source).parse().unwrap().evaluate();
+    /// assert_eq!(evaluate("1 > 0 and 2 > 3"), Ok(0.0));
+    /// assert_eq!(evaluate("0 and 1/0"), Ok(0.0));
+    /// assert_eq!(evaluate("1 or 1/0"), Ok(1.0));
+    /// assert!(matches!(evaluate("1 and 1/0"), Err(EvalError::DivByZero { .. })));
+    /// assert!(matches!(evaluate("0 or 1/0"), Err(EvalError::DivByZero { .. })));
+    /// ```
+    And,
+    /// `x or y`, which is `1` when either side is true, see `Op::And`.
+    Or
+}
+
+/// The operations performed by a `UnaryOp`, which are
+/// written before their operand, such as `-x`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UnaryKind {
+    /// `-x`, which negates its operand.
+    Neg,
+    /// `+x`, which leaves its operand unchanged.
+    Pos,
+    /// `not x`, which is `1` when its operand is `0`, and `0` otherwise.
+    Not
+}
+
+impl UnaryKind {
+    /// The symbol used to write the operation in an expression.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryKind::Neg => "-",
+            UnaryKind::Pos => "+",
+            UnaryKind::Not => "not"
+        }
+    }
+
+    /// How tightly the operation binds its operand. Higher values bind
+    /// more tightly, on the same scale as `Op::precedence()`.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            UnaryKind::Not => NOT_PRECEDENCE,
+            UnaryKind::Neg | UnaryKind::Pos => UNARY_PRECEDENCE
+        }
+    }
+}
+
+/// How a chain of operators with the same precedence is grouped,
+/// see `Op::associativity()`.
+/// 
+/// ```
+/// use simple_expression_parser::{evaluate, Associativity, Op};
+/// 
+/// assert_eq!(Op::Sub.associativity(), Associativity::Left);
+/// assert_eq!(evaluate("10 - 2 - 3"), Ok(5.0));
+/// assert_eq!(Op::Div.associativity(), Associativity::Left);
+/// assert_eq!(evaluate("64 / 4 / 2"), Ok(8.0));
+/// assert_eq!(Op::Pow.associativity(), Associativity::Right);
+/// assert_eq!(evaluate("2^3^2"), Ok(512.0));
+/// assert_eq!(Op::Less.associativity(), Associativity::Chain);
+/// assert_eq!(evaluate("3 > 2 > 1"), Ok(1.0));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    /// Grouped from the left, so `a - b - c` is `(a - b) - c`.
+    Left,
+    /// Grouped from the right, so `a ^ b ^ c` is `a ^ (b ^ c)`.
+    Right,
+    /// Not grouped at all, instead `a < b < c` is `a < b and b < c`.
+    Chain
+}
+
+impl Op {
+    /// The symbol used to write the operation in an expression,
+    /// as written by `to_infix()` and in error messages.
+    /// 
+    /// ```
+    /// use simple_expression_parser::Op;
+    /// 
+    /// assert_eq!(Op::Mult.symbol(), "*");
+    /// assert_eq!(Op::BitXor.symbol(), "^^");
+    /// assert_eq!(Op::And.symbol(), "and");
+    /// ```
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mult => "*",
+            Op::Div => "/",
+            Op::Mod => "%",
+            Op::Pow => "^",
+            Op::Factorial => "!",
+            Op::Percent => "%",
+            Op::AddPercent => "+",
+            Op::SubPercent => "-",
+            Op::Equal => "==",
+            Op::NotEqual => "!=",
+            Op::Less => "<",
+            Op::LessEqual => "<=",
+            Op::Greater => ">",
+            Op::GreaterEqual => ">=",
+            Op::BitAnd => "&",
+            Op::BitOr => "|",
+            Op::BitXor => "^^",
+            Op::ShiftLeft => "<<",
+            Op::ShiftRight => ">>",
+            Op::In => "in",
+            Op::And => "and",
+            Op::Or => "or"
+        }
+    }
+
+    /// How tightly the operation binds its operands when written between
+    /// them. The parser uses this to decide how operators are grouped, and
+    /// `to_infix()` to decide where brackets are needed. Higher values
+    /// bind more tightly.
+    /// 
+    /// ```
+    /// use simple_expression_parser::Op;
+    /// 
+    /// assert!(Op::Mult.precedence() > Op::Add.precedence());
+    /// assert!(Op::Pow.precedence() > Op::Mult.precedence());
+    /// assert!(Op::Add.precedence() > Op::Less.precedence());
+    /// assert_eq!(Op::Add.precedence(), Op::Sub.precedence());
+    /// ```
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Op::Or => 1,
+            Op::And => 2,
+            Op::Equal | Op::NotEqual | Op::Less | Op::LessEqual
+            | Op::Greater | Op::GreaterEqual | Op::In => 4,
+            Op::BitOr => 5,
+            Op::BitXor => 6,
+            Op::BitAnd => 7,
+            Op::ShiftLeft | Op::ShiftRight => 8,
+            Op::Add | Op::Sub | Op::AddPercent | Op::SubPercent => 9,
+            Op::Mult | Op::Div | Op::Mod => 10,
+            Op::Pow => 12,
+            Op::Factorial | Op::Percent => POSTFIX_PRECEDENCE
+        }
+    }
+
+    /// How a chain of the operation is grouped, such as `10 - 2 - 3`,
+    /// which is `(10 - 2) - 3`. Every operator is left-associative apart
+    /// from '^', which is right-associative, and the comparisons,
+    /// which chain.
+    pub fn associativity(&self) -> Associativity {
+        match self {
+            Op::Pow => Associativity::Right,
+            op if op.is_comparison() => Associativity::Chain,
+            _ => Associativity::Left
+        }
+    }
+
+    /// Whether the operation is a comparison, such as '<'.
+    pub(crate) fn is_comparison(&self) -> bool {
+        matches!(self, Op::Equal | Op::NotEqual | Op::Less | Op::LessEqual | Op::Greater | Op::GreaterEqual)
+    }
+
+    /// Whether the operation applies a percentage, see `Op::AddPercent`.
+    pub(crate) fn is_percentage(&self) -> bool {
+        matches!(self, Op::AddPercent | Op::SubPercent)
+    }
+
+    /// The operation which adds or subtracts `right`, which applies it to
+    /// the left hand side if it's a percentage, such as `10%`.
+    pub(crate) fn with_percentage(self, right: &dyn Node) -> Op {
+        let percentage = right.as_any().downcast_ref::<PostfixOp>()
+            .is_some_and(|postfix| postfix.op == Op::Percent);
+        match self {
+            Op::Add if percentage => Op::AddPercent,
+            Op::Sub if percentage => Op::SubPercent,
+            op => op
+        }
+    }
+
+    /// Whether the operands of a chain of the operation can be
+    /// reordered freely, see `Node::canonicalize()`.
+    /// 
+    /// `and` and `or` are left out since they only evaluate their right
+    /// operand when needed, so reordering them changes which errors occur.
+    fn is_commutative(&self) -> bool {
+        matches!(self, Op::Add | Op::Mult | Op::BitAnd | Op::BitOr | Op::BitXor)
+    }
 }
 
-/// Every syntax tree object must implement the Node trait.
-pub trait Node {
-    /// Evaluate the node, producing a numerical output.
-    fn evaluate(&self) -> f32;
+// Assignments can only be the whole of a statement, so they're
+// never the operand of anything, see Parser::statement().
+const ASSIGNMENT_PRECEDENCE: u8 = 0;
+
+// Conditionals bind more loosely than any operator,
+// see Parser::expr() for details.
+const CONDITIONAL_PRECEDENCE: u8 = 0;
+
+// `not` sits between `and` and the comparisons,
+// see Parser::prefix() for details.
+const NOT_PRECEDENCE: u8 = 3;
+
+// Negation sits between multiplication and exponentiation,
+// see Parser::prefix() for details.
+pub(crate) const UNARY_PRECEDENCE: u8 = 11;
+
+// Postfix operations bind more tightly than exponentiation,
+// see Parser::postfix() for details.
+const POSTFIX_PRECEDENCE: u8 = 13;
+
+// Nodes which never need brackets around them, such as literals.
+const ATOM_PRECEDENCE: u8 = u8::MAX;
+
+/// Every syntax tree object must implement the Node trait.
+pub trait Node {
+    /// Evaluate the node, producing a numerical output.
+    /// 
+    /// Variables are looked up in `env`. Errors if the expression
+    /// has no meaningful value, such as when dividing by zero or
+    /// referencing a variable which isn't bound.
+    fn evaluate_with(&self, env: &Environment) -> Result<Number, EvalError>;
+
+    /// Evaluates the node one operand at a time, which is how
+    /// `evaluate_with()` evaluates a tree without recursing.
+    /// 
+    /// `operands` holds the values of the operands evaluated so far,
+    /// in the order they were asked for. Each call either asks for
+    /// another operand to be evaluated, or finishes with the node's
+    /// value. `evaluate_exact_with()` steps through the same nodes, so
+    /// operands are integers where they're evaluated exactly, and
+    /// floats otherwise. By default the whole node is evaluated at once
+    /// with `evaluate_exact_with()`, which is all a node without
+    /// children needs.
+    fn evaluate_step<'a>(&'a self, _operands: &[Value], env: &Environment) -> Result<EvalStep<'a>, EvalError> {
+        self.evaluate_exact_with(env).map(EvalStep::Done)
+    }
+
+    /// Evaluate the node without any variables bound.
+    fn evaluate(&self) -> Result<Number, EvalError> {
+        self.evaluate_with(&Environment::new())
+    }
+
+    /// Evaluate the node like `evaluate_with()`, but only evaluate
+    /// each distinct subtree once, reusing its value wherever an
+    /// identical subtree appears again.
+    /// 
+    /// Subtrees are identified by their structure, which is worked out once
+    /// for the whole tree before it's evaluated, in time proportional to
+    /// its size, as the `parse` benchmark shows. This is only worthwhile when there are repeated subtrees which are
+    /// expensive to evaluate, such as calls to a slow custom function.
+    /// Values are only reused within one call. A call to `rand()` or
+    /// `randint()` gives a new number each time, so neither it nor
+    /// anything containing it is reused.
+    /// 
+    /// ```
+    /// use std::{cell::Cell, rc::Rc};
+    /// use simple_expression_parser::{Parser, Environment};
+    /// 
+    /// let calls = Rc::new(Cell::new(0));
+    /// let mut env = Environment::new();
+    /// env.insert("x", 2.0);
+    /// let counter = Rc::clone(&calls);
+    /// env.define_function("slow", move |arguments| {
+    ///     counter.set(counter.get() + 1);
+    ///     Ok(arguments[0] * 10.0)
+    /// });
+    /// 
+    /// let tree = Parser::new("slow(x) + slow(x) * 2".to_string()).parse().unwrap();
+    /// assert_eq!(tree.evaluate_memoized(&env), Ok(60.0));
+    /// assert_eq!(calls.get(), 1);
+    /// 
+    /// let long = Parser::new(format!("1{}", " + slow(x)".repeat(1_000))).parse().unwrap();
+    /// assert_eq!(long.evaluate_memoized(&env), Ok(20_001.0));
+    /// assert_eq!(calls.get(), 2);
+    /// 
+    /// let random = Parser::new("rand() - rand() + slow(randint(1, 6)) - slow(randint(1, 6))").parse().unwrap();
+    /// assert_ne!(random.evaluate_memoized(&env), Ok(0.0));
+    /// ```
+    fn evaluate_memoized(&self, env: &Environment) -> Result<Number, EvalError> {
+        env.memoize(|| self.evaluate_with(env))
+    }
+
+    /// Evaluate the node like `evaluate_with()`, also counting
+    /// how many of each operation were performed.
+    /// 
+    /// Only operations which were actually performed are counted, so
+    /// the side of `and`, `or` or a conditional which isn't needed
+    /// doesn't count, nor does anything after an error.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Parser, Environment, Op};
+    /// 
+    /// let tree = Parser::new("1 + 2 * 3".to_string()).parse().unwrap();
+    /// let (result, stats) = tree.evaluate_with_stats(&Environment::new());
+    /// assert_eq!(result, Ok(7.0));
+    /// assert_eq!(stats.count(Op::Add), 1);
+    /// assert_eq!(stats.count(Op::Mult), 1);
+    /// assert_eq!(stats.total(), 2);
+    /// ```
+    fn evaluate_with_stats(&self, env: &Environment) -> (Result<Number, EvalError>, EvalStats) {
+        env.collect_stats(|| self.evaluate_with(env))
+    }
+
+    /// Evaluate the node, keeping integer arithmetic exact.
+    /// 
+    /// Operations on two integers produce an integer, so `/` is an
+    /// integer division which truncates towards zero. Once a float enters
+    /// the expression, such as a FloatLiteral, a constant, a variable or
+    /// the result of a function, the rest of it is evaluated as floats.
+    /// Integer results which would overflow an i64, or which aren't whole
+    /// (such as `2^-1` or `50%`), are also promoted to floats.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Parser, Value};
+    /// 
+    /// let exact = |source: &str| Parser::new(source.to_string())
+    ///     .parse().unwrap()
+    ///     .evaluate_exact().unwrap();
+    /// assert_eq!(exact("7/2"), Value::Int(3));
+    /// assert_eq!(exact("7.0/2"), Value::Float(3.5));
+    /// assert_eq!(exact("6/3"), Value::Int(2));
+    /// ```
+    fn evaluate_exact_with(&self, env: &Environment) -> Result<Value, EvalError> {
+        self.evaluate_with(env).map(Value::Float)
+    }
+
+    /// Evaluate the node exactly without any variables bound.
+    fn evaluate_exact(&self) -> Result<Value, EvalError> {
+        self.evaluate_exact_with(&Environment::new())
+    }
+
+    /// Evaluate the node as an exact fraction, so that `1/3 + 1/3 + 1/3`
+    /// is exactly `1`. Only available with the `rational` feature.
+    /// 
+    /// Arithmetic, comparisons, `%`, `!`, the bitwise operators and
+    /// powers with an integer exponent are exact, as are `abs()`,
+    /// `floor()`, `ceil()`, `round()`, `min()` and `max()`. Float
+    /// literals and variables are read as the decimal they're written
+    /// as, so `0.1` is `1/10`. Anything else whose value isn't a
+    /// fraction, such as `sqrt(2)`, `pi` or `2^0.5`, is an
+    /// `EvalError::Inexact`, as are custom and random functions.
+    /// A power or factorial too large to calculate in reasonable time is an
+    /// `EvalError::Overflow`, see `rational::MAX_BITS`.
+    /// Use `Rational::to_f64()` to display the result as a float.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Parser, Rational, EvalError};
+    /// 
+    /// let rational = |source: &str| Parser::new(source).parse().unwrap().evaluate_rational();
+    /// assert_eq!(rational("1/3 + 1/3 + 1/3"), Ok(Rational::from(1)));
+    /// assert_eq!(rational("0.1 + 0.2 - 0.3"), Ok(Rational::from(0)));
+    /// assert_eq!(rational("2^-2 * 3"), Ok(Rational::new(3, 4)));
+    /// assert_eq!(rational("(1/3)^-1"), Ok(Rational::from(3)));
+    /// assert_eq!(rational("1/3").map(|value| value.to_string()), Ok("1/3".to_string()));
+    /// assert_eq!(rational("sqrt(4)"), Err(EvalError::Inexact { expression: "sqrt(4)".to_string() }));
+    /// ```
+    #[cfg(feature = "rational")]
+    fn evaluate_rational_with(&self, env: &Environment) -> Result<Rational, EvalError> {
+        env.step()?;
+        Err(EvalError::Inexact { expression: self.to_infix() })
+    }
+
+    /// Evaluate the node as an exact fraction without any variables bound.
+    #[cfg(feature = "rational")]
+    fn evaluate_rational(&self) -> Result<Rational, EvalError> {
+        self.evaluate_rational_with(&Environment::new())
+    }
+
+    /// Display function should produce a string in the following format:
+    /// 
+    /// ```text
+    /// ObjectName {
+    /// |-> attribute1: ChildObject {
+    /// |-> |-> ...
+    /// |-> }
+    /// }
+    /// ```
+    /// 
+    /// Where each `|-> ` is one level of indentation, drawn as set
+    /// by `opts`, and the attributes are indented by `depth`+1 levels.
+    /// 
+    /// Unless `depth` == 0, the first line should not have any indentation,
+    /// as it is inlined with the parent display string.
+    /// 
+    fn display_with(&self, depth: usize, opts: &DisplayOptions) -> String;
+
+    /// Displays the node with the default options,
+    /// indenting by 4 spaces without connectors.
+    fn display(&self, depth: usize) -> String {
+        self.display_with(depth, &DisplayOptions::default())
+    }
+
+    /// Displays the whole tree with a `|-> ` connector
+    /// drawn for each level of indentation.
+    /// 
+    /// Operators are shown by their symbols, as written in an expression.
+    /// 
+    /// ```
+    /// use simple_expression_parser::Parser;
+    /// 
+    /// let tree = Parser::new("1 + 2".to_string()).parse().unwrap();
+    /// assert_eq!(tree.display_tree(), "\
+    /// BinOp {
+    /// |-> left: IntLiteral {
+    /// |-> |-> value: 1
+    /// |-> }
+    /// |-> right: IntLiteral {
+    /// |-> |-> value: 2
+    /// |-> }
+    /// |-> op: +
+    /// }");
+    /// 
+    /// let display = Parser::new("-x * 3!").parse().unwrap().display(0);
+    /// let ops: Vec<&str> = display.lines().filter_map(|line| line.trim().strip_prefix("op: ")).collect();
+    /// assert_eq!(ops, ["-", "!", "*"]);
+    /// assert!(!display.contains("Mult"));
+    /// ```
+    fn display_tree(&self) -> String {
+        self.display_with(0, &DisplayOptions { connectors: true, ..DisplayOptions::default() })
+    }
+
+    /// The names of every variable which the tree references, and so
+    /// which must be bound before it can be evaluated. The variable
+    /// assigned to by an assignment is only included if it's also
+    /// referenced, as in `x = x + 1`.
+    /// 
+    /// ```
+    /// use simple_expression_parser::Parser;
+    /// 
+    /// let parse = |source: &str| Parser::new(source.to_string()).parse().unwrap();
+    /// let variables: Vec<_> = parse("x + y * x").free_variables().into_iter().collect();
+    /// assert_eq!(variables, ["x", "y"]);
+    /// assert!(parse("2 * pi + sin(1)").free_variables().is_empty());
+    /// assert!(parse("x + 1").contains_variable("x"));
+    /// ```
+    fn free_variables(&self) -> BTreeSet<String> {
+        let mut collector = VariableCollector(BTreeSet::new());
+        self.accept(&mut collector);
+        collector.0
+    }
+
+    /// Whether the tree references the variable `name`,
+    /// see `free_variables()`.
+    fn contains_variable(&self, name: &str) -> bool {
+        self.free_variables().contains(name)
+    }
+
+    /// The value and span of every integer and float literal in the
+    /// tree, in the order they appear in the source. Named constants
+    /// such as `pi` aren't literals, so aren't included.
+    /// 
+    /// ```
+    /// use simple_expression_parser::Parser;
+    /// 
+    /// let tree = Parser::new("2 + 3.5 * 2".to_string()).parse().unwrap();
+    /// assert_eq!(tree.literals(), [(2.0, (0, 1)), (3.5, (4, 7)), (2.0, (10, 11))]);
+    /// ```
+    fn literals(&self) -> Vec<(Number, (usize, usize))> {
+        let mut collector = LiteralCollector(Vec::new());
+        self.accept(&mut collector);
+        collector.0
+    }
+
+    /// Reconstructs an expression which parses back into this node,
+    /// e.g. `(1 + 2) * 3`.
+    /// 
+    /// Brackets are only inserted where the precedence or associativity
+    /// of the operators would otherwise change the meaning.
+    fn to_infix(&self) -> String;
+
+    /// Serialises the node and all of its children as a JSON object,
+    /// e.g. `{"type":"BinOp","op":"Add","left":{...},"right":{...}}`.
+    /// 
+    /// Every object has a `type` field naming the node, with the
+    /// remaining fields matching the node's own fields.
+    fn to_json(&self) -> String;
+
+    /// Produces an equivalent tree with constant subexpressions folded,
+    /// e.g. `2 * 3 + x` becomes `6 + x`.
+    /// 
+    /// Identities such as `x * 1`, `x + 0` and `--x` are also removed.
+    /// Subexpressions which fail to evaluate, such as `1/0`, are kept
+    /// as they are so that the error is still reported on evaluation.
+    fn simplify(self: Box<Self>) -> Box<dyn Node>;
+
+    /// Produces the derivative of the node with respect to the variable
+    /// `var`, e.g. `x^2 + 3x` becomes `2 * x^(2 - 1) + 3`, which
+    /// `simplify()` then tidies into `2 * x + 3`.
+    /// 
+    /// Terms which are known to be zero are left out as the derivative
+    /// is built. Anything which doesn't depend on `var` has a derivative
+    /// of zero, but operations which do and have no derivative, such as
+    /// `x!` or `x & 1`, are an error. Function calls are assumed to be
+    /// to the built-in functions, so custom functions can't be
+    /// differentiated. Operators in the derivative weren't parsed from
+    /// the source, so any errors they cause point at its start.
+    /// 
+    /// ```
+    /// use simple_expression_parser::Parser;
+    /// 
+    /// let derivative = |source: &str| Parser::new(source.to_string())
+    ///     .parse().unwrap()
+    ///     .differentiate("x").unwrap()
+    ///     .simplify()
+    ///     .to_infix();
+    /// assert_eq!(derivative("x^2 + 3x"), "2 * x + 3");
+    /// assert_eq!(derivative("sin(2x)"), "cos(2 * x) * 2");
+    /// assert_eq!(derivative("y * x"), "y");
+    /// ```
+    fn differentiate(&self, var: &str) -> Result<Box<dyn Node>, EvalError>;
+
+    /// Produces a canonical form of the tree, so that expressions which
+    /// only differ in the order of a sum or product are equal by `eq_node()`.
+    /// 
+    /// Chains of `+`, `*`, `&`, `|` and `^^` are flattened and their
+    /// operands sorted, then rebuilt as a left-associative chain. Nothing
+    /// else is rewritten, so `x - y` and `-y + x` are still different.
+    /// Reordering a floating point sum can change its rounding, so the
+    /// canonical form may evaluate very slightly differently.
+    /// 
+    /// ```
+    /// use simple_expression_parser::Parser;
+    /// 
+    /// let canonical = |source: &str| Parser::new(source).parse().unwrap().canonicalize();
+    /// assert!(canonical("1 + 2 + 3").eq_node(canonical("3 + 2 + 1").as_ref()));
+    /// assert!(canonical("x + (y + 1)").eq_node(canonical("(1 + x) + y").as_ref()));
+    /// assert!(canonical("2 * x * y").eq_node(canonical("y * (x * 2)").as_ref()));
+    /// assert!(canonical("a * (b + c)").eq_node(canonical("(c + b) * a").as_ref()));
+    /// assert!(!canonical("1 - 2").eq_node(canonical("2 - 1").as_ref()));
+    /// assert!(!canonical("1 + 2 * 3").eq_node(canonical("(1 + 2) * 3").as_ref()));
+    /// ```
+    fn canonicalize(&self) -> Box<dyn Node> {
+        // Nodes without children are already canonical.
+        self.clone_box()
+    }
+
+    /// Creates a deep copy of the node and all of its children.
+    /// 
+    /// This is what allows `Box<dyn Node>` to implement `Clone`.
+    fn clone_box(&self) -> Box<dyn Node>;
+
+    /// Compares the structure and values of two trees, recursively.
+    /// 
+    /// Spans aren't compared, so `1+2` is equal to `1 + 2`, but not to `2+1`.
+    fn eq_node(&self, other: &dyn Node) -> bool;
+
+    /// Walks the node and all of its children with `visitor`.
+    /// 
+    /// The node's own `visit_*` method is called before any of
+    /// its children are visited, from left to right.
+    fn accept(&self, visitor: &mut dyn Visitor);
+
+    /// The node's direct children, from left to right,
+    /// in the same order as they're visited by `accept()`.
+    fn children(&self) -> Vec<&dyn Node> {
+        Vec::new()
+    }
+
+    /// Moves the node's children into `children`, leaving it without any,
+    /// so that a deep tree can be dropped one node at a time rather than
+    /// recursively. A node which doesn't, such as one without children,
+    /// drops its children as usual.
+    fn detach_children(&mut self, _children: &mut Vec<Box<dyn Node>>) {}
+
+    /// The chars of the source which the node was parsed from, as the
+    /// index of its first char and the index just past its last char.
+    /// 
+    /// e.g. in `1 + 2`, the `1` spans `(0, 1)` and the whole `BinOp`
+    /// spans `(0, 5)`. Brackets around a node aren't part of its span,
+    /// but those within it are, so all of `(1 + 2) * 3` is the product.
+    fn span(&self) -> (usize, usize);
+
+    /// Allows a `&dyn Node` to be downcast into its concrete type.
+    fn as_any(&self) -> &dyn Any;
+
+    /// How tightly the node binds when written with `to_infix()`.
+    /// Higher values bind more tightly.
+    fn precedence(&self) -> u8 {
+        ATOM_PRECEDENCE
+    }
+}
+
+/// Walks a syntax tree without needing to downcast each node.
+/// 
+/// Every method does nothing by default, so a visitor only needs to
+/// implement the methods for the nodes it's interested in. Pass the
+/// visitor to `Node::accept()` on the root of the tree to visit every node.
+/// 
+/// For example, counting the operations in an expression:
+/// 
+/// ```
+/// use simple_expression_parser::{Parser, Visitor, BinOp, UnaryOp, PostfixOp};
+/// 
+/// struct OpCounter(usize);
+/// 
+/// impl Visitor for OpCounter {
+///     fn visit_binop(&mut self, _node: &BinOp) { self.0 += 1; }
+///     fn visit_unaryop(&mut self, _node: &UnaryOp) { self.0 += 1; }
+///     fn visit_postfixop(&mut self, _node: &PostfixOp) { self.0 += 1; }
+/// }
+/// 
+/// let tree = Parser::new("-1 + 2 * 3!".to_string()).parse().unwrap();
+/// let mut counter = OpCounter(0);
+/// tree.accept(&mut counter);
+/// assert_eq!(counter.0, 4);
+/// ```
+pub trait Visitor {
+    fn visit_binop(&mut self, _node: &BinOp) {}
+    fn visit_unaryop(&mut self, _node: &UnaryOp) {}
+    fn visit_postfixop(&mut self, _node: &PostfixOp) {}
+    fn visit_int(&mut self, _node: &IntLiteral) {}
+    fn visit_float(&mut self, _node: &FloatLiteral) {}
+    fn visit_constant(&mut self, _node: &Constant) {}
+    fn visit_variable(&mut self, _node: &Variable) {}
+    fn visit_function_call(&mut self, _node: &FunctionCall) {}
+    fn visit_conditional(&mut self, _node: &Conditional) {}
+    fn visit_assignment(&mut self, _node: &Assignment) {}
+    fn visit_range(&mut self, _node: &Range) {}
+    fn visit_comparison_chain(&mut self, _node: &ComparisonChain) {}
+}
+
+/// Collects the name of every variable it visits,
+/// see `Node::free_variables()`.
+struct VariableCollector(BTreeSet<String>);
+
+impl Visitor for VariableCollector {
+    fn visit_variable(&mut self, node: &Variable) {
+        self.0.insert(node.name.clone());
+    }
+}
+
+/// Collects the value and span of every literal it visits,
+/// see `Node::literals()`.
+struct LiteralCollector(Vec<(Number, (usize, usize))>);
+
+impl Visitor for LiteralCollector {
+    fn visit_int(&mut self, node: &IntLiteral) {
+        self.0.push((node.value as Number, node.span()));
+    }
+
+    fn visit_float(&mut self, node: &FloatLiteral) {
+        self.0.push((node.value, node.span()));
+    }
+}
+
+impl Clone for Box<dyn Node> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// A node being folded by `fold()`, along with
+/// the results of folding each of its children.
+pub struct FoldItem<'a, T> {
+    pub node: &'a dyn Node,
+    /// The results for the node's children, in
+    /// the order given by `Node::children()`.
+    pub children: Vec<T>
+}
+
+/// One step of an expression in postfix notation, see `to_postfix()`.
+/// 
+/// Each operation pops its operands off of a stack, the last pushed
+/// being its rightmost operand, and pushes its result back on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostfixItem {
+    /// A literal or the value of a constant.
+    Number(Number),
+    Variable(String),
+    UnaryOp(UnaryKind),
+    /// A binary operation, such as `+`.
+    BinaryOp(Op),
+    /// A postfix operation, such as `!`.
+    PostfixOp(Op),
+    /// A call to the function `name`, popping `arguments` operands.
+    Call {
+        name: String,
+        arguments: usize
+    },
+    /// Pops a condition and the values of both branches,
+    /// pushing the value chosen by the condition.
+    Conditional,
+    /// Binds the variable to the value on top of the stack,
+    /// which is left where it is.
+    Assign(String),
+    /// Pops the bounds of a range, pushing the range itself
+    /// for `in` to pop. Nothing else can pop a range.
+    Range,
+    /// A chain of comparisons, such as `1 < x < 10`, popping one more
+    /// operand than there are comparisons and pushing 1 if every
+    /// comparison holds, or otherwise 0.
+    Comparisons(Vec<Op>)
+}
+
+/// Numbers and variables are written as they are, operations as their
+/// symbol, apart from signs which are written as `neg` and `pos`, and
+/// calls as their name and number of arguments, such as `max/3`.
+impl fmt::Display for PostfixItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostfixItem::Number(value) => write!(f, "{}", value),
+            PostfixItem::Variable(name) => write!(f, "{}", name),
+            PostfixItem::UnaryOp(UnaryKind::Neg) => write!(f, "neg"),
+            PostfixItem::UnaryOp(UnaryKind::Pos) => write!(f, "pos"),
+            PostfixItem::UnaryOp(op) => write!(f, "{}", op.symbol()),
+            PostfixItem::BinaryOp(op) | PostfixItem::PostfixOp(op) => write!(f, "{}", op.symbol()),
+            PostfixItem::Call { name, arguments } => write!(f, "{}/{}", name, arguments),
+            PostfixItem::Conditional => write!(f, "?:"),
+            PostfixItem::Assign(name) => write!(f, "{} =", name),
+            PostfixItem::Range => write!(f, ".."),
+            PostfixItem::Comparisons(ops) => {
+                let ops: Vec<&str> = ops.iter().map(Op::symbol).collect();
+                write!(f, "{}", ops.join(","))
+            }
+        }
+    }
+}
+
+impl<'n> dyn Node + 'n {
+    /// Reduces the tree to a single value, bottom up.
+    /// 
+    /// `f` is called once for every node, after it has been called for
+    /// all of the node's children, and is given their results. Downcast
+    /// `FoldItem::node` to see what kind of node it is.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Parser, BinOp};
+    /// 
+    /// let tree = Parser::new("1 + 2 * sqrt(x)").parse().unwrap();
+    /// let depth = tree.fold(|item| 1 + item.children.into_iter().max().unwrap_or(0));
+    /// assert_eq!(depth, 4);
+    /// let count = tree.fold(|item| 1 + item.children.into_iter().sum::<usize>());
+    /// assert_eq!(count, 6);
+    /// 
+    /// // Rebuilds the source in prefix notation
+    /// let prefix = tree.fold(|item| match item.node.as_any().downcast_ref::<BinOp>() {
+    ///     Some(binop) => format!("({:?} {})", binop.op, item.children.join(" ")),
+    ///     None if item.children.is_empty() => item.node.to_infix(),
+    ///     None => format!("[{}]", item.children.join(" "))
+    /// });
+    /// assert_eq!(prefix, "(Add 1 (Mult 2 [x]))");
+    /// ```
+    pub fn fold<T>(&self, mut f: impl FnMut(FoldItem<'_, T>) -> T) -> T {
+        self.fold_with(&mut f)
+    }
+
+    /// Converts the tree into postfix notation, also known as reverse
+    /// Polish notation, by visiting each node after its children, so
+    /// `1 + 2 * 3` becomes `1 2 3 * +`.
+    /// 
+    /// This is suited to evaluating on a stack machine. Such a machine
+    /// evaluates every operand, so unlike `evaluate()`, both branches of
+    /// a conditional and both sides of `and` and `or` are evaluated.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Parser, PostfixItem, Op, UnaryKind};
+    /// 
+    /// let postfix = |source: &str| Parser::new(source).parse().unwrap().to_postfix();
+    /// let text: Vec<String> = postfix("1 + 2 * 3").iter().map(|item| item.to_string()).collect();
+    /// assert_eq!(text, ["1", "2", "3", "*", "+"]);
+    /// 
+    /// // A small stack machine for some of the operations
+    /// let run = |items: Vec<PostfixItem>| {
+    ///     let mut stack: Vec<f64> = Vec::new();
+    ///     for item in items {
+    ///         let value = match item {
+    ///             PostfixItem::Number(value) => value,
+    ///             PostfixItem::UnaryOp(UnaryKind::Neg) => -stack.pop().unwrap(),
+    ///             PostfixItem::Call { name, .. } if name == "sqrt" => stack.pop().unwrap().sqrt(),
+    ///             PostfixItem::BinaryOp(op) => {
+    ///                 let (right, left) = (stack.pop().unwrap(), stack.pop().unwrap());
+    ///                 match op {
+    ///                     Op::Add => left + right,
+    ///                     Op::Sub => left - right,
+    ///                     Op::Mult => left * right,
+    ///                     Op::Div => left / right,
+    ///                     Op::Pow => left.powf(right),
+    ///                     _ => unimplemented!()
+    ///                 }
+    ///             },
+    ///             _ => unimplemented!()
+    ///         };
+    ///         stack.push(value);
+    ///     }
+    ///     stack.pop().unwrap()
+    /// };
+    /// for source in ["1 + 2 * 3", "(1 + 2) * 3", "10 - 4 - 3", "-2^2 + sqrt(16) / 8", "2^3^2"] {
+    ///     let tree = Parser::new(source).parse().unwrap();
+    ///     assert_eq!(Ok(run(tree.to_postfix())), tree.evaluate());
+    /// }
+    /// ```
+    pub fn to_postfix(&self) -> Vec<PostfixItem> {
+        self.fold(|FoldItem { node, children }: FoldItem<'_, Vec<PostfixItem>>| {
+            let mut items: Vec<PostfixItem> = children.into_iter().flatten().collect();
+            let any = node.as_any();
+            let item = if let Some(binop) = any.downcast_ref::<BinOp>() {
+                PostfixItem::BinaryOp(binop.op)
+            } else if let Some(unaryop) = any.downcast_ref::<UnaryOp>() {
+                PostfixItem::UnaryOp(unaryop.op)
+            } else if let Some(postfixop) = any.downcast_ref::<PostfixOp>() {
+                PostfixItem::PostfixOp(postfixop.op)
+            } else if let Some(int) = any.downcast_ref::<IntLiteral>() {
+                PostfixItem::Number(int.value as Number)
+            } else if let Some(float) = any.downcast_ref::<FloatLiteral>() {
+                PostfixItem::Number(float.value)
+            } else if let Some(constant) = any.downcast_ref::<Constant>() {
+                PostfixItem::Number(constant.value)
+            } else if let Some(variable) = any.downcast_ref::<Variable>() {
+                PostfixItem::Variable(variable.name.clone())
+            } else if let Some(call) = any.downcast_ref::<FunctionCall>() {
+                PostfixItem::Call { name: call.name.clone(), arguments: call.arguments.len() }
+            } else if let Some(assignment) = any.downcast_ref::<Assignment>() {
+                PostfixItem::Assign(assignment.name.clone())
+            } else if any.is::<Range>() {
+                PostfixItem::Range
+            } else if let Some(chain) = any.downcast_ref::<ComparisonChain>() {
+                PostfixItem::Comparisons(chain.ops.clone())
+            } else {
+                PostfixItem::Conditional
+            };
+            items.push(item);
+            items
+        })
+    }
+
+    /// Writes the tree as an S-expression, with each operation or call as a
+    /// list of its operator or function name followed by its operands, such
+    /// as `(+ 1 (* 2 3))`. It can be read back in with `from_sexpr()`.
+    /// 
+    /// Numbers, constants and variables are written as they are in infix,
+    /// except that a constant defined with `Parser::define_constant()` is
+    /// written as just its value, without the comment naming it. A
+    /// conditional is written `(? condition if_true if_false)`, an
+    /// assignment `(= name value)` and a range `(.. low high)`. Operators
+    /// written with the same symbol, such as `-` for both subtraction and
+    /// negation, are told apart by their number of operands.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Parser, from_sexpr};
+    /// 
+    /// let sexpr = |source: &str| Parser::new(source).parse().unwrap().to_sexpr();
+    /// assert_eq!(sexpr("1 + 2 * 3"), "(+ 1 (* 2 3))");
+    /// assert_eq!(sexpr("-x^2 + 5!"), "(+ (- (^ x 2)) (! 5))");
+    /// assert_eq!(sexpr("x > 0 ? max(x, 2.0) : rand()"), "(? (> x 0) (max x 2.0) (rand))");
+    /// assert_eq!(sexpr("y = x in 1..10"), "(= y (in x (.. 1 10)))");
+    /// 
+    /// // Reading the S-expression back in builds the same tree
+    /// for source in [
+    ///     "1 + 2 * 3", "(1 + 2) * 3", "2^3^2 - -4", "-x^2 + 3! % 7",
+    ///     "max(1, 2, 3) / rand() + atan2(y, x)", "x > 0 ? x : -x",
+    ///     "y = 2 * pi * r", "5 in 1..10 and not done or x != 1e-3",
+    ///     "0xFF & 3 | 1 ^^ 2 << 4 >> 1", "+2.5e10 <= tau == (e >= 1)"
+    /// ] {
+    ///     let tree = Parser::new(source).parse().unwrap();
+    ///     let read = from_sexpr(&tree.to_sexpr()).unwrap();
+    ///     assert!(read.eq_node(tree.as_ref()), "{}", source);
+    /// }
+    /// ```
+    pub fn to_sexpr(&self) -> String {
+        self.fold(|FoldItem { node, children }: FoldItem<'_, String>| {
+            if let Some(chain) = node.as_any().downcast_ref::<ComparisonChain>() {
+                // Each comparison after the first is only written where it changes.
+                let mut items = vec![chain.ops[0].symbol().to_string()];
+                for (i, operand) in children.into_iter().enumerate() {
+                    if i >= 2 && chain.ops[i - 1] != chain.ops[i - 2] {
+                        items.push(chain.ops[i - 1].symbol().to_string());
+                    }
+                    items.push(operand);
+                }
+                return format!("({})", items.join(" "));
+            }
+            let head = match operation(node) {
+                Some(head) => head,
+                None => return match node.as_any().downcast_ref::<Constant>().filter(|constant| !constant.is_built_in()) {
+                    // Written as its value, as in infix, though without a comment.
+                    Some(constant) if constant.value == Number::NEG_INFINITY => "(- inf)".to_string(),
+                    Some(constant) => number_to_infix(constant.value),
+                    None => node.to_infix()
+                }
+            };
+            let items: Vec<String> = core::iter::once(head).chain(children).collect();
+            format!("({})", items.join(" "))
+        })
+    }
+
+    /// Narrates how the tree is evaluated, as a list of steps such as
+    /// `multiply 2 by 3 to get 6`, in the order they're carried out.
+    /// 
+    /// Each operation and function call is a step, given the values of
+    /// its operands, while numbers and constants need no step of their
+    /// own. The steps are recorded as the tree is evaluated, so they always
+    /// match what `evaluate()` does: only the branch of a conditional which
+    /// is taken is narrated, and a division follows the environment's
+    /// `DivisionMode`. If evaluation fails, the last step is the error.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Parser, Environment, DivisionMode};
+    /// 
+    /// let explain = |source: &str| Parser::new(source).parse().unwrap().explain();
+    /// assert_eq!(explain("1 + 2 * 3"), ["multiply 2 by 3 to get 6", "add 1 to 6 to get 7"]);
+    /// assert_eq!(explain("(10 - 4) / 2^2"), [
+    ///     "subtract 4 from 10 to get 6",
+    ///     "raise 2 to the power of 2 to get 4",
+    ///     "divide 6 by 4 to get 1.5"
+    /// ]);
+    /// assert_eq!(explain("max(1, -3!) > 0 ? 1 : 1/0"), [
+    ///     "take the factorial of 3 to get 6",
+    ///     "negate 6 to get -6",
+    ///     "apply max to 1 and -6 to get 1",
+    ///     "compare 1 > 0 to get 1",
+    ///     "1 is true, so take 1"
+    /// ]);
+    /// assert_eq!(explain("2 * y"), ["fail with the error: Unbound variable 'y'"]);
+    /// assert_eq!(explain("0 and 1/0"), ["0 is false, so skip 1 / 0 to get 0"]);
+    /// 
+    /// let mut env = Environment::new();
+    /// env.insert("x", 4.0);
+    /// let tree = Parser::new("sqrt(x) + 1").parse().unwrap();
+    /// assert_eq!(tree.explain_with(&env), [
+    ///     "look up x to get 4",
+    ///     "apply sqrt to 4 to get 2",
+    ///     "add 2 to 1 to get 3"
+    /// ]);
+    /// 
+    /// let mut env = Environment::new();
+    /// env.set_division_mode(DivisionMode::Truncating);
+    /// let explain = |source: &str| Parser::new(source).parse().unwrap().explain_with(&env);
+    /// assert_eq!(explain("7.0 / 2"), ["divide 7 by 2 to get 3.5"]);
+    /// assert_eq!(explain("(1 + 2) * 7 / 2"), [
+    ///     "add 1 to 2 to get 3",
+    ///     "multiply 3 by 7 to get 21",
+    ///     "divide 21 by 2 to get 10"
+    /// ]);
+    /// ```
+    pub fn explain(&self) -> Vec<String> {
+        self.explain_with(&Environment::new())
+    }
+
+    /// Narrates how the tree is evaluated with the variables
+    /// and functions of `env`, see `explain()`.
+    pub fn explain_with(&self, env: &Environment) -> Vec<String> {
+        let (result, mut steps) = env.collect_narration(|| self.evaluate_with(env));
+        if let Err(err) = result {
+            steps.push(format!("fail with the error: {}", err));
+        }
+        steps
+    }
+
+    /// Evaluates the tree without any variables bound, like `evaluate()`,
+    /// but is guaranteed never to panic for any tree the parser produces.
+    /// 
+    /// The parser limits how deeply brackets and the like are nested, but
+    /// a long chain of operators such as `1 + 1 + ... + 1` is as deep as
+    /// it is long. This, `evaluate()` and `evaluate_exact()` keep the
+    /// nodes being evaluated on a stack of their own with
+    /// `Node::evaluate_step()`, whatever the `DivisionMode`, and trees
+    /// are dropped without recursing, so the depth of the tree is only
+    /// limited by memory. Every other failure is an `EvalError`.
+    /// 
+    /// Nodes other than those defined by this crate are evaluated with
+    /// their own `evaluate_step()`, so aren't covered by the guarantee.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Parser, Environment, DivisionMode, Value};
+    /// 
+    /// // Random expressions built from every kind of node, including
+    /// // edge cases such as `nan`, huge numbers and unbound variables.
+    /// let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+    /// let mut random = move |n: usize| {
+    ///     state ^= state << 13;
+    ///     state ^= state >> 7;
+    ///     state ^= state << 17;
+    ///     state as usize % n
+    /// };
+    /// fn expression(random: &mut dyn FnMut(usize) -> usize, depth: usize) -> String {
+    ///     const ATOMS: [&str; 12] = ["0", "1", "-0.5", "7", "2.5", "1e308", "0xFF", "inf", "nan", "pi", "x", "rand()"];
+    ///     const OPS: [&str; 20] = [
+    ///         "+", "-", "*", "/", "%", "^", "==", "!=", "<", "<=",
+    ///         ">", ">=", "&", "|", "^^", "<<", ">>", "and", "or", "in"
+    ///     ];
+    ///     const FUNCTIONS: [&str; 8] = ["sqrt", "ln", "abs", "floor", "sin", "exp", "round", "atan"];
+    ///     if depth == 0 {
+    ///         return ATOMS[random(ATOMS.len())].to_string();
+    ///     }
+    ///     let depth = depth - 1;
+    ///     let mut operand = |random: &mut dyn FnMut(usize) -> usize| format!("({})", expression(random, depth));
+    ///     match random(9) {
+    ///         0 => ATOMS[random(ATOMS.len())].to_string(),
+    ///         1 => format!("-{}", operand(random)),
+    ///         2 => format!("not {}", operand(random)),
+    ///         3 => format!("{}!", operand(random)),
+    ///         4 => format!("{}({})", FUNCTIONS[random(FUNCTIONS.len())], operand(random)),
+    ///         5 => format!("max({}, {}, {})", operand(random), operand(random), operand(random)),
+    ///         6 => format!("{} ? {} : {}", operand(random), operand(random), operand(random)),
+    ///         7 => format!("|{}|", operand(random)),
+    ///         _ => match OPS[random(OPS.len())] {
+    ///             "in" => format!("{} in {}..{}", operand(random), operand(random), operand(random)),
+    ///             op => format!("{} {} {}", operand(random), op, operand(random))
+    ///         }
+    ///     }
+    /// }
+    /// for _ in 0..2000 {
+    ///     let depth = random(7);
+    ///     let source = expression(&mut random, depth);
+    ///     let tree = Parser::new(source.as_str()).parse().unwrap();
+    ///     let result = tree.try_evaluate();
+    ///     // Both agree except on random numbers. They're compared as
+    ///     // text, as NaN isn't equal to itself.
+    ///     if !source.contains("rand") {
+    ///         assert_eq!(format!("{:?}", result), format!("{:?}", tree.evaluate()), "{}", source);
+    ///     }
+    /// }
+    /// 
+    /// // A tree 200,000 levels deep, on the default stack
+    /// let source = format!("1{}", " + 1".repeat(200_000));
+    /// let deep = Parser::new(source).parse().unwrap();
+    /// assert_eq!(deep.try_evaluate(), Ok(200_001.0));
+    /// assert_eq!(deep.evaluate(), Ok(200_001.0));
+    /// 
+    /// // Exact values and truncating divisions are evaluated on the same stack
+    /// let mut env = Environment::new();
+    /// env.set_division_mode(DivisionMode::Truncating);
+    /// let source = format!("7{}", " / 1".repeat(200_000));
+    /// let deep = Parser::new(source).parse().unwrap();
+    /// assert_eq!(deep.evaluate_with(&env), Ok(7.0));
+    /// assert_eq!(deep.evaluate_exact(), Ok(Value::Int(7)));
+    /// let halves = Parser::new(format!("1000{}", " / 2".repeat(200_000))).parse().unwrap();
+    /// assert_eq!(halves.evaluate_with(&env), Ok(0.0));
+    /// assert_eq!(halves.try_evaluate(), Ok(0.0));
+    /// ```
+    pub fn try_evaluate(&self) -> Result<Number, EvalError> {
+        Environment::new().evaluate(self)
+    }
+
+    fn fold_with<T>(&self, f: &mut dyn FnMut(FoldItem<'_, T>) -> T) -> T {
+        let children = self.children().into_iter()
+            .map(|child| child.fold_with(f))
+            .collect();
+        f(FoldItem { node: self, children })
+    }
+}
+
+impl PartialEq for dyn Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.eq_node(other)
+    }
+}
+
+/// The operator or function name at the head of the list `node` is
+/// written as by `to_sexpr()`, or `None` if it's written as it is in infix,
+/// like a number. Operators written with the same symbol, such as `-`
+/// for both subtraction and negation, are told apart by their operands.
+/// A chain of comparisons is given as every comparison in it.
+fn operation(node: &dyn Node) -> Option<String> {
+    let any = node.as_any();
+    let head = if let Some(binop) = any.downcast_ref::<BinOp>() {
+        binop.op.symbol().to_string()
+    } else if let Some(unaryop) = any.downcast_ref::<UnaryOp>() {
+        unaryop.op.symbol().to_string()
+    } else if let Some(postfixop) = any.downcast_ref::<PostfixOp>() {
+        postfixop.op.symbol().to_string()
+    } else if let Some(call) = any.downcast_ref::<FunctionCall>() {
+        call.name.clone()
+    } else if let Some(assignment) = any.downcast_ref::<Assignment>() {
+        format!("= {}", assignment.name)
+    } else if any.is::<Range>() {
+        "..".to_string()
+    } else if any.is::<Conditional>() {
+        "?".to_string()
+    } else if let Some(chain) = any.downcast_ref::<ComparisonChain>() {
+        let ops: Vec<&str> = chain.ops.iter().map(Op::symbol).collect();
+        ops.join(" ")
+    } else {
+        return None;
+    };
+    Some(head)
+}
+
+/// Stands in for a child which has been moved out of its parent, such as
+/// by `detach_children()`. It's never part of a tree which can be reached,
+/// so has no meaningful value.
+#[derive(Clone)]
+pub(crate) struct Placeholder;
+
+impl Node for Placeholder {
+    fn evaluate_with(&self, _env: &Environment) -> Result<Number, EvalError> {
+        Ok(Number::NAN)
+    }
+
+    fn display_with(&self, _depth: usize, _opts: &DisplayOptions) -> String {
+        "Placeholder".to_string()
+    }
+
+    fn to_infix(&self) -> String {
+        "nan".to_string()
+    }
+
+    fn to_json(&self) -> String {
+        "{\"type\":\"Placeholder\"}".to_string()
+    }
+
+    fn simplify(self: Box<Self>) -> Box<dyn Node> {
+        self
+    }
+
+    fn differentiate(&self, _var: &str) -> Result<Box<dyn Node>, EvalError> {
+        Ok(Box::new(Placeholder))
+    }
+
+    fn clone_box(&self) -> Box<dyn Node> {
+        Box::new(Placeholder)
+    }
+
+    fn eq_node(&self, other: &dyn Node) -> bool {
+        other.as_any().is::<Placeholder>()
+    }
+
+    fn accept(&self, _visitor: &mut dyn Visitor) {}
+
+    fn span(&self) -> (usize, usize) {
+        (0, 0)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Simplifies the operand of the percentage `node`, such as the `1 + 2`
+/// in `(1 + 2)%`, without folding the percentage itself into a fraction,
+/// so that `x + 10%` is still written with a percentage, see `Op::AddPercent`.
+fn simplify_percentage(mut node: Box<dyn Node>) -> Box<dyn Node> {
+    match node.as_any().downcast_ref::<PostfixOp>() {
+        Some(postfix) if postfix.op == Op::Percent => {},
+        _ => return node.simplify()
+    }
+    let (start, end) = node.span();
+    let mut operand = Vec::new();
+    node.detach_children(&mut operand);
+    let left = operand.pop().expect("a percentage has an operand").simplify();
+    Box::new(PostfixOp { left, op: Op::Percent, start, end })
+}
+
+/// Takes the child out of `node`, leaving a placeholder in its place.
+fn take(node: &mut Box<dyn Node>) -> Box<dyn Node> {
+    mem::replace(node, Box::new(Placeholder))
+}
+
+/// Moves the child out of `node` into `children`, see `Node::detach_children()`.
+fn detach(node: &mut Box<dyn Node>, children: &mut Vec<Box<dyn Node>>) {
+    if !node.as_any().is::<Placeholder>() {
+        children.push(take(node));
+    }
+}
+
+/// Drops the descendants of `node` one at a time, by detaching the children
+/// of each before it's dropped, so that the depth of the tree doesn't matter.
+fn drop_children(node: &mut dyn Node) {
+    let mut children = Vec::new();
+    node.detach_children(&mut children);
+    while let Some(mut child) = children.pop() {
+        child.detach_children(&mut children);
+    }
+}
+
+/// The value of `node` if it's a constant, such as `3`, `pi` or `-2.5`.
+fn constant_value(node: &dyn Node) -> Option<Number> {
+    let any = node.as_any();
+    if any.is::<IntLiteral>() || any.is::<FloatLiteral>() || any.is::<Constant>() {
+        return node.evaluate().ok();
+    }
+    // Negative numbers are represented by negating a literal.
+    match any.downcast_ref::<UnaryOp>() {
+        Some(unary) if unary.op == UnaryKind::Neg => constant_value(unary.right.as_ref()).map(|value| -value),
+        _ => None
+    }
+}
+
+/// Replaces `node` with a literal holding its value, if it's
+/// made up of only constants and evaluates successfully.
+/// 
+/// The literal spans the same source as `node` did.
+fn fold(node: Box<dyn Node>) -> Box<dyn Node> {
+    let (start, end) = node.span();
+    match node.evaluate() {
+        Ok(value) if value.is_finite() => {
+            // Whole numbers which fit in an i64 become IntLiterals.
+            let literal: Box<dyn Node> = if math::fract(value) == 0.0 && value.abs() < -(i64::MIN as Number) {
+                Box::new(IntLiteral { value: value.abs() as i64, start, end })
+            } else {
+                Box::new(FloatLiteral { value: value.abs(), start, end })
+            };
+            if value.is_sign_negative() && value != 0.0 {
+                Box::new(UnaryOp { right: literal, op: UnaryKind::Neg, start, end })
+            } else {
+                literal
+            }
+        },
+        _ => node
+    }
+}
+
+// The helpers below build derivatives, leaving out terms known to be
+// zero and factors known to be one so that the result stays readable.
+
+fn is_zero(node: &dyn Node) -> bool {
+    constant_value(node) == Some(0.0)
+}
+
+fn is_one(node: &dyn Node) -> bool {
+    constant_value(node) == Some(1.0)
+}
+
+fn int(value: i64, (start, end): (usize, usize)) -> Box<dyn Node> {
+    Box::new(IntLiteral { value, start, end })
+}
+
+fn call(name: &str, argument: Box<dyn Node>, (start, end): (usize, usize)) -> Box<dyn Node> {
+    Box::new(FunctionCall { name: name.to_string(), arguments: vec![argument], start, end })
+}
+
+fn binop(left: Box<dyn Node>, op: Op, right: Box<dyn Node>) -> Box<dyn Node> {
+    Box::new(BinOp::new(left, right, op, Position::default()))
+}
+
+fn sum(left: Box<dyn Node>, right: Box<dyn Node>) -> Box<dyn Node> {
+    if is_zero(left.as_ref()) {
+        right
+    } else if is_zero(right.as_ref()) {
+        left
+    } else {
+        binop(left, Op::Add, right)
+    }
+}
+
+fn difference(left: Box<dyn Node>, right: Box<dyn Node>) -> Box<dyn Node> {
+    if is_zero(right.as_ref()) {
+        left
+    } else if is_zero(left.as_ref()) {
+        negate(right)
+    } else {
+        binop(left, Op::Sub, right)
+    }
+}
+
+fn product(left: Box<dyn Node>, right: Box<dyn Node>) -> Box<dyn Node> {
+    if is_zero(left.as_ref()) || is_one(right.as_ref()) {
+        left
+    } else if is_zero(right.as_ref()) || is_one(left.as_ref()) {
+        right
+    } else {
+        binop(left, Op::Mult, right)
+    }
+}
+
+fn quotient(left: Box<dyn Node>, right: Box<dyn Node>) -> Box<dyn Node> {
+    if is_zero(left.as_ref()) || is_one(right.as_ref()) {
+        left
+    } else {
+        binop(left, Op::Div, right)
+    }
+}
+
+fn power(left: Box<dyn Node>, right: Box<dyn Node>) -> Box<dyn Node> {
+    if is_one(right.as_ref()) {
+        left
+    } else {
+        binop(left, Op::Pow, right)
+    }
+}
+
+fn negate(node: Box<dyn Node>) -> Box<dyn Node> {
+    if is_zero(node.as_ref()) {
+        return node;
+    }
+    match node.as_any().downcast_ref::<UnaryOp>() {
+        Some(inner) if inner.op == UnaryKind::Neg => inner.right.clone(),
+        _ => {
+            let (start, end) = node.span();
+            Box::new(UnaryOp { right: node, op: UnaryKind::Neg, start, end })
+        }
+    }
+}
+
+/// The error for a node depending on `var` which has no derivative.
+fn not_differentiable(node: &dyn Node, var: &str) -> EvalError {
+    EvalError::NotDifferentiable { expression: node.to_infix(), var: var.to_string() }
+}
+
+/// Writes `node` in infix form, wrapped in brackets if it binds
+/// more loosely than `min_precedence`.
+fn infix_operand(node: &dyn Node, min_precedence: u8) -> String {
+    if node.precedence() < min_precedence {
+        format!("({})", node.to_infix())
+    } else {
+        node.to_infix()
+    }
+}
+
+/// The lowest precedence each child of `node` can have to be written
+/// without brackets around it, as by `to_infix()`, when parsed in percent
+/// mode or not, in the order given by `children()`, or `None` for a node
+/// not defined by this crate.
+pub(crate) fn operand_precedences(node: &dyn Node, percent_mode: bool) -> Option<Vec<u8>> {
+    let any = node.as_any();
+    let precedences = if let Some(binop) = any.downcast_ref::<BinOp>() {
+        let (left, right) = binop.operand_precedences(percent_mode);
+        vec![left, right]
+    } else if let Some(chain) = any.downcast_ref::<ComparisonChain>() {
+        vec![chain.precedence() + 1; chain.operands.len()]
+    } else if let Some(unaryop) = any.downcast_ref::<UnaryOp>() {
+        vec![unaryop.op.precedence()]
+    } else if any.is::<PostfixOp>() {
+        vec![POSTFIX_PRECEDENCE]
+    } else if let Some(call) = any.downcast_ref::<FunctionCall>() {
+        vec![0; call.arguments.len()]
+    } else if any.is::<Conditional>() {
+        vec![CONDITIONAL_PRECEDENCE + 1, 0, 0]
+    } else if any.is::<Range>() {
+        vec![Op::In.precedence() + 1; 2]
+    } else if any.is::<Assignment>() {
+        vec![0]
+    } else if node.children().is_empty() {
+        Vec::new()
+    } else {
+        return None;
+    };
+    Some(precedences)
+}
+
+/// Writes `value` as a JSON string literal, escaping it where needed.
+fn json_string(value: &str) -> String {
+    let mut json = String::from('"');
+    for char in value.chars() {
+        match char {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c)
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Writes `value` as a number which is read back as the same float,
+/// including the special values, which are written as constants.
+fn number_to_infix(value: Number) -> String {
+    if value.is_nan() {
+        String::from("nan")
+    } else if value.is_infinite() {
+        String::from(if value > 0.0 { "inf" } else { "-inf" })
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+/// Writes `value` as a JSON number. JSON can't represent
+/// infinities or NaN, so they're written as `null`.
+fn json_number(value: Number) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        String::from("null")
+    }
+}
+
+/// Represents a binary operation, meaning it's a mathematical
+/// operation with both a left and right side.
+/// 
+/// For example `1 + 1` is a binary operation.
+/// It has a left and right hand side, with an operation in the middle.
+#[derive(Clone)]
+pub struct BinOp {
+    pub left: Box<dyn Node>,
+    pub right: Box<dyn Node>,
+    pub op: Op,
+    /// Where the operator is found in the source, used to
+    /// point at it when the operation fails to evaluate.
+    pub op_pos: Position,
+    pub start: usize,
+    pub end: usize
+}
+
+impl BinOp {
+    /// Creates a binary operation spanning from the start
+    /// of `left` to the end of `right`.
+    pub fn new(left: Box<dyn Node>, right: Box<dyn Node>, op: Op, op_pos: Position) -> Self {
+        let start = left.span().0;
+        let end = right.span().1;
+        Self { left, right, op, op_pos, start, end }
+    }
+}
+
+impl BinOp {
+    /// The lowest precedence each operand can have to be written without
+    /// brackets around it, in percent mode or not, see `operand_precedences()`.
+    fn operand_precedences(&self, percent_mode: bool) -> (u8, u8) {
+        let precedence = self.op.precedence();
+        match self.op {
+            // The base of a power is an entity, and the exponent is a unary.
+            Op::Pow => (precedence + 1, UNARY_PRECEDENCE),
+            // A negative right hand side of '%' is bracketed so that
+            // it isn't read as a percentage in percent mode.
+            Op::Mod if percent_mode => (precedence, UNARY_PRECEDENCE + 1),
+            // Comparisons chain, so a comparison on either side is bracketed
+            // to stop `(1 < 2) < 3` being read back as `1 < 2 < 3`.
+            op if op.is_comparison() => (precedence + 1, precedence + 1),
+            // Otherwise an operand of equal precedence only needs brackets
+            // on the side which the operator isn't grouped towards.
+            _ => match self.op.associativity() {
+                Associativity::Right => (precedence + 1, precedence),
+                _ => (precedence, precedence + 1)
+            }
+        }
+    }
+
+    /// Applies the operator to the values of both operands.
+    fn apply(&self, left: Number, right: Number) -> Result<Number, EvalError> {
+        self.check_operands(left, right)?;
+        // Simple map to rust native operations
+        match self.op {
+            Op::Add => Ok(left + right),
+            Op::Sub => Ok(left - right),
+            // The right hand side is already a fraction, so `10%` is `0.1`.
+            Op::AddPercent => Ok(left + left * right),
+            Op::SubPercent => Ok(left - left * right),
+            Op::Div | Op::Mod if right == 0.0 => Err(EvalError::DivByZero { pos: self.op_pos }),
+            Op::Div => Ok(left / right),
+            Op::Mult => Ok(left * right),
+            // Rust's '%' is a truncated remainder, so the result takes
+            // the sign of the left hand side, e.g. `-7 % 3 == -1`.
+            Op::Mod => Ok(left % right),
+            Op::Pow => math::pow(left, right)
+                .ok_or_else(|| EvalError::RequiresStd { operation: "^".to_string() }),
+            Op::Factorial | Op::Percent => unreachable!("{:?} isn't a binary operation", self.op),
+            Op::And | Op::Or => unreachable!("{:?} is evaluated by BinOp::logical()", self.op),
+            Op::In => unreachable!("in is evaluated by Range::contains()"),
+            // Comparisons produce 1 when true and 0 when false.
+            Op::Equal => Ok(truth(left == right)),
+            Op::NotEqual => Ok(truth(left != right)),
+            Op::Less => Ok(truth(left < right)),
+            Op::LessEqual => Ok(truth(left <= right)),
+            Op::Greater => Ok(truth(left > right)),
+            Op::GreaterEqual => Ok(truth(left >= right)),
+            // Bitwise operations are applied to the operands as integers.
+            Op::BitAnd => Ok(((left as i64) & (right as i64)) as Number),
+            Op::BitOr => Ok(((left as i64) | (right as i64)) as Number),
+            Op::BitXor => Ok(((left as i64) ^ (right as i64)) as Number),
+            Op::ShiftLeft => Ok(((left as i64) << shift_amount(right)?) as Number),
+            Op::ShiftRight => Ok(((left as i64) >> shift_amount(right)?) as Number)
+        }
+    }
+
+    /// Checks that the operator is defined for the kinds of its operands.
+    /// 
+    /// The bitwise operators are only defined for integers. Every other
+    /// operator takes any number, including the `1` or `0` produced by
+    /// a comparison, so `(1 > 2) * 3` is `0` rather than an error.
+    fn check_operands(&self, left: Number, right: Number) -> Result<(), EvalError> {
+        let (left_kind, right_kind) = (ValueKind::of(left), ValueKind::of(right));
+        let bitwise = matches!(self.op, Op::BitAnd | Op::BitOr | Op::BitXor | Op::ShiftLeft | Op::ShiftRight);
+        if bitwise && (left_kind != ValueKind::Int || right_kind != ValueKind::Int) {
+            return Err(EvalError::TypeMismatch { op: self.op, left_kind, right_kind });
+        }
+        Ok(())
+    }
+
+    /// Applies the operator to two integer operands, producing
+    /// an integer unless the result can't be represented as one.
+    fn apply_integers(&self, left: i64, right: i64) -> Result<Value, EvalError> {
+        let result = match self.op {
+            Op::Add => left.checked_add(right),
+            Op::Sub => left.checked_sub(right),
+            // Percentages are fractions, so they're left to the float path.
+            Op::AddPercent | Op::SubPercent => None,
+            Op::Mult => left.checked_mul(right),
+            Op::Div | Op::Mod if right == 0 => return Err(EvalError::DivByZero { pos: self.op_pos }),
+            Op::Div => left.checked_div(right),
+            Op::Mod => left.checked_rem(right),
+            // Negative powers are fractions, so they're left to the float path.
+            Op::Pow => u32::try_from(right).ok().and_then(|right| left.checked_pow(right)),
+            Op::Factorial | Op::Percent => unreachable!("{:?} isn't a binary operation", self.op),
+            Op::And | Op::Or => unreachable!("{:?} is evaluated by BinOp::logical()", self.op),
+            Op::In => unreachable!("in is evaluated by Range::contains()"),
+            Op::Equal => Some((left == right) as i64),
+            Op::NotEqual => Some((left != right) as i64),
+            Op::Less => Some((left < right) as i64),
+            Op::LessEqual => Some((left <= right) as i64),
+            Op::Greater => Some((left > right) as i64),
+            Op::GreaterEqual => Some((left >= right) as i64),
+            Op::BitAnd => Some(left & right),
+            Op::BitOr => Some(left | right),
+            Op::BitXor => Some(left ^ right),
+            Op::ShiftLeft => Some(left << shift_amount(right as Number)?),
+            Op::ShiftRight => Some(left >> shift_amount(right as Number)?)
+        };
+        match result {
+            Some(result) => Ok(Value::Int(result)),
+            None => self.apply(left as Number, right as Number).map(Value::Float)
+        }
+    }
+
+    /// Applies the operator to two exact values, see `Node::evaluate_exact_with()`.
+    fn apply_exact(&self, left: Value, right: Value) -> Result<Value, EvalError> {
+        match (left, right) {
+            (Value::Int(left), Value::Int(right)) => self.apply_integers(left, right),
+            (left, right) => self.apply(left.into(), right.into()).map(Value::Float)
+        }
+    }
+
+    /// Applies the operator to two fractions, see `Node::evaluate_rational_with()`.
+    #[cfg(feature = "rational")]
+    fn apply_rational(&self, left: Rational, right: Rational) -> Result<Rational, EvalError> {
+        let inexact = || EvalError::Inexact { expression: self.to_infix() };
+        match self.op {
+            Op::Add => Ok(left + right),
+            Op::Sub => Ok(left - right),
+            Op::AddPercent => Ok(left * (Rational::from(1) + right)),
+            Op::SubPercent => Ok(left * (Rational::from(1) - right)),
+            Op::Mult => Ok(left * right),
+            Op::Div | Op::Mod if right.is_zero() => Err(EvalError::DivByZero { pos: self.op_pos }),
+            Op::Div => Ok(left / right),
+            Op::Mod => Ok(left % right),
+            // Only integer powers of a fraction are always fractions.
+            Op::Pow => {
+                let exponent = right.to_i64().ok_or_else(inexact)?;
+                if left.is_zero() && exponent < 0 {
+                    return Err(EvalError::DivByZero { pos: self.op_pos });
+                }
+                left.checked_pow(exponent).ok_or_else(|| EvalError::Overflow { expression: self.to_infix() })
+            },
+            Op::Factorial | Op::Percent => unreachable!("{:?} isn't a binary operation", self.op),
+            Op::And | Op::Or => unreachable!("{:?} is evaluated by BinOp::logical()", self.op),
+            Op::In => unreachable!("in is evaluated by Range::contains()"),
+            Op::Equal => Ok(Rational::truth(left == right)),
+            Op::NotEqual => Ok(Rational::truth(left != right)),
+            Op::Less => Ok(Rational::truth(left < right)),
+            Op::LessEqual => Ok(Rational::truth(left <= right)),
+            Op::Greater => Ok(Rational::truth(left > right)),
+            Op::GreaterEqual => Ok(Rational::truth(left >= right)),
+            // The bitwise operators are only defined for 64-bit integers.
+            Op::BitAnd | Op::BitOr | Op::BitXor | Op::ShiftLeft | Op::ShiftRight => {
+                match (left.to_i64(), right.to_i64()) {
+                    (Some(left), Some(right)) => match self.apply_integers(left, right)? {
+                        Value::Int(result) => Ok(Rational::from(result)),
+                        Value::Float(result) => Rational::from_number(result).ok_or_else(inexact)
+                    },
+                    (left, right) => {
+                        let kind = |int: Option<i64>| if int.is_some() { ValueKind::Int } else { ValueKind::Float };
+                        Err(EvalError::TypeMismatch { op: self.op, left_kind: kind(left), right_kind: kind(right) })
+                    }
+                }
+            }
+        }
+    }
+
+    /// The range on the right hand side of `in`.
+    fn range(&self) -> Result<&Range, EvalError> {
+        self.right.as_any().downcast_ref::<Range>()
+            .ok_or_else(|| EvalError::ExpectedRange { expression: self.right.to_infix() })
+    }
+
+    /// Applies `and` or `or` to the truth of the left hand side, only
+    /// calling `right` for the truth of the right hand side if it's needed
+    /// to decide the result. Any nonzero value is true.
+    #[cfg(feature = "rational")]
+    fn logical(&self, left: bool, right: impl FnOnce() -> Result<bool, EvalError>) -> Result<bool, EvalError> {
+        match self.op {
+            Op::And => Ok(left && right()?),
+            Op::Or => Ok(left || right()?),
+            _ => unreachable!("{:?} isn't a logical operation", self.op)
+        }
+    }
+
+    /// Narrates applying the operator to the values
+    /// of both operands, see `Node::explain()`.
+    fn narrate(&self, env: &Environment, left: impl fmt::Display, right: impl fmt::Display, result: impl fmt::Display) {
+        env.narrate(|| match self.op {
+            Op::Add => format!("add {} to {} to get {}", left, right, result),
+            Op::Sub => format!("subtract {} from {} to get {}", right, left, result),
+            Op::AddPercent => format!("add {1} of {0} to {0} to get {2}", left, right, result),
+            Op::SubPercent => format!("subtract {1} of {0} from {0} to get {2}", left, right, result),
+            Op::Mult => format!("multiply {} by {} to get {}", left, right, result),
+            Op::Div => format!("divide {} by {} to get {}", left, right, result),
+            Op::Mod => format!("take the remainder of {} divided by {} to get {}", left, right, result),
+            Op::Pow => format!("raise {} to the power of {} to get {}", left, right, result),
+            op if op.is_comparison() => format!("compare {} {} {} to get {}", left, op.symbol(), right, result),
+            op => format!("calculate {} {} {} to get {}", left, op.symbol(), right, result)
+        });
+    }
+
+    /// Narrates `and` or `or`, where `right` is `None` if the
+    /// right hand side was skipped, see `Node::explain()`.
+    fn narrate_logical(&self, env: &Environment, left: Number, right: Option<Number>, result: Number) {
+        let truth = if left != 0.0 { "true" } else { "false" };
+        env.narrate(|| match right {
+            Some(right) => format!("calculate {} {} {} to get {}", left, self.op.symbol(), right, result),
+            None => format!("{} is {}, so skip {} to get {}", left, truth, self.right.to_infix(), result)
+        });
+    }
+}
+
+impl Node for BinOp {
+    fn evaluate_with(&self, env: &Environment) -> Result<Number, EvalError> {
+        env.evaluate(self)
+    }
+
+    fn evaluate_step<'a>(&'a self, operands: &[Value], env: &Environment) -> Result<EvalStep<'a>, EvalError> {
+        if operands.is_empty() {
+            env.step()?;
+        }
+        // Only exact evaluation keeps track of which operands
+        // are integers, see DivisionMode.
+        let operand = match self.op == Op::Div && env.division_mode == DivisionMode::Truncating {
+            true => EvalStep::EvaluateExact,
+            false => EvalStep::Evaluate
+        };
+        let step = match (self.op, operands) {
+            (_, []) => operand(self.left.as_ref()),
+            // The right hand side is only evaluated if it's needed.
+            (Op::And | Op::Or, [left]) => {
+                env.record(|stats| stats.add(self.op));
+                let left = Number::from(*left);
+                match (self.op, left != 0.0) {
+                    (Op::And, false) | (Op::Or, true) => {
+                        let result = truth(self.op == Op::Or);
+                        self.narrate_logical(env, left, None, result);
+                        EvalStep::Done(Value::Int(result as i64))
+                    },
+                    _ => EvalStep::Evaluate(self.right.as_ref())
+                }
+            },
+            (Op::And | Op::Or, [left, right, ..]) => {
+                let right = Number::from(*right);
+                let result = truth(right != 0.0);
+                self.narrate_logical(env, (*left).into(), Some(right), result);
+                EvalStep::Done(Value::Int(result as i64))
+            },
+            (Op::In, [_]) => {
+                env.record(|stats| stats.add(self.op));
+                EvalStep::Evaluate(self.range()?.low.as_ref())
+            },
+            (Op::In, [_, _]) => EvalStep::Evaluate(self.range()?.high.as_ref()),
+            (Op::In, [value, low, high, ..]) => {
+                let result = within(env, (*value).into(), (*low).into(), (*high).into());
+                EvalStep::Done(Value::Int(result as i64))
+            },
+            (_, [_]) => operand(self.right.as_ref()),
+            (_, [left, right, ..]) => {
+                env.record(|stats| stats.add(self.op));
+                let result = self.apply_exact(*left, *right)?;
+                self.narrate(env, left, right, result);
+                EvalStep::Done(result)
+            }
+        };
+        Ok(step)
+    }
+
+    fn evaluate_exact_with(&self, env: &Environment) -> Result<Value, EvalError> {
+        env.evaluate_exact(self)
+    }
+
+    #[cfg(feature = "rational")]
+    fn evaluate_rational_with(&self, env: &Environment) -> Result<Rational, EvalError> {
+        env.step()?;
+        let left = self.left.evaluate_rational_with(env)?;
+        if matches!(self.op, Op::And | Op::Or) {
+            env.record(|stats| stats.add(self.op));
+            let right = || self.right.evaluate_rational_with(env).map(|right| !right.is_zero());
+            return self.logical(!left.is_zero(), right).map(Rational::truth);
+        }
+        if self.op == Op::In {
+            env.record(|stats| stats.add(self.op));
+            return self.range()?.contains_rational(&left, env).map(Rational::truth);
+        }
+        let right = self.right.evaluate_rational_with(env)?;
+        env.record(|stats| stats.add(self.op));
+        self.apply_rational(left, right)
+    }
+
+    fn display_with(&self, depth: usize, opts: &DisplayOptions) -> String {
+        format!(
+            "BinOp {{\n{1}left: {2}\n{1}right: {3}\n{1}op: {4}\n{0}}}",
+            opts.indentation(depth),
+            opts.indentation(depth + 1),
+            self.left.display_with(depth + 1, opts), self.right.display_with(depth + 1, opts), self.op.symbol()
+        )
+    }
+    fn to_infix(&self) -> String {
+        // Written so that it reads back the same whichever mode it's parsed in.
+        let (left, right) = self.operand_precedences(true);
+        let left = infix_operand(self.left.as_ref(), left);
+        let right = infix_operand(self.right.as_ref(), right);
+        format!("{} {} {}", left, self.op.symbol(), right)
+    }
+
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"type\":\"BinOp\",\"op\":\"{:?}\",\"left\":{},\"right\":{}}}",
+            self.op, self.left.to_json(), self.right.to_json()
+        )
+    }
+
+    fn precedence(&self) -> u8 {
+        self.op.precedence()
+    }
+
+    fn simplify(mut self: Box<Self>) -> Box<dyn Node> {
+        let op = self.op;
+        let left = take(&mut self.left).simplify();
+        let right = if op.is_percentage() {
+            simplify_percentage(take(&mut self.right))
+        } else {
+            take(&mut self.right).simplify()
+        };
+        let (op_pos, start, end) = (self.op_pos, self.start, self.end);
+
+        match (constant_value(left.as_ref()), constant_value(right.as_ref())) {
+            (Some(_), Some(_)) => fold(Box::new(BinOp { left, right, op, op_pos, start, end })),
+            // A percentage of a constant is only folded along with what it's applied to.
+            (Some(_), _) if op.is_percentage() && right.children().into_iter().all(|child| constant_value(child).is_some()) => {
+                fold(Box::new(BinOp { left, right, op, op_pos, start, end }))
+            },
+            // x + 0, x - 0, x * 1, x / 1 and x ^ 1 are all just x
+            (_, Some(value)) if value == 0.0 && matches!(op, Op::Add | Op::Sub) => left,
+            (_, Some(value)) if value == 1.0 && matches!(op, Op::Mult | Op::Div | Op::Pow) => left,
+            // 0 + x and 1 * x are also just x
+            (Some(value), _) if value == 0.0 && op == Op::Add => right,
+            (Some(value), _) if value == 1.0 && op == Op::Mult => right,
+            _ => Box::new(BinOp { left, right, op, op_pos, start, end })
+        }
+    }
+
+    fn canonicalize(&self) -> Box<dyn Node> {
+        let left = self.left.canonicalize();
+        let right = self.right.canonicalize();
+        let (op, op_pos, start, end) = (self.op, self.op_pos, self.start, self.end);
+        if !op.is_commutative() {
+            return Box::new(BinOp { left, right, op, op_pos, start, end });
+        }
+
+        let mut operands = Vec::new();
+        flatten(left, op, &mut operands);
+        flatten(right, op, &mut operands);
+        // Spans aren't part of the JSON, so equal operands sort
+        // together wherever they were in the source.
+        operands.sort_by_cached_key(|operand| operand.to_json());
+        let mut operands = operands.into_iter();
+        let first = operands.next().expect("an operation has two operands");
+        operands.fold(first, |left, right| Box::new(BinOp { left, right, op, op_pos, start, end }))
+    }
+
+    fn differentiate(&self, var: &str) -> Result<Box<dyn Node>, EvalError> {
+        // Like a comparison, membership is constant except where it
+        // jumps between 0 and 1, and its range has no derivative.
+        if self.op == Op::In {
+            return Ok(int(0, self.span()));
+        }
+        let (left, right) = (self.left.clone(), self.right.clone());
+        let d_left = self.left.differentiate(var)?;
+        let d_right = self.right.differentiate(var)?;
+        let span = self.span();
+
+        Ok(match self.op {
+            Op::Add => sum(d_left, d_right),
+            Op::Sub => difference(d_left, d_right),
+            // (u + uv)' = u' + u'v + uv', and likewise for u - uv
+            Op::AddPercent => sum(d_left.clone(), sum(product(d_left, right), product(left, d_right))),
+            Op::SubPercent => difference(d_left.clone(), sum(product(d_left, right), product(left, d_right))),
+            // (uv)' = u'v + uv'
+            Op::Mult => sum(product(d_left, right), product(left, d_right)),
+            // (u/v)' = (u'v - uv') / v^2, or just u'/v when v is constant
+            Op::Div if is_zero(d_right.as_ref()) => quotient(d_left, right),
+            Op::Div => quotient(
+                difference(product(d_left, right.clone()), product(left, d_right)),
+                power(right, int(2, span))
+            ),
+            // (u^n)' = n u^(n - 1) u' when n is constant
+            Op::Pow if is_zero(d_right.as_ref()) => product(
+                product(right.clone(), power(left, difference(right, int(1, span)))),
+                d_left
+            ),
+            // (a^v)' = a^v ln(a) v' when a is constant
+            Op::Pow if is_zero(d_left.as_ref()) => product(
+                product(self.clone_box(), call("ln", left, span)),
+                d_right
+            ),
+            // Otherwise (u^v)' = u^v (v' ln(u) + v u' / u)
+            Op::Pow => product(self.clone_box(), sum(
+                product(d_right, call("ln", left.clone(), span)),
+                quotient(product(right, d_left), left)
+            )),
+            // u % v = u - v trunc(u / v), where the truncated
+            // quotient (u - u % v) / v is constant almost everywhere.
+            Op::Mod if is_zero(d_right.as_ref()) => d_left,
+            Op::Mod => difference(
+                d_left,
+                product(d_right, quotient(difference(left, self.clone_box()), right))
+            ),
+            // Comparisons and logical operators are constant
+            // except where they jump between 0 and 1.
+            op if op.is_comparison() || matches!(op, Op::And | Op::Or) => int(0, span),
+            _ if is_zero(d_left.as_ref()) && is_zero(d_right.as_ref()) => int(0, span),
+            _ => return Err(not_differentiable(self, var))
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+
+    fn eq_node(&self, other: &dyn Node) -> bool {
+        other.as_any().downcast_ref::<Self>().is_some_and(|other|
+            self.op == other.op
+                && self.left.eq_node(other.left.as_ref())
+                && self.right.eq_node(other.right.as_ref())
+        )
+    }
+
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visitor.visit_binop(self);
+        self.left.accept(visitor);
+        self.right.accept(visitor);
+    }
+
+    fn children(&self) -> Vec<&dyn Node> {
+        vec![self.left.as_ref(), self.right.as_ref()]
+    }
+
+    fn detach_children(&mut self, children: &mut Vec<Box<dyn Node>>) {
+        detach(&mut self.left, children);
+        detach(&mut self.right, children);
+    }
+
+    fn span(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Drop for BinOp {
+    fn drop(&mut self) {
+        drop_children(self);
+    }
+}
+
+/// A chain of two or more comparisons, such as `1 < x <= 10`,
+/// which is true when every comparison in it is true.
+/// 
+/// Like in maths, `1 < x < 10` means `1 < x` and `x < 10`, rather than
+/// comparing the result of `1 < x` with `10`. The operands are evaluated
+/// from left to right, each only once, and the chain stops at the first
+/// comparison which is false. A single comparison is a `BinOp`.
+/// 
+/// ```
+/// use std::{cell::Cell, rc::Rc};
+/// use simple_expression_parser::{Parser, Environment, ComparisonChain, Op};
+/// 
+/// let evaluate = |source: &str| Parser::new(source).parse().unwrap().evaluate();
+/// assert_eq!(evaluate("1<2<3"), Ok(1.0));
+/// assert_eq!(evaluate("3<2<1"), Ok(0.0));
+/// assert_eq!(evaluate("1 < 3 > 2"), Ok(1.0));
+/// assert_eq!(evaluate("1 == 1 < 2 != 2"), Ok(0.0));
+/// assert_eq!(evaluate("(3 < 2) < 1"), Ok(1.0));
+/// // The chain stops before the division by zero
+/// assert_eq!(evaluate("2 < 1 < 1/0"), Ok(0.0));
+/// 
+/// let tree = Parser::new("1 < x <= 10").parse().unwrap();
+/// let chain = tree.as_any().downcast_ref::<ComparisonChain>().unwrap();
+/// assert_eq!(chain.ops, [Op::Less, Op::LessEqual]);
+/// assert_eq!(chain.operands.len(), 3);
+/// assert_eq!(tree.to_infix(), "1 < x <= 10");
+/// 
+/// // The middle operand is only evaluated once
+/// let calls = Rc::new(Cell::new(0));
+/// let counter = Rc::clone(&calls);
+/// let mut env = Environment::new();
+/// env.define_function("f", move |arguments| {
+///     counter.set(counter.get() + 1);
+///     Ok(arguments[0])
+/// });
+/// let tree = Parser::new("1 < f(2) < 3").parse().unwrap();
+/// assert_eq!(tree.evaluate_with(&env), Ok(1.0));
+/// assert_eq!(calls.get(), 1);
+/// ```
+#[derive(Clone)]
+pub struct ComparisonChain {
+    /// The values compared, one more than there are comparisons.
+    pub operands: Vec<Box<dyn Node>>,
+    /// The comparison between each operand and the next.
+    pub ops: Vec<Op>,
+    pub start: usize,
+    pub end: usize
+}
+
+impl ComparisonChain {
+    /// Evaluates each operand in turn as a fraction, until one of the
+    /// comparisons is false, see `Node::evaluate_rational_with()`.
+    #[cfg(feature = "rational")]
+    fn evaluate_rational_chain(&self, env: &Environment) -> Result<bool, EvalError> {
+        let mut left = self.operands[0].evaluate_rational_with(env)?;
+        for (op, right) in self.ops.iter().zip(&self.operands[1..]) {
+            let right = right.evaluate_rational_with(env)?;
+            if !compare(env, *op, &left, &right, left.partial_cmp(&right)) {
+                return Ok(false);
+            }
+            left = right;
+        }
+        Ok(true)
+    }
+}
+
+impl Node for ComparisonChain {
+    fn evaluate_with(&self, env: &Environment) -> Result<Number, EvalError> {
+        env.evaluate(self)
+    }
+
+    fn evaluate_step<'a>(&'a self, operands: &[Value], env: &Environment) -> Result<EvalStep<'a>, EvalError> {
+        if operands.is_empty() {
+            env.step()?;
+        }
+        // Each operand is compared with the one before it as soon as it's
+        // evaluated. Integers are compared as integers, which is exact even
+        // where they're too large to be represented exactly as floats.
+        if let [.., left, right] = operands {
+            let order = match (left, right) {
+                (Value::Int(left), Value::Int(right)) => Some(left.cmp(right)),
+                (left, right) => Number::from(*left).partial_cmp(&Number::from(*right))
+            };
+            if !compare(env, self.ops[operands.len() - 2], left, right, order) {
+                return Ok(EvalStep::Done(Value::Int(0)));
+            }
+        }
+        match self.operands.get(operands.len()) {
+            Some(operand) => Ok(EvalStep::Evaluate(operand.as_ref())),
+            None => Ok(EvalStep::Done(Value::Int(1)))
+        }
+    }
+
+    fn evaluate_exact_with(&self, env: &Environment) -> Result<Value, EvalError> {
+        env.evaluate_exact(self)
+    }
+
+    #[cfg(feature = "rational")]
+    fn evaluate_rational_with(&self, env: &Environment) -> Result<Rational, EvalError> {
+        env.step()?;
+        self.evaluate_rational_chain(env).map(Rational::truth)
+    }
+
+    fn display_with(&self, depth: usize, opts: &DisplayOptions) -> String {
+        let operands: String = self.operands.iter()
+            .map(|operand| format!(
+                "{}{}\n",
+                opts.indentation(depth + 2),
+                operand.display_with(depth + 2, opts)
+            ))
+            .collect();
+        let ops: Vec<&str> = self.ops.iter().map(Op::symbol).collect();
+        format!(
+            "ComparisonChain {{\n{1}operands: [\n{2}{1}]\n{1}ops: {3}\n{0}}}",
+            opts.indentation(depth),
+            opts.indentation(depth + 1),
+            operands, ops.join(", ")
+        )
+    }
+
+    // Like the operands of a single comparison, any
+    // comparison within the chain is bracketed.
+    fn to_infix(&self) -> String {
+        let precedence = self.precedence();
+        let mut infix = infix_operand(self.operands[0].as_ref(), precedence + 1);
+        for (op, operand) in self.ops.iter().zip(&self.operands[1..]) {
+            infix += &format!(" {} {}", op.symbol(), infix_operand(operand.as_ref(), precedence + 1));
+        }
+        infix
+    }
+
+    fn to_json(&self) -> String {
+        let operands: Vec<String> = self.operands.iter()
+            .map(|operand| operand.to_json())
+            .collect();
+        let ops: Vec<String> = self.ops.iter().map(|op| format!("\"{:?}\"", op)).collect();
+        format!(
+            "{{\"type\":\"ComparisonChain\",\"ops\":[{}],\"operands\":[{}]}}",
+            ops.join(","), operands.join(",")
+        )
+    }
+
+    fn precedence(&self) -> u8 {
+        Op::Less.precedence()
+    }
+
+    fn simplify(mut self: Box<Self>) -> Box<dyn Node> {
+        self.operands = mem::take(&mut self.operands).into_iter()
+            .map(|operand| operand.simplify())
+            .collect();
+        if self.operands.iter().all(|operand| constant_value(operand.as_ref()).is_some()) {
+            fold(self)
+        } else {
+            self
+        }
+    }
+
+    fn canonicalize(&self) -> Box<dyn Node> {
+        let operands = self.operands.iter()
+            .map(|operand| operand.canonicalize())
+            .collect();
+        Box::new(ComparisonChain { operands, ops: self.ops.clone(), start: self.start, end: self.end })
+    }
+
+    // Like a single comparison, the chain is constant
+    // except where it jumps between 0 and 1.
+    fn differentiate(&self, _var: &str) -> Result<Box<dyn Node>, EvalError> {
+        Ok(int(0, self.span()))
+    }
+
+    fn clone_box(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+
+    fn eq_node(&self, other: &dyn Node) -> bool {
+        other.as_any().downcast_ref::<Self>().is_some_and(|other|
+            self.ops == other.ops
+                && self.operands.len() == other.operands.len()
+                && self.operands.iter().zip(&other.operands)
+                    .all(|(operand, other)| operand.eq_node(other.as_ref()))
+        )
+    }
+
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visitor.visit_comparison_chain(self);
+        for operand in &self.operands {
+            operand.accept(visitor);
+        }
+    }
+
+    fn children(&self) -> Vec<&dyn Node> {
+        self.operands.iter().map(|operand| operand.as_ref()).collect()
+    }
+
+    fn detach_children(&mut self, children: &mut Vec<Box<dyn Node>>) {
+        children.append(&mut self.operands);
+    }
+
+    fn span(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Whether the comparison `op` holds between two neighbouring operands
+/// of a chain, given how they're ordered, or `None` if they aren't
+/// ordered, as when either is `nan`.
+fn compare(env: &Environment, op: Op, left: impl fmt::Display, right: impl fmt::Display, order: Option<Ordering>) -> bool {
+    env.record(|stats| stats.add(op));
+    let result = match op {
+        Op::Equal => order == Some(Ordering::Equal),
+        Op::NotEqual => order != Some(Ordering::Equal),
+        Op::Less => order == Some(Ordering::Less),
+        Op::LessEqual => matches!(order, Some(Ordering::Less | Ordering::Equal)),
+        Op::Greater => order == Some(Ordering::Greater),
+        Op::GreaterEqual => matches!(order, Some(Ordering::Greater | Ordering::Equal)),
+        _ => unreachable!("{:?} isn't a comparison", op)
+    };
+    env.narrate(|| format!("compare {} {} {} to get {}", left, op.symbol(), right, truth(result)));
+    result
+}
+
+impl Drop for ComparisonChain {
+    fn drop(&mut self) {
+        drop_children(self);
+    }
+}
+
+/// Represents a unary operation, meaning it's a mathematical
+/// operation with just a right side.
+/// 
+/// The meaningful operations are `-x` and `not x`, though `+x` is
+/// still valid syntax, despite it not doing anything. Signs can be
+/// repeated, each applying to the result of the next.
+/// 
+/// ```
+/// use simple_expression_parser::{evaluate, Parser, UnaryOp, UnaryKind};
+/// 
+/// assert_eq!(evaluate("--5"), Ok(5.0));
+/// assert_eq!(evaluate("-+5"), Ok(-5.0));
+/// assert_eq!(evaluate("-(-(-5))"), Ok(-5.0));
+/// 
+/// let tree = Parser::new("-+5".to_string()).parse().unwrap();
+/// let neg = tree.as_any().downcast_ref::<UnaryOp>().unwrap();
+/// assert_eq!(neg.op, UnaryKind::Neg);
+/// assert_eq!(neg.right.as_any().downcast_ref::<UnaryOp>().unwrap().op, UnaryKind::Pos);
+/// ```
+#[derive(Clone)]
+pub struct UnaryOp {
+    pub right: Box<dyn Node>,
+    pub op: UnaryKind,
+    pub start: usize,
+    pub end: usize
+}
+
+impl UnaryOp {
+    /// Narrates applying the operator to the value
+    /// of its operand, see `Node::explain()`.
+    fn narrate(&self, env: &Environment, right: impl fmt::Display, result: impl fmt::Display) {
+        match self.op {
+            UnaryKind::Neg => env.narrate(|| format!("negate {} to get {}", right, result)),
+            // A '+' sign does nothing, so needs no step.
+            UnaryKind::Pos => {},
+            UnaryKind::Not => env.narrate(|| format!("take not {} to get {}", right, result))
+        }
+    }
+}
+
+impl Node for UnaryOp {
+    fn evaluate_with(&self, env: &Environment) -> Result<Number, EvalError> {
+        env.evaluate(self)
+    }
+
+    fn evaluate_step<'a>(&'a self, operands: &[Value], env: &Environment) -> Result<EvalStep<'a>, EvalError> {
+        let right = match operands {
+            [] => {
+                env.step()?;
+                return Ok(EvalStep::Evaluate(self.right.as_ref()));
+            },
+            [right, ..] => *right
+        };
+        if self.op != UnaryKind::Pos {
+            env.record(|stats| stats.add_unary(self.op));
+        }
+        let result = match (self.op, right) {
+            (UnaryKind::Neg, Value::Int(right)) => right.checked_neg()
+                .map_or(Value::Float(-(right as Number)), Value::Int),
+            (UnaryKind::Neg, Value::Float(right)) => Value::Float(-right),
+            (UnaryKind::Pos, right) => right,
+            (UnaryKind::Not, right) => Value::Int((Number::from(right) == 0.0) as i64)
+        };
+        self.narrate(env, right, result);
+        Ok(EvalStep::Done(result))
+    }
+
+    fn evaluate_exact_with(&self, env: &Environment) -> Result<Value, EvalError> {
+        env.evaluate_exact(self)
+    }
+
+    #[cfg(feature = "rational")]
+    fn evaluate_rational_with(&self, env: &Environment) -> Result<Rational, EvalError> {
+        env.step()?;
+        let right = self.right.evaluate_rational_with(env)?;
+        if self.op != UnaryKind::Pos {
+            env.record(|stats| stats.add_unary(self.op));
+        }
+        match self.op {
+            UnaryKind::Neg => Ok(-right),
+            UnaryKind::Pos => Ok(right),
+            UnaryKind::Not => Ok(Rational::truth(right.is_zero()))
+        }
+    }
+
+    fn display_with(&self, depth: usize, opts: &DisplayOptions) -> String {
+        format!(
+            "UnaryOp {{\n{1}right: {2}\n{1}op: {3}\n{0}}}",
+            opts.indentation(depth),
+            opts.indentation(depth + 1),
+            self.right.display_with(depth + 1, opts), self.op.symbol()
+        )
+    }
+    fn to_infix(&self) -> String {
+        match self.op {
+            UnaryKind::Not => format!("not {}", infix_operand(self.right.as_ref(), self.op.precedence())),
+            UnaryKind::Neg | UnaryKind::Pos => {
+                format!("{}{}", self.op.symbol(), infix_operand(self.right.as_ref(), self.op.precedence()))
+            }
+        }
+    }
+
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"type\":\"UnaryOp\",\"op\":\"{:?}\",\"right\":{}}}",
+            self.op, self.right.to_json()
+        )
+    }
+
+    fn precedence(&self) -> u8 {
+        self.op.precedence()
+    }
+
+    fn simplify(mut self: Box<Self>) -> Box<dyn Node> {
+        let right = take(&mut self.right).simplify();
+        match self.op {
+            UnaryKind::Neg => {
+                // --x is just x
+                match right.as_any().downcast_ref::<UnaryOp>() {
+                    Some(inner) if inner.op == UnaryKind::Neg => inner.right.clone(),
+                    _ => Box::new(UnaryOp { right, op: UnaryKind::Neg, start: self.start, end: self.end })
+                }
+            },
+            // +x leaves the value unchanged.
+            UnaryKind::Pos => right,
+            UnaryKind::Not => {
+                let not = Box::new(UnaryOp { right, op: UnaryKind::Not, start: self.start, end: self.end });
+                if constant_value(not.right.as_ref()).is_some() { fold(not) } else { not }
+            }
+        }
+    }
+
+    fn canonicalize(&self) -> Box<dyn Node> {
+        let right = self.right.canonicalize();
+        Box::new(UnaryOp { right, op: self.op, start: self.start, end: self.end })
+    }
+
+    fn differentiate(&self, var: &str) -> Result<Box<dyn Node>, EvalError> {
+        let d_right = self.right.differentiate(var)?;
+        match self.op {
+            UnaryKind::Neg => Ok(negate(d_right)),
+            UnaryKind::Pos => Ok(d_right),
+            UnaryKind::Not => Ok(int(0, self.span()))
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+
+    fn eq_node(&self, other: &dyn Node) -> bool {
+        other.as_any().downcast_ref::<Self>().is_some_and(|other|
+            self.op == other.op
+                && self.right.eq_node(other.right.as_ref())
+        )
+    }
+
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visitor.visit_unaryop(self);
+        self.right.accept(visitor);
+    }
+
+    fn children(&self) -> Vec<&dyn Node> {
+        vec![self.right.as_ref()]
+    }
+
+    fn detach_children(&mut self, children: &mut Vec<Box<dyn Node>>) {
+        detach(&mut self.right, children);
+    }
+
+    fn span(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Drop for UnaryOp {
+    fn drop(&mut self) {
+        drop_children(self);
+    }
+}
+
+/// Represents a postfix operation, meaning it's a mathematical
+/// operation with just a left side.
+/// 
+/// The only postfix operation is the factorial, `x!`.
+#[derive(Clone)]
+pub struct PostfixOp {
+    pub left: Box<dyn Node>,
+    pub op: Op,
+    pub start: usize,
+    pub end: usize
+}
+
+impl PostfixOp {
+    /// Applies the operator to the value of the operand.
+    fn apply(&self, left: Number) -> Result<Number, EvalError> {
+        match self.op {
+            Op::Factorial => factorial(left),
+            Op::Percent => Ok(left / 100.0),
+            _ => Ok(left)
+        }
+    }
+
+    /// Narrates applying the operator to the value
+    /// of its operand, see `Node::explain()`.
+    fn narrate(&self, env: &Environment, left: impl fmt::Display, result: impl fmt::Display) {
+        env.narrate(|| match self.op {
+            Op::Factorial => format!("take the factorial of {} to get {}", left, result),
+            _ => format!("take {} percent to get {}", left, result)
+        });
+    }
+}
+
+impl Node for PostfixOp {
+    fn evaluate_with(&self, env: &Environment) -> Result<Number, EvalError> {
+        env.evaluate(self)
+    }
+
+    fn evaluate_step<'a>(&'a self, operands: &[Value], env: &Environment) -> Result<EvalStep<'a>, EvalError> {
+        let left = match operands {
+            [] => {
+                env.step()?;
+                return Ok(EvalStep::Evaluate(self.left.as_ref()));
+            },
+            [left, ..] => *left
+        };
+        env.record(|stats| stats.add(self.op));
+        let result = match (self.op, left) {
+            (Op::Factorial, Value::Int(left)) if left >= 0 => {
+                match (2..=left).try_fold(1i64, i64::checked_mul) {
+                    Some(result) => Value::Int(result),
+                    None => factorial(left as Number).map(Value::Float)?
+                }
+            },
+            (_, left) => self.apply(left.into()).map(Value::Float)?
+        };
+        self.narrate(env, left, result);
+        Ok(EvalStep::Done(result))
+    }
+
+    fn evaluate_exact_with(&self, env: &Environment) -> Result<Value, EvalError> {
+        env.evaluate_exact(self)
+    }
+
+    #[cfg(feature = "rational")]
+    fn evaluate_rational_with(&self, env: &Environment) -> Result<Rational, EvalError> {
+        env.step()?;
+        let left = self.left.evaluate_rational_with(env)?;
+        env.record(|stats| stats.add(self.op));
+        match self.op {
+            Op::Factorial => match left.to_i64() {
+                Some(value) if value >= 0 => Rational::checked_factorial(value as u64)
+                    .ok_or_else(|| EvalError::Overflow { expression: self.to_infix() }),
+                _ => Err(EvalError::InvalidFactorial { value: left.to_f64() })
+            },
+            Op::Percent => Ok(left / Rational::from(100)),
+            _ => Ok(left)
+        }
+    }
+
+    fn display_with(&self, depth: usize, opts: &DisplayOptions) -> String {
+        format!(
+            "PostfixOp {{\n{1}left: {2}\n{1}op: {3}\n{0}}}",
+            opts.indentation(depth),
+            opts.indentation(depth + 1),
+            self.left.display_with(depth + 1, opts), self.op.symbol()
+        )
+    }
+
+    fn to_infix(&self) -> String {
+        format!("{}{}", infix_operand(self.left.as_ref(), POSTFIX_PRECEDENCE), self.op.symbol())
+    }
+
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"type\":\"PostfixOp\",\"op\":\"{:?}\",\"left\":{}}}",
+            self.op, self.left.to_json()
+        )
+    }
+
+    fn precedence(&self) -> u8 {
+        POSTFIX_PRECEDENCE
+    }
+
+    fn simplify(mut self: Box<Self>) -> Box<dyn Node> {
+        let left = take(&mut self.left).simplify();
+        let node = Box::new(PostfixOp { left, op: self.op, start: self.start, end: self.end });
+        if constant_value(node.left.as_ref()).is_some() {
+            fold(node)
+        } else {
+            node
+        }
+    }
+
+    fn canonicalize(&self) -> Box<dyn Node> {
+        let left = self.left.canonicalize();
+        Box::new(PostfixOp { left, op: self.op, start: self.start, end: self.end })
+    }
+
+    fn differentiate(&self, var: &str) -> Result<Box<dyn Node>, EvalError> {
+        let d_left = self.left.differentiate(var)?;
+        match self.op {
+            Op::Percent => Ok(quotient(d_left, int(100, self.span()))),
+            // The factorial is only defined for integers.
+            _ if is_zero(d_left.as_ref()) => Ok(d_left),
+            _ => Err(not_differentiable(self, var))
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+
+    fn eq_node(&self, other: &dyn Node) -> bool {
+        other.as_any().downcast_ref::<Self>().is_some_and(|other|
+            self.op == other.op
+                && self.left.eq_node(other.left.as_ref())
+        )
+    }
+
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visitor.visit_postfixop(self);
+        self.left.accept(visitor);
+    }
+
+    fn children(&self) -> Vec<&dyn Node> {
+        vec![self.left.as_ref()]
+    }
+
+    fn detach_children(&mut self, children: &mut Vec<Box<dyn Node>>) {
+        detach(&mut self.left, children);
+    }
+
+    fn span(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Drop for PostfixOp {
+    fn drop(&mut self) {
+        drop_children(self);
+    }
+}
+
+/// Converts the result of a comparison into a number,
+/// 1 for true and 0 for false.
+fn truth(value: bool) -> Number {
+    if value { 1.0 } else { 0.0 }
+}
+
+/// Whether `value` lies within `low..high`, narrating the check.
+fn within(env: &Environment, value: Number, low: Number, high: Number) -> bool {
+    let within = low <= value && value <= high;
+    env.narrate(|| format!("check whether {} is within {}..{} to get {}", value, low, high, truth(within)));
+    within
+}
+
+/// Appends the operands of a chain of `op` to `operands`, from left
+/// to right, or just `node` itself if it isn't an `op` operation.
+fn flatten(node: Box<dyn Node>, op: Op, operands: &mut Vec<Box<dyn Node>>) {
+    match node.as_any().downcast_ref::<BinOp>() {
+        Some(binop) if binop.op == op => {
+            flatten(binop.left.clone(), op, operands);
+            flatten(binop.right.clone(), op, operands);
+        },
+        _ => operands.push(node)
+    }
+}
+
+/// Converts `value`, which must be an integer, into
+/// the number of bits to shift by, from 0 to 63.
+fn shift_amount(value: Number) -> Result<u32, EvalError> {
+    match value as i64 {
+        amount @ 0..=63 => Ok(amount as u32),
+        _ => Err(EvalError::InvalidShift { amount: value })
+    }
+}
+
+/// Computes `value!`, erroring unless `value` is a non-negative integer.
+fn factorial(value: Number) -> Result<Number, EvalError> {
+    if value < 0.0 || math::fract(value) != 0.0 {
+        return Err(EvalError::InvalidFactorial { value });
+    }
+    let mut result: Number = 1.0;
+    let mut n = 2.0;
+    // Once the result overflows to infinity there's no point continuing.
+    while n <= value && result.is_finite() {
+        result *= n;
+        n += 1.0;
+    }
+    if result.is_finite() {
+        Ok(result)
+    } else {
+        Err(EvalError::Overflow { expression: format!("{}!", value) })
+    }
+}
+
+/// Integer constants
+/// 
+/// e.g. `3` or `100`
+/// 
+/// An integer literal too large for an `i64`, whether written in decimal
+/// or with a radix prefix such as `0x`, is read as a `FloatLiteral`
+/// instead, the same as if it were written in scientific notation. It's
+/// only a `ParseError::LiteralOverflow` if it's too large for a float.
+#[derive(Clone)]
+pub struct IntLiteral {
+    pub value: i64,
+    pub start: usize,
+    pub end: usize
+}
+
+impl Node for IntLiteral {
+    fn evaluate_with(&self, env: &Environment) -> Result<Number, EvalError> {
+        env.step()?;
+        Ok(self.value as Number)
+    }
+
+    fn evaluate_exact_with(&self, env: &Environment) -> Result<Value, EvalError> {
+        env.step()?;
+        Ok(Value::Int(self.value))
+    }
+
+    #[cfg(feature = "rational")]
+    fn evaluate_rational_with(&self, env: &Environment) -> Result<Rational, EvalError> {
+        env.step()?;
+        Ok(Rational::from(self.value))
+    }
+
+    fn display_with(&self, depth: usize, opts: &DisplayOptions) -> String {
+        format!(
+            "IntLiteral {{\n{1}value: {2}\n{0}}}",
+            opts.indentation(depth),
+            opts.indentation(depth + 1),
+            self.value
+        )
+    }
+
+    fn to_infix(&self) -> String {
+        self.value.to_string()
+    }
+
+    fn to_json(&self) -> String {
+        format!("{{\"type\":\"IntLiteral\",\"value\":{}}}", self.value)
+    }
+
+    fn simplify(self: Box<Self>) -> Box<dyn Node> {
+        self
+    }
+
+    fn differentiate(&self, _var: &str) -> Result<Box<dyn Node>, EvalError> {
+        Ok(int(0, self.span()))
+    }
+
+    fn clone_box(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+
+    fn eq_node(&self, other: &dyn Node) -> bool {
+        other.as_any().downcast_ref::<Self>()
+            .is_some_and(|other| self.value == other.value)
+    }
+
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visitor.visit_int(self);
+    }
+
+    fn span(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Decimal constants
+/// 
+/// e.g. `3.14` or `1.234`
+#[derive(Clone)]
+pub struct FloatLiteral {
+    pub value: Number,
+    pub start: usize,
+    pub end: usize
+}
+
+impl Node for FloatLiteral {
+    fn evaluate_with(&self, env: &Environment) -> Result<Number, EvalError> {
+        env.step()?;
+        Ok(self.value)
+    }
+
+    #[cfg(feature = "rational")]
+    fn evaluate_rational_with(&self, env: &Environment) -> Result<Rational, EvalError> {
+        env.step()?;
+        Rational::from_number(self.value)
+            .ok_or_else(|| EvalError::Inexact { expression: self.to_infix() })
+    }
+
+    fn display_with(&self, depth: usize, opts: &DisplayOptions) -> String {
+        format!(
+            "FloatLiteral {{\n{1}value: {2:?}\n{0}}}",
+            opts.indentation(depth),
+            opts.indentation(depth + 1),
+            self.value
+        )
+    }
+
+    // The debug format always includes a decimal point or an exponent,
+    // such as `1.0` or `1e300`, so the text is read back as a FloatLiteral.
+    fn to_infix(&self) -> String {
+        format!("{:?}", self.value)
+    }
+
+    fn to_json(&self) -> String {
+        format!("{{\"type\":\"FloatLiteral\",\"value\":{}}}", json_number(self.value))
+    }
+
+    fn simplify(self: Box<Self>) -> Box<dyn Node> {
+        self
+    }
+
+    fn differentiate(&self, _var: &str) -> Result<Box<dyn Node>, EvalError> {
+        Ok(int(0, self.span()))
+    }
+
+    fn clone_box(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+
+    fn eq_node(&self, other: &dyn Node) -> bool {
+        other.as_any().downcast_ref::<Self>()
+            .is_some_and(|other| self.value == other.value)
+    }
+
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visitor.visit_float(self);
+    }
+
+    fn span(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+/// Named mathematical constants
+/// 
+/// e.g. `pi` or `e`, or any constant defined with `Parser::define_constant()`
+/// 
+/// The special float values are also constants, written as `inf` and
+/// `nan` in any case, and behave as IEEE 754 specifies. In particular
+/// `nan` isn't equal to anything, including itself.
+/// 
+/// ```
+/// use simple_expression_parser::evaluate;
+/// 
+/// assert_eq!(evaluate("inf"), Ok(f64::INFINITY));
+/// assert_eq!(evaluate("-inf < -1e308"), Ok(1.0));
+/// assert!(evaluate("NaN").unwrap().is_nan());
+/// assert!(evaluate("inf - inf").unwrap().is_nan());
+/// assert_eq!(evaluate("nan == nan"), Ok(0.0));
+/// assert_eq!(evaluate("nan != nan"), Ok(1.0));
+/// ```
+#[derive(Clone)]
+pub struct Constant {
+    pub name: String,
+    pub value: Number,
+    pub start: usize,
+    pub end: usize
+}
+
+impl Node for Constant {
+    fn evaluate_with(&self, env: &Environment) -> Result<Number, EvalError> {
+        env.step()?;
+        Ok(self.value)
+    }
+
+    fn display_with(&self, depth: usize, opts: &DisplayOptions) -> String {
+        format!(
+            "Constant {{\n{1}name: {2}\n{1}value: {3}\n{0}}}",
+            opts.indentation(depth),
+            opts.indentation(depth + 1),
+            self.name, self.value
+        )
+    }
+
+    // A constant defined with `Parser::define_constant()` is written as
+    // its value, followed by a comment naming it, since without the
+    // same definition its name would be read back as a variable.
+    fn to_infix(&self) -> String {
+        if self.is_built_in() {
+            return self.name.clone();
+        }
+        let value = number_to_infix(self.value);
+        // A name which would end the comment early is left out.
+        if self.name.contains("*/") {
+            return value;
+        }
+        format!("{} /* {} */", value, self.name)
+    }
+
+    fn precedence(&self) -> u8 {
+        // A negative value written in place of the name, as in
+        // `-1.5 /* g */`, must be bracketed like a negation.
+        if !self.is_built_in() && self.value.is_sign_negative() && !self.value.is_nan() {
+            return UNARY_PRECEDENCE;
+        }
+        ATOM_PRECEDENCE
+    }
+
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"type\":\"Constant\",\"name\":{},\"value\":{}}}",
+            json_string(&self.name), json_number(self.value)
+        )
+    }
+
+    fn simplify(self: Box<Self>) -> Box<dyn Node> {
+        self
+    }
+
+    fn differentiate(&self, _var: &str) -> Result<Box<dyn Node>, EvalError> {
+        Ok(int(0, self.span()))
+    }
+
+    fn clone_box(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+
+    fn eq_node(&self, other: &dyn Node) -> bool {
+        other.as_any().downcast_ref::<Self>()
+            // Compared by bits so that a `nan` constant equals itself.
+            .is_some_and(|other| self.name == other.name && self.value.to_bits() == other.value.to_bits())
+    }
+
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visitor.visit_constant(self);
+    }
+
+    fn span(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Constant {
+    /// Whether the constant is one of the built-in constants, such as
+    /// `pi`, rather than one defined with `Parser::define_constant()`.
+    fn is_built_in(&self) -> bool {
+        parser::constant(&self.name).is_some_and(|value| value.to_bits() == self.value.to_bits())
+    }
+}
+
+/// Named values which are bound when the expression is evaluated
+/// 
+/// e.g. `x` or `rate`
+#[derive(Clone)]
+pub struct Variable {
+    pub name: String,
+    pub start: usize,
+    pub end: usize
+}
+
+impl Node for Variable {
+    fn evaluate_with(&self, env: &Environment) -> Result<Number, EvalError> {
+        env.step()?;
+        let value = env.get(&self.name)
+            .ok_or_else(|| EvalError::UnboundVariable { name: self.name.clone() })?;
+        env.narrate(|| format!("look up {} to get {}", self.name, value));
+        Ok(value)
+    }
+
+    #[cfg(feature = "rational")]
+    fn evaluate_rational_with(&self, env: &Environment) -> Result<Rational, EvalError> {
+        let value = self.evaluate_with(env)?;
+        Rational::from_number(value)
+            .ok_or_else(|| EvalError::Inexact { expression: self.name.clone() })
+    }
+
+    fn display_with(&self, depth: usize, opts: &DisplayOptions) -> String {
+        format!(
+            "Variable {{\n{1}name: {2}\n{0}}}",
+            opts.indentation(depth),
+            opts.indentation(depth + 1),
+            self.name
+        )
+    }
+
+    fn to_infix(&self) -> String {
+        self.name.clone()
+    }
+
+    fn to_json(&self) -> String {
+        format!("{{\"type\":\"Variable\",\"name\":{}}}", json_string(&self.name))
+    }
+
+    fn simplify(self: Box<Self>) -> Box<dyn Node> {
+        self
+    }
+
+    fn differentiate(&self, var: &str) -> Result<Box<dyn Node>, EvalError> {
+        Ok(int((self.name == var) as i64, self.span()))
+    }
+
+    fn clone_box(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+
+    fn eq_node(&self, other: &dyn Node) -> bool {
+        other.as_any().downcast_ref::<Self>()
+            .is_some_and(|other| self.name == other.name)
+    }
+
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visitor.visit_variable(self);
+    }
+
+    fn span(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Calls a built-in function with a list of arguments
+/// 
+/// e.g. `sqrt(2)`, `sin(pi / 2)` or `max(1, 2, 3)`
+/// 
+/// `min()`, `max()`, `sum()` and `avg()` reduce any number of arguments
+/// to one, but need at least one to reduce.
+/// 
+/// ```
+/// use simple_expression_parser::{evaluate, EvalError, Parser};
+/// 
+/// assert_eq!(evaluate("sum(5)"), Ok(5.0));
+/// assert_eq!(evaluate("sum(1, 2, 3, 4)"), Ok(10.0));
+/// assert_eq!(evaluate("avg(2, 4)"), Ok(3.0));
+/// assert_eq!(evaluate("avg(1, 2, 3, 4)"), Ok(2.5));
+/// 
+/// let empty = Parser::new("sum()").parse().unwrap();
+/// assert!(matches!(
+///     empty.evaluate(),
+///     Err(EvalError::WrongArgumentCount { min: 1, max: None, found: 0, .. })
+/// ));
+/// ```
+#[derive(Clone)]
+pub struct FunctionCall {
+    pub name: String,
+    pub arguments: Vec<Box<dyn Node>>,
+    pub start: usize,
+    pub end: usize
+}
+
+/// The minimum and maximum number of arguments taken by a built-in
+/// function, or `None` if no function has that name. A maximum
+/// of `None` means the function takes any number of arguments.
+fn arity(name: &str) -> Option<(usize, Option<usize>)> {
+    match name {
+        "sin" | "cos" | "tan" | "asin" | "acos" | "atan"
+        | "sinh" | "cosh" | "tanh" | "sqrt" | "cbrt" | "abs"
+        | "exp" | "ln" | "log2" | "log10" | "floor" | "ceil" | "round" => Some((1, Some(1))),
+        "atan2" | "hypot" | "randint" => Some((2, Some(2))),
+        "rand" => Some((0, Some(0))),
+        "min" | "max" | "sum" | "avg" => Some((1, None)),
+        _ => None
+    }
+}
+
+impl FunctionCall {
+    /// Applies the function to the values of its arguments, which
+    /// must already have been checked unless it's a custom function.
+    fn apply(&self, env: &Environment, arguments: &[Number]) -> Result<Number, EvalError> {
+        if let Some(function) = env.function(&self.name) {
+            return function(arguments)
+                .map_err(|message| EvalError::FunctionFailed { name: self.name.clone(), message });
+        }
+        // The random functions depend on the environment's generator.
+        match self.name.as_str() {
+            "rand" => return Ok(env.random()),
+            "randint" => return random_int(env, arguments[0], arguments[1]),
+            _ => {}
+        }
+        math::builtin(&self.name, arguments)
+            .ok_or_else(|| EvalError::RequiresStd { operation: format!("{}()", self.name) })
+    }
+
+    /// Whether this calls one of the random functions, which give a
+    /// different value each time, even with the same arguments.
+    fn is_random(&self) -> bool {
+        matches!(self.name.as_str(), "rand" | "randint")
+    }
 
-    /// Display function should produce a string in the following format:
-    /// 
-    /// ```
-    /// ObjectName {
-    /// |-> attribute1: ChildObject {
-    /// |-> |-> ...
-    /// |-> }
-    /// }
-    /// ```
-    /// 
-    /// Where each `|-> ` is equal to `depth`+1.
-    /// 
-    /// Unless `depth` == 0, the first line should not have any indentation,
-    /// as it is inlined with the parent display string.
-    /// 
-    fn display(&self, depth: usize) -> String;
+    /// Checks that the built-in function exists and
+    /// is given the right number of arguments.
+    fn check_arity(&self) -> Result<(), EvalError> {
+        let (min, max) = arity(&self.name)
+            .ok_or_else(|| EvalError::UnknownFunction { name: self.name.clone() })?;
+        let found = self.arguments.len();
+        if found < min || max.is_some_and(|max| found > max) {
+            return Err(EvalError::WrongArgumentCount { name: self.name.clone(), min, max, found });
+        }
+        Ok(())
+    }
 }
 
-/// Represents a binary operation, meaning it's a mathematical
-/// operation with both a left and right side.
-/// 
-/// For example `1 + 1` is a binary operation.
-/// It has a left and right hand side, with an operation in the middle.
-pub struct BinOp {
-    pub left: Box<dyn Node>,
-    pub right: Box<dyn Node>,
-    pub op: Op
+/// A random integer from `low` to `high` inclusive, as returned by `randint()`.
+fn random_int(env: &Environment, low: Number, high: Number) -> Result<Number, EvalError> {
+    let integers = [low, high].iter().all(|bound| bound.is_finite() && math::fract(*bound) == 0.0);
+    if !integers || low > high {
+        return Err(EvalError::InvalidRange { low, high });
+    }
+    let offset = math::floor(env.random() * (high - low + 1.0));
+    // Rounding can push the offset up to the size of the range, when
+    // the range is too large for every integer in it to be represented.
+    Ok((low + offset).min(high))
 }
 
-impl Node for BinOp {
-    fn evaluate(&self) -> f32 {
-        // Simple map to rust native operations
-        match self.op {
-            Op::Add => self.left.evaluate() + self.right.evaluate(),
-            Op::Sub => self.left.evaluate() - self.right.evaluate(),
-            Op::Div => self.left.evaluate() / self.right.evaluate(),
-            Op::Mult => self.left.evaluate() * self.right.evaluate(),
+impl Node for FunctionCall {
+    fn evaluate_with(&self, env: &Environment) -> Result<Number, EvalError> {
+        env.evaluate(self)
+    }
+
+    fn evaluate_step<'a>(&'a self, operands: &[Value], env: &Environment) -> Result<EvalStep<'a>, EvalError> {
+        if operands.is_empty() {
+            env.step()?;
+            // Custom functions check their own arguments.
+            if env.function(&self.name).is_none() {
+                self.check_arity()?;
+            }
+        }
+        match self.arguments.get(operands.len()) {
+            // Arguments are always floats, as are the values of functions.
+            Some(argument) => Ok(EvalStep::EvaluateFloat(argument.as_ref())),
+            None => {
+                env.record(|stats| stats.function_calls += 1);
+                let arguments: Vec<Number> = operands.iter().map(|&operand| operand.into()).collect();
+                let result = self.apply(env, &arguments)?;
+                env.narrate(|| {
+                    let mut listed: Vec<String> = operands.iter().map(|operand| operand.to_string()).collect();
+                    match listed.pop() {
+                        None => format!("call {} to get {}", self.name, result),
+                        Some(last) if listed.is_empty() => format!("apply {} to {} to get {}", self.name, last, result),
+                        Some(last) => format!("apply {} to {} and {} to get {}", self.name, listed.join(", "), last, result)
+                    }
+                });
+                Ok(EvalStep::Done(Value::Float(result)))
+            }
         }
     }
 
-    fn display(&self, depth: usize) -> String {
+    #[cfg(feature = "rational")]
+    fn evaluate_rational_with(&self, env: &Environment) -> Result<Rational, EvalError> {
+        env.step()?;
+        // Nothing is known about the values of custom functions.
+        if env.function(&self.name).is_some() {
+            return Err(EvalError::Inexact { expression: self.to_infix() });
+        }
+        self.check_arity()?;
+
+        let arguments = self.arguments.iter()
+            .map(|argument| argument.evaluate_rational_with(env))
+            .collect::<Result<Vec<Rational>, EvalError>>()?;
+        env.record(|stats| stats.function_calls += 1);
+        match (self.name.as_str(), arguments.first()) {
+            ("abs", Some(argument)) => Ok(argument.abs()),
+            ("floor", Some(argument)) => Ok(argument.floor()),
+            ("ceil", Some(argument)) => Ok(argument.ceil()),
+            ("round", Some(argument)) => Ok(argument.round()),
+            ("min", _) => Ok(arguments.iter().min().cloned().expect("min() takes at least one argument")),
+            ("max", _) => Ok(arguments.iter().max().cloned().expect("max() takes at least one argument")),
+            ("sum", _) => Ok(arguments.into_iter().fold(Rational::from(0), |total, argument| total + argument)),
+            ("avg", _) => {
+                let count = Rational::from(arguments.len() as i64);
+                Ok(arguments.into_iter().fold(Rational::from(0), |total, argument| total + argument) / count)
+            },
+            _ => Err(EvalError::Inexact { expression: self.to_infix() })
+        }
+    }
+
+    fn display_with(&self, depth: usize, opts: &DisplayOptions) -> String {
+        let arguments: String = self.arguments.iter()
+            .map(|argument| format!(
+                "{}{}\n",
+                opts.indentation(depth + 2),
+                argument.display_with(depth + 2, opts)
+            ))
+            .collect();
+        format!(
+            "FunctionCall {{\n{1}name: {2}\n{1}arguments: [\n{3}{1}]\n{0}}}",
+            opts.indentation(depth),
+            opts.indentation(depth + 1),
+            self.name, arguments
+        )
+    }
+
+    fn to_infix(&self) -> String {
+        let arguments: Vec<String> = self.arguments.iter()
+            .map(|argument| argument.to_infix())
+            .collect();
+        format!("{}({})", self.name, arguments.join(", "))
+    }
+
+    fn to_json(&self) -> String {
+        let arguments: Vec<String> = self.arguments.iter()
+            .map(|argument| argument.to_json())
+            .collect();
         format!(
-            "BinOp {{\n{1}left: {2}\n{1}right: {3}\n{1}op: {4:#?}\n{0}}}",
-            " ".repeat(depth*DISPLAY_INDENTATION),
-            " ".repeat((depth+1)*DISPLAY_INDENTATION),
-            self.left.display(depth + 1), self.right.display(depth + 1), self.op
+            "{{\"type\":\"FunctionCall\",\"name\":{},\"arguments\":[{}]}}",
+            json_string(&self.name), arguments.join(",")
+        )
+    }
+
+    fn simplify(mut self: Box<Self>) -> Box<dyn Node> {
+        let arguments = mem::take(&mut self.arguments).into_iter()
+            .map(|argument| argument.simplify())
+            .collect();
+        let node = Box::new(FunctionCall { name: mem::take(&mut self.name), arguments, start: self.start, end: self.end });
+        // A random function gives a different value each time,
+        // even with constant arguments, so is never folded.
+        if !node.is_random() && node.arguments.iter().all(|argument| constant_value(argument.as_ref()).is_some()) {
+            fold(node)
+        } else {
+            node
+        }
+    }
+
+    // Arguments are canonicalized but never reordered,
+    // even for functions like `max()` where it's safe to.
+    fn canonicalize(&self) -> Box<dyn Node> {
+        let arguments = self.arguments.iter()
+            .map(|argument| argument.canonicalize())
+            .collect();
+        Box::new(FunctionCall { name: self.name.clone(), arguments, start: self.start, end: self.end })
+    }
+
+    fn differentiate(&self, var: &str) -> Result<Box<dyn Node>, EvalError> {
+        let derivatives = self.arguments.iter()
+            .map(|argument| argument.differentiate(var))
+            .collect::<Result<Vec<Box<dyn Node>>, EvalError>>()?;
+        let span = self.span();
+        if derivatives.iter().all(|derivative| is_zero(derivative.as_ref())) {
+            return Ok(int(0, span));
+        }
+
+        // The chain rule, (f(u))' = f'(u) u', for the functions of one argument.
+        let chain = |outer: Box<dyn Node>| Ok(product(outer, derivatives[0].clone()));
+        let u = || self.arguments[0].clone();
+        // 1 - u^2 and 1 + u^2, used by the inverse trigonometric functions.
+        let one_minus_square = || difference(int(1, span), power(u(), int(2, span)));
+        let one_plus_square = || sum(int(1, span), power(u(), int(2, span)));
+
+        match (self.name.as_str(), &self.arguments[..], &derivatives[..]) {
+            ("sin", [_], _) => chain(call("cos", u(), span)),
+            ("cos", [_], _) => chain(negate(call("sin", u(), span))),
+            ("tan", [_], [du]) => Ok(quotient(du.clone(), power(call("cos", u(), span), int(2, span)))),
+            ("asin", [_], [du]) => Ok(quotient(du.clone(), call("sqrt", one_minus_square(), span))),
+            ("acos", [_], [du]) => Ok(negate(quotient(du.clone(), call("sqrt", one_minus_square(), span)))),
+            ("atan", [_], [du]) => Ok(quotient(du.clone(), one_plus_square())),
+            ("sinh", [_], _) => chain(call("cosh", u(), span)),
+            ("cosh", [_], _) => chain(call("sinh", u(), span)),
+            ("tanh", [_], [du]) => Ok(quotient(du.clone(), power(call("cosh", u(), span), int(2, span)))),
+            ("sqrt", [_], [du]) => Ok(quotient(du.clone(), product(int(2, span), self.clone_box()))),
+            ("cbrt", [_], [du]) => Ok(quotient(du.clone(), product(int(3, span), power(self.clone_box(), int(2, span))))),
+            ("abs", [_], _) => chain(quotient(u(), self.clone_box())),
+            ("exp", [_], _) => chain(self.clone_box()),
+            ("ln", [_], [du]) => Ok(quotient(du.clone(), u())),
+            ("log2", [_], [du]) => Ok(quotient(du.clone(), product(u(), call("ln", int(2, span), span)))),
+            ("log10", [_], [du]) => Ok(quotient(du.clone(), product(u(), call("ln", int(10, span), span)))),
+            // Rounding is constant except where it jumps between integers.
+            ("floor" | "ceil" | "round", [_], _) => Ok(int(0, span)),
+            // atan2(y, x)' = (x y' - y x') / (x^2 + y^2)
+            ("atan2", [y, x], [dy, dx]) => Ok(quotient(
+                difference(product(x.clone(), dy.clone()), product(y.clone(), dx.clone())),
+                sum(power(x.clone(), int(2, span)), power(y.clone(), int(2, span)))
+            )),
+            // hypot(x, y)' = (x x' + y y') / hypot(x, y)
+            ("hypot", [x, y], [dx, dy]) => Ok(quotient(
+                sum(product(x.clone(), dx.clone()), product(y.clone(), dy.clone())),
+                self.clone_box()
+            )),
+            // The derivative of a sum is the sum of the derivatives,
+            // and that of a mean is their mean.
+            ("sum", _, [first, rest @ ..]) => Ok(rest.iter().cloned().fold(first.clone(), sum)),
+            ("avg", _, [first, rest @ ..]) => Ok(quotient(
+                rest.iter().cloned().fold(first.clone(), sum),
+                int(derivatives.len() as i64, span)
+            )),
+            ("min" | "max", [_], [du]) => Ok(du.clone()),
+            // The derivative of whichever argument is chosen, comparing
+            // the first with the min or max of the rest.
+            ("min" | "max", [first, rest @ ..], [d_first, ..]) => {
+                let rest: Box<dyn Node> = match rest {
+                    [second] => second.clone(),
+                    _ => Box::new(FunctionCall {
+                        name: self.name.clone(),
+                        arguments: rest.to_vec(),
+                        start: self.start,
+                        end: self.end
+                    })
+                };
+                let d_rest = rest.differentiate(var)?;
+                let op = if self.name == "min" { Op::LessEqual } else { Op::GreaterEqual };
+                Ok(Box::new(Conditional {
+                    condition: binop(first.clone(), op, rest),
+                    if_true: d_first.clone(),
+                    if_false: d_rest,
+                    start: self.start,
+                    end: self.end
+                }))
+            },
+            _ => Err(not_differentiable(self, var))
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+
+    fn eq_node(&self, other: &dyn Node) -> bool {
+        other.as_any().downcast_ref::<Self>().is_some_and(|other|
+            self.name == other.name
+                && self.arguments.len() == other.arguments.len()
+                && self.arguments.iter().zip(&other.arguments)
+                    .all(|(left, right)| left.eq_node(right.as_ref()))
         )
     }
+
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visitor.visit_function_call(self);
+        for argument in &self.arguments {
+            argument.accept(visitor);
+        }
+    }
+
+    fn children(&self) -> Vec<&dyn Node> {
+        self.arguments.iter().map(|argument| argument.as_ref()).collect()
+    }
+
+    fn detach_children(&mut self, children: &mut Vec<Box<dyn Node>>) {
+        children.append(&mut self.arguments);
+    }
+
+    fn span(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
-/// Represents a unary operation, meaning it's a mathematical
-/// operation with just a right side.
+impl Drop for FunctionCall {
+    fn drop(&mut self) {
+        drop_children(self);
+    }
+}
+
+/// Chooses between two expressions depending on a condition
 /// 
-/// The only meaningful operation is `-x` though `+x` is still
-/// valid syntax, despite it not doing anything.
-pub struct UnaryOp {
-    pub right: Box<dyn Node>,
-    pub op: Op
+/// e.g. `x > 0 ? x : -x`
+#[derive(Clone)]
+pub struct Conditional {
+    pub condition: Box<dyn Node>,
+    pub if_true: Box<dyn Node>,
+    pub if_false: Box<dyn Node>,
+    pub start: usize,
+    pub end: usize
 }
 
-impl Node for UnaryOp {
-    fn evaluate(&self) -> f32 {
-        match self.op {
-            Op::Add | Op::Mult | Op::Div 
-            => self.right.evaluate(),
-            Op::Sub => -self.right.evaluate()
+impl Conditional {
+    /// The branch taken for the value of the condition,
+    /// narrating the choice, see `Node::explain()`.
+    fn branch(&self, condition: Number, env: &Environment) -> &dyn Node {
+        let (truth, branch) = match condition != 0.0 {
+            true => ("true", &self.if_true),
+            false => ("false", &self.if_false)
+        };
+        env.narrate(|| format!("{} is {}, so take {}", condition, truth, branch.to_infix()));
+        branch.as_ref()
+    }
+}
+
+impl Node for Conditional {
+    // Only the branch which is taken is evaluated, so the other
+    // can safely contain something like a division by zero.
+    fn evaluate_with(&self, env: &Environment) -> Result<Number, EvalError> {
+        env.evaluate(self)
+    }
+
+    fn evaluate_step<'a>(&'a self, operands: &[Value], env: &Environment) -> Result<EvalStep<'a>, EvalError> {
+        let step = match operands {
+            [] => {
+                env.step()?;
+                EvalStep::Evaluate(self.condition.as_ref())
+            },
+            [condition] => EvalStep::Evaluate(self.branch((*condition).into(), env)),
+            [_, value, ..] => EvalStep::Done(*value)
+        };
+        Ok(step)
+    }
+
+    fn evaluate_exact_with(&self, env: &Environment) -> Result<Value, EvalError> {
+        env.evaluate_exact(self)
+    }
+
+    #[cfg(feature = "rational")]
+    fn evaluate_rational_with(&self, env: &Environment) -> Result<Rational, EvalError> {
+        env.step()?;
+        if !self.condition.evaluate_rational_with(env)?.is_zero() {
+            self.if_true.evaluate_rational_with(env)
+        } else {
+            self.if_false.evaluate_rational_with(env)
         }
     }
 
-    fn display(&self, depth: usize) -> String {
+    fn display_with(&self, depth: usize, opts: &DisplayOptions) -> String {
         format!(
-            "UnaryOp {{\n{1}right: {2}\n{1}op: {3:#?}\n{0}}}",
-            " ".repeat(depth*DISPLAY_INDENTATION),
-            " ".repeat((depth+1)*DISPLAY_INDENTATION),
-            self.right.display(depth + 1), self.op
+            "Conditional {{\n{1}condition: {2}\n{1}if_true: {3}\n{1}if_false: {4}\n{0}}}",
+            opts.indentation(depth),
+            opts.indentation(depth + 1),
+            self.condition.display_with(depth + 1, opts),
+            self.if_true.display_with(depth + 1, opts),
+            self.if_false.display_with(depth + 1, opts)
         )
     }
+
+    // Conditionals are right-associative, so only
+    // the condition needs brackets around another.
+    fn to_infix(&self) -> String {
+        format!(
+            "{} ? {} : {}",
+            infix_operand(self.condition.as_ref(), CONDITIONAL_PRECEDENCE + 1),
+            self.if_true.to_infix(),
+            self.if_false.to_infix()
+        )
+    }
+
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"type\":\"Conditional\",\"condition\":{},\"if_true\":{},\"if_false\":{}}}",
+            self.condition.to_json(), self.if_true.to_json(), self.if_false.to_json()
+        )
+    }
+
+    fn precedence(&self) -> u8 {
+        CONDITIONAL_PRECEDENCE
+    }
+
+    fn simplify(mut self: Box<Self>) -> Box<dyn Node> {
+        let condition = take(&mut self.condition).simplify();
+        let if_true = take(&mut self.if_true).simplify();
+        let if_false = take(&mut self.if_false).simplify();
+        // A constant condition always takes the same branch.
+        match constant_value(condition.as_ref()) {
+            Some(value) if value != 0.0 => if_true,
+            Some(_) => if_false,
+            None => Box::new(Conditional { condition, if_true, if_false, start: self.start, end: self.end })
+        }
+    }
+
+    fn canonicalize(&self) -> Box<dyn Node> {
+        Box::new(Conditional {
+            condition: self.condition.canonicalize(),
+            if_true: self.if_true.canonicalize(),
+            if_false: self.if_false.canonicalize(),
+            start: self.start,
+            end: self.end
+        })
+    }
+
+    fn differentiate(&self, var: &str) -> Result<Box<dyn Node>, EvalError> {
+        let if_true = self.if_true.differentiate(var)?;
+        let if_false = self.if_false.differentiate(var)?;
+        if is_zero(if_true.as_ref()) && is_zero(if_false.as_ref()) {
+            return Ok(int(0, self.span()));
+        }
+        Ok(Box::new(Conditional {
+            condition: self.condition.clone(),
+            if_true,
+            if_false,
+            start: self.start,
+            end: self.end
+        }))
+    }
+
+    fn clone_box(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+
+    fn eq_node(&self, other: &dyn Node) -> bool {
+        other.as_any().downcast_ref::<Self>().is_some_and(|other|
+            self.condition.eq_node(other.condition.as_ref())
+                && self.if_true.eq_node(other.if_true.as_ref())
+                && self.if_false.eq_node(other.if_false.as_ref())
+        )
+    }
+
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visitor.visit_conditional(self);
+        self.condition.accept(visitor);
+        self.if_true.accept(visitor);
+        self.if_false.accept(visitor);
+    }
+
+    fn children(&self) -> Vec<&dyn Node> {
+        vec![self.condition.as_ref(), self.if_true.as_ref(), self.if_false.as_ref()]
+    }
+
+    fn detach_children(&mut self, children: &mut Vec<Box<dyn Node>>) {
+        detach(&mut self.condition, children);
+        detach(&mut self.if_true, children);
+        detach(&mut self.if_false, children);
+    }
+
+    fn span(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
-/// Integer constants
+impl Drop for Conditional {
+    fn drop(&mut self) {
+        drop_children(self);
+    }
+}
+
+/// An interval such as `1..10`, which includes both of its bounds.
 /// 
-/// e.g. `3` or `100`
-pub struct IntLiteral {
-    pub value: String
+/// Ranges can only be the right hand side of `in`, which tests whether a
+/// number lies within them, giving 1 if it does and 0 if it doesn't. A
+/// range has no value of its own, so it can't be used in arithmetic,
+/// and `(1..10) + 1` is a `ParseError::MisplacedRange`. Its bounds bind
+/// more tightly than `in` and comparisons, so `x in 1..n + 1` is
+/// `x in 1..(n + 1)`, and may be any numbers, such as `0..0.5`.
+/// A range whose low bound is above its high bound is empty.
+/// 
+/// ```
+/// use simple_expression_parser::{evaluate, Parser, ParseError};
+/// 
+/// assert_eq!(evaluate("5 in 1..10"), Ok(1.0));
+/// assert_eq!(evaluate("1 in 1..10"), Ok(1.0));
+/// assert_eq!(evaluate("10 in 1..10"), Ok(1.0));
+/// assert_eq!(evaluate("11 in 1..10"), Ok(0.0));
+/// assert_eq!(evaluate("0.25 in 0..0.5"), Ok(1.0));
+/// assert_eq!(evaluate("2 + 3 in 1..2 * 2"), Ok(0.0));
+/// assert_eq!(evaluate("5 in 10..1"), Ok(0.0));
+/// assert_eq!(evaluate("5 in 1..10 and 5 in 6..10"), Ok(0.0));
+/// assert_eq!(evaluate("not 5 in 6..10"), Ok(1.0));
+/// 
+/// let error = |source: &str| Parser::new(source).parse().err();
+/// assert!(matches!(error("(1..10) + 1"), Some(ParseError::MisplacedRange { .. })));
+/// assert!(matches!(error("1..10"), Some(ParseError::MisplacedRange { .. })));
+/// assert!(matches!(error("x in 10"), Some(ParseError::UnexpectedEof { .. })));
+/// ```
+#[derive(Clone)]
+pub struct Range {
+    pub low: Box<dyn Node>,
+    pub high: Box<dyn Node>,
+    pub start: usize,
+    pub end: usize
 }
 
-impl Node for IntLiteral {
-    fn evaluate(&self) -> f32 {
-        // TODO: See comment on FloatLiteral::evaluate()
-        self.value.parse::<f32>().unwrap()
+impl Range {
+    /// Whether `value` lies within the range, including its bounds.
+    pub fn contains(&self, value: Number, env: &Environment) -> Result<bool, EvalError> {
+        let low = env.evaluate(self.low.as_ref())?;
+        let high = env.evaluate(self.high.as_ref())?;
+        Ok(within(env, value, low, high))
     }
 
-    fn display(&self, depth: usize) -> String {
+    /// Whether `value` lies within the range, comparing exactly.
+    #[cfg(feature = "rational")]
+    fn contains_rational(&self, value: &Rational, env: &Environment) -> Result<bool, EvalError> {
+        let low = self.low.evaluate_rational_with(env)?;
+        let high = self.high.evaluate_rational_with(env)?;
+        Ok(&low <= value && value <= &high)
+    }
+}
+
+impl Node for Range {
+    fn evaluate_with(&self, env: &Environment) -> Result<Number, EvalError> {
+        env.step()?;
+        Err(EvalError::RangeAsNumber { expression: self.to_infix() })
+    }
+
+    fn display_with(&self, depth: usize, opts: &DisplayOptions) -> String {
         format!(
-            "IntLiteral {{\n{1}value: {2}\n{0}}}",
-            " ".repeat(depth*DISPLAY_INDENTATION),
-            " ".repeat((depth+1)*DISPLAY_INDENTATION),
-            self.value
+            "Range {{\n{1}low: {2}\n{1}high: {3}\n{0}}}",
+            opts.indentation(depth),
+            opts.indentation(depth + 1),
+            self.low.display_with(depth + 1, opts),
+            self.high.display_with(depth + 1, opts)
+        )
+    }
+
+    // The bounds are parsed like the operands of a comparison.
+    fn to_infix(&self) -> String {
+        format!(
+            "{}..{}",
+            infix_operand(self.low.as_ref(), Op::In.precedence() + 1),
+            infix_operand(self.high.as_ref(), Op::In.precedence() + 1)
+        )
+    }
+
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"type\":\"Range\",\"low\":{},\"high\":{}}}",
+            self.low.to_json(), self.high.to_json()
+        )
+    }
+
+    fn simplify(mut self: Box<Self>) -> Box<dyn Node> {
+        let low = take(&mut self.low).simplify();
+        let high = take(&mut self.high).simplify();
+        Box::new(Range { low, high, start: self.start, end: self.end })
+    }
+
+    fn canonicalize(&self) -> Box<dyn Node> {
+        let low = self.low.canonicalize();
+        let high = self.high.canonicalize();
+        Box::new(Range { low, high, start: self.start, end: self.end })
+    }
+
+    fn differentiate(&self, var: &str) -> Result<Box<dyn Node>, EvalError> {
+        Err(not_differentiable(self, var))
+    }
+
+    fn clone_box(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+
+    fn eq_node(&self, other: &dyn Node) -> bool {
+        other.as_any().downcast_ref::<Self>().is_some_and(|other|
+            self.low.eq_node(other.low.as_ref())
+                && self.high.eq_node(other.high.as_ref())
         )
     }
+
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visitor.visit_range(self);
+        self.low.accept(visitor);
+        self.high.accept(visitor);
+    }
+
+    fn children(&self) -> Vec<&dyn Node> {
+        vec![self.low.as_ref(), self.high.as_ref()]
+    }
+
+    fn detach_children(&mut self, children: &mut Vec<Box<dyn Node>>) {
+        detach(&mut self.low, children);
+        detach(&mut self.high, children);
+    }
+
+    fn span(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
-/// Decimal constants
+impl Drop for Range {
+    fn drop(&mut self) {
+        drop_children(self);
+    }
+}
+
+/// Binds a variable to the value of an expression, such as `x = 5`
 /// 
-/// e.g. `3.14` or `1.234`
-pub struct FloatLiteral {
-    pub value: String
+/// Evaluating an assignment binds the variable in the environment it's
+/// evaluated with, even though the environment is only borrowed, and
+/// evaluates to the value assigned.
+/// 
+/// ```
+/// use simple_expression_parser::{Parser, Environment};
+/// 
+/// let env = Environment::new();
+/// let mut parser = Parser::new("x = 3".to_string());
+/// assert_eq!(parser.parse().unwrap().evaluate_with(&env), Ok(3.0));
+/// 
+/// parser.reset("x * 2");
+/// assert_eq!(parser.parse().unwrap().evaluate_with(&env), Ok(6.0));
+/// ```
+#[derive(Clone)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Box<dyn Node>,
+    pub start: usize,
+    pub end: usize
 }
 
-impl Node for FloatLiteral {
-    fn evaluate(&self) -> f32 {
-        // TODO: Although the tokensier should produce values which sucesfully
-        // parse everytime, it would still be good to do a check here rather
-        // than panicking if it fails.
-        self.value.parse::<f32>().unwrap()
+impl Node for Assignment {
+    fn evaluate_with(&self, env: &Environment) -> Result<Number, EvalError> {
+        env.evaluate(self)
     }
 
-    fn display(&self, depth: usize) -> String {
+    fn evaluate_step<'a>(&'a self, operands: &[Value], env: &Environment) -> Result<EvalStep<'a>, EvalError> {
+        match operands {
+            [] => {
+                env.step()?;
+                Ok(EvalStep::Evaluate(self.value.as_ref()))
+            },
+            [value, ..] => {
+                env.assign(&self.name, (*value).into());
+                env.narrate(|| format!("assign {} to {}", value, self.name));
+                Ok(EvalStep::Done(*value))
+            }
+        }
+    }
+
+    fn evaluate_exact_with(&self, env: &Environment) -> Result<Value, EvalError> {
+        env.evaluate_exact(self)
+    }
+
+    #[cfg(feature = "rational")]
+    fn evaluate_rational_with(&self, env: &Environment) -> Result<Rational, EvalError> {
+        env.step()?;
+        let value = self.value.evaluate_rational_with(env)?;
+        env.assign(&self.name, value.to_f64());
+        Ok(value)
+    }
+
+    fn display_with(&self, depth: usize, opts: &DisplayOptions) -> String {
         format!(
-            "FloatLiteral {{\n{1}value: {2}\n{0}}}",
-            " ".repeat(depth*DISPLAY_INDENTATION),
-            " ".repeat((depth+1)*DISPLAY_INDENTATION),
-            self.value
+            "Assignment {{\n{1}name: {2}\n{1}value: {3}\n{0}}}",
+            opts.indentation(depth),
+            opts.indentation(depth + 1),
+            self.name, self.value.display_with(depth + 1, opts)
+        )
+    }
+
+    fn to_infix(&self) -> String {
+        format!("{} = {}", self.name, self.value.to_infix())
+    }
+
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"type\":\"Assignment\",\"name\":{},\"value\":{}}}",
+            json_string(&self.name), self.value.to_json()
+        )
+    }
+
+    fn precedence(&self) -> u8 {
+        ASSIGNMENT_PRECEDENCE
+    }
+
+    fn simplify(mut self: Box<Self>) -> Box<dyn Node> {
+        let value = take(&mut self.value).simplify();
+        Box::new(Assignment { name: mem::take(&mut self.name), value, start: self.start, end: self.end })
+    }
+
+    fn canonicalize(&self) -> Box<dyn Node> {
+        let value = self.value.canonicalize();
+        Box::new(Assignment { name: self.name.clone(), value, start: self.start, end: self.end })
+    }
+
+    // The derivative is of the value being assigned,
+    // which isn't itself an assignment.
+    fn differentiate(&self, var: &str) -> Result<Box<dyn Node>, EvalError> {
+        self.value.differentiate(var)
+    }
+
+    fn clone_box(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+
+    fn eq_node(&self, other: &dyn Node) -> bool {
+        other.as_any().downcast_ref::<Self>().is_some_and(|other|
+            self.name == other.name
+                && self.value.eq_node(other.value.as_ref())
         )
     }
-}
\ No newline at end of file
+
+    fn accept(&self, visitor: &mut dyn Visitor) {
+        visitor.visit_assignment(self);
+        self.value.accept(visitor);
+    }
+
+    fn children(&self) -> Vec<&dyn Node> {
+        vec![self.value.as_ref()]
+    }
+
+    fn detach_children(&mut self, children: &mut Vec<Box<dyn Node>>) {
+        detach(&mut self.value, children);
+    }
+
+    fn span(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Drop for Assignment {
+    fn drop(&mut self) {
+        drop_children(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> Box<dyn Node> {
+        Parser::new(source).parse().unwrap()
+    }
+
+    fn evaluate(source: &str) -> Result<Number, EvalError> {
+        parse(source).evaluate()
+    }
+
+    #[test]
+    fn remainder_takes_the_sign_of_the_left_hand_side() {
+        assert_eq!(evaluate("10 % 3"), Ok(1.0));
+        assert_eq!(evaluate("-7 % 3"), Ok(-1.0));
+        assert_eq!(evaluate("(-7) % 3"), Ok(-1.0));
+        assert_eq!(evaluate("7 % -3"), Ok(1.0));
+        assert_eq!(evaluate("-7 % -3"), Ok(-1.0));
+        assert_eq!(evaluate("-7.5 % 2"), Ok(-1.5));
+    }
+
+    #[test]
+    fn exact_remainder_takes_the_sign_of_the_left_hand_side() {
+        assert_eq!(parse("-7 % 3").evaluate_exact(), Ok(Value::Int(-1)));
+        assert_eq!(parse("7 % -3").evaluate_exact(), Ok(Value::Int(1)));
+    }
+
+    #[test]
+    fn infix_parses_back_into_an_equal_tree() {
+        for source in [
+            "(1 + 2) * 3", "1 + 2 * 3", "1 - (2 - 3)", "(1 - 2) - 3", "2 / (3 * 4)",
+            "(2 ^ 3) ^ 2", "2 ^ 3 ^ 2", "-(1 + 2)", "-2 ^ 2", "(-2) ^ 2", "--1.5"
+        ] {
+            let tree = parse(source);
+            let infix = tree.to_infix();
+            assert!(parse(&infix).eq_node(tree.as_ref()), "{} became {}", source, infix);
+        }
+    }
+
+    #[test]
+    fn infix_only_keeps_the_brackets_which_change_the_meaning() {
+        assert_eq!(parse("(1 + 2) * 3").to_infix(), "(1 + 2) * 3");
+        assert_eq!(parse("1 + (2 * 3)").to_infix(), "1 + 2 * 3");
+        assert_eq!(parse("(1 - 2) - 3").to_infix(), "1 - 2 - 3");
+        assert_eq!(parse("1 - (2 - 3)").to_infix(), "1 - (2 - 3)");
+    }
+
+    #[test]
+    fn trees_are_compared_by_structure() {
+        assert!(parse("1+2").eq_node(parse("1+2").as_ref()));
+        assert!(parse("1+2").eq_node(parse("1 + 2").as_ref()));
+        assert!(!parse("1+2").eq_node(parse("2+1").as_ref()));
+        assert!(!parse("1+2").eq_node(parse("1-2").as_ref()));
+        assert!(!parse("1+2").eq_node(parse("1+2.5").as_ref()));
+    }
+
+    #[test]
+    fn json_names_each_node_and_its_fields() {
+        assert_eq!(
+            parse("1 + 2.5 * x").to_json(),
+            concat!(
+                r#"{"type":"BinOp","op":"Add","left":{"type":"IntLiteral","value":1},"#,
+                r#""right":{"type":"BinOp","op":"Mult","left":{"type":"FloatLiteral","value":2.5},"#,
+                r#""right":{"type":"Variable","name":"x"}}}"#
+            )
+        );
+    }
+
+    #[test]
+    fn division_by_zero_names_the_operator() {
+        let at = |index| EvalError::DivByZero { pos: Position { index, line: 1, column: index + 1 } };
+        assert_eq!(evaluate("1/0"), Err(at(1)));
+        assert_eq!(evaluate("1/(2-2)"), Err(at(1)));
+        assert_eq!(evaluate("2 + 1 / (2 - 2)"), Err(at(6)));
+        assert_eq!(evaluate("1/0.0001"), Ok(10000.0));
+    }
+
+    #[test]
+    fn only_the_chosen_branch_is_evaluated() {
+        assert_eq!(evaluate("1 > 0 ? 2 : 1/0"), Ok(2.0));
+        assert_eq!(evaluate("1 < 0 ? 1/0 : 3"), Ok(3.0));
+        assert!(matches!(evaluate("1 < 0 ? 2 : 1/0"), Err(EvalError::DivByZero { .. })));
+        assert_eq!(parse("1 > 0 ? 2 : 1/0").evaluate_exact(), Ok(Value::Int(2)));
+
+        let tree = parse("x > 0 ? x : -x");
+        let mut env = Environment::new();
+        env.insert("x", -4.0);
+        assert_eq!(tree.evaluate_with(&env), Ok(4.0));
+        env.insert("x", 5.0);
+        assert_eq!(tree.evaluate_with(&env), Ok(5.0));
+    }
+
+    #[test]
+    fn bitwise_operators_require_integers() {
+        assert_eq!(evaluate("1.5 & 1"), Err(EvalError::TypeMismatch {
+            op: Op::BitAnd,
+            left_kind: ValueKind::Float,
+            right_kind: ValueKind::Int
+        }));
+        // The result of a comparison is a number like any other.
+        assert_eq!(evaluate("(1 > 2) * 3"), Ok(0.0));
+        assert_eq!(evaluate("(1 < 2) + 1"), Ok(2.0));
+    }
+
+    #[test]
+    fn factorials_too_large_for_a_float_overflow() {
+        assert!(evaluate("50!").is_ok_and(|value| value.is_finite()));
+        assert_eq!(evaluate("171!"), Err(EvalError::Overflow { expression: "171!".to_string() }));
+    }
+}
@@ -0,0 +1,529 @@
+use core::fmt;
+use alloc::{
+    format,
+    string::String,
+    vec::Vec
+};
+
+use crate::{
+    tokeniser::{TokenKind, Position},
+    ast::{Number, Op, ValueKind}
+};
+
+/// Every way in which tokenising or parsing an expression can fail.
+///
+/// Each variant carries the position (line and column, counted
+/// in chars) of the offending input, so callers can both `match` on the cause
+/// and print a friendly message via `Display`.
+///
+/// Errors found while reading a literal or an unrecognised char also carry
+/// a `context`, a snippet of the surrounding source with a caret under the
+/// offending char, see `tokeniser::context()`. It's shown on the lines after
+/// the message, and is empty if the source was read from a reader.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// The tokeniser found a character which can't begin any token.
+    UnrecognisedChar {
+        char: char,
+        pos: Position,
+        context: String
+    },
+    /// A block comment was opened with `/*` but the source ended before
+    /// it was closed with `*/`. The position is that of the opening `/*`.
+    ///
+    /// e.g. `1 + /* note`
+    UnterminatedComment {
+        pos: Position,
+        context: String
+    },
+    /// The source couldn't be read, or wasn't valid UTF-8.
+    ReadFailed {
+        message: String,
+        pos: Position
+    },
+    /// A number was followed by a decimal point, but no decimal digits.
+    ///
+    /// e.g. `3.`
+    UnfinishedFloat {
+        value: String,
+        pos: Position,
+        context: String
+    },
+    /// A hexadecimal float was followed by an exponent marker,
+    /// but no exponent digits.
+    ///
+    /// e.g. `0x1p` or `0x1p+`
+    UnfinishedExponent {
+        value: String,
+        pos: Position,
+        context: String
+    },
+    /// A number had a second decimal point, or one after its exponent.
+    ///
+    /// e.g. `1.2.3` or `1e5.5`
+    MalformedNumber {
+        value: String,
+        pos: Position,
+        context: String
+    },
+    /// An integer part had a leading zero, which in some languages
+    /// marks an octal literal, so it's rejected as ambiguous.
+    ///
+    /// e.g. `010` or `00.5`
+    LeadingZero {
+        value: String,
+        pos: Position,
+        context: String
+    },
+    /// An underscore in a number wasn't placed between two digits.
+    ///
+    /// e.g. `_10`, `10_` or `1__0`
+    MisplacedDigitSeparator {
+        value: String,
+        pos: Position,
+        context: String
+    },
+    /// A radix prefix wasn't followed by any digits.
+    ///
+    /// e.g. `0x`
+    UnfinishedRadixLiteral {
+        value: String,
+        pos: Position,
+        context: String
+    },
+    /// A digit isn't valid in the radix of its literal.
+    ///
+    /// e.g. the `G` in `0xG1`
+    InvalidDigit {
+        digit: char,
+        radix: u32,
+        value: String,
+        pos: Position,
+        context: String
+    },
+    /// A literal's value is too large to be represented as a float.
+    /// 
+    /// e.g. `1e400`
+    LiteralOverflow {
+        value: String,
+        pos: Position,
+        context: String
+    },
+    /// A '=' was found anywhere other than after the variable at the
+    /// start of an assignment, so it's most likely a mistyped '=='.
+    ///
+    /// e.g. `1 = 1`
+    LoneEquals {
+        pos: Position
+    },
+    /// An assignment was made to one of the constants, which
+    /// can't be changed.
+    ///
+    /// e.g. `pi = 3`
+    AssignToConstant {
+        name: String,
+        pos: Position
+    },
+    /// An opening bracket was never closed before the end of the input.
+    ///
+    /// e.g. `(1 + 2`
+    UnclosedParenthesis {
+        opened: Position,
+        pos: Position
+    },
+    /// An opening bracket was closed by a different type of bracket.
+    /// 
+    /// e.g. `(1 + 2]`
+    MismatchedBracket {
+        opening: char,
+        closing: char,
+        opened: Position,
+        pos: Position
+    },
+    /// A range was found anywhere other than after `in`, as
+    /// ranges have no value of their own, see `ast::Range`.
+    ///
+    /// e.g. `(1..10) + 1`
+    MisplacedRange {
+        pos: Position
+    },
+    /// A closing bracket was found with no opening bracket to match it.
+    ///
+    /// e.g. `1 + 2)`
+    UnmatchedParenthesis {
+        pos: Position
+    },
+    /// The source was longer than the parser's maximum input length,
+    /// see `Parser::set_max_input_length()`.
+    InputTooLong {
+        max_length: usize
+    },
+    /// The source had no expression in it, being empty or only
+    /// whitespace and comments. This is only an error for
+    /// `Parser::parse()`, as a program may have no statements.
+    EmptyInput,
+    /// The expression was nested more deeply than the parser's maximum
+    /// depth, see `Parser::set_max_depth()`.
+    /// 
+    /// e.g. thousands of brackets, as in `((((...))))`
+    TooDeeplyNested {
+        max_depth: usize,
+        pos: Position
+    },
+    /// A complete expression was followed by more tokens.
+    ///
+    /// e.g. the `3` in `1 + 2 3`
+    TrailingToken {
+        kind: TokenKind,
+        value: Option<String>,
+        pos: Position
+    },
+    /// The parser required a specific kind of token, but found another.
+    ///
+    /// e.g. a missing closing bracket in `(1 + 2`
+    ExpectedToken {
+        expected: TokenKind,
+        found: TokenKind,
+        pos: Position
+    },
+    /// Two arithmetic operators were written one after the other,
+    /// which is most likely a typo for one of `suggestions`.
+    /// 
+    /// e.g. the `*/` in `1 */ 2` or the `//` in `1 // 2`
+    MistypedOperator {
+        found: String,
+        suggestions: Vec<String>,
+        pos: Position
+    },
+    /// The parser found a token which isn't valid at this point
+    /// in the expression.
+    ///
+    /// e.g. the second `*` in `1 * * 2`
+    UnexpectedToken {
+        kind: TokenKind,
+        value: Option<String>,
+        pos: Position
+    },
+    /// The input ended where more of the expression was expected, such
+    /// as an operand after an operator. `expected` describes what was
+    /// expected, e.g. `an operand` or `':'`.
+    /// 
+    /// Unlike most errors this can be recovered from by reading more
+    /// input, see `is_incomplete()`.
+    /// 
+    /// e.g. the end of `1 +`
+    UnexpectedEof {
+        expected: String,
+        pos: Position
+    },
+    /// An S-expression read by `from_sexpr()` was well bracketed, but
+    /// isn't an expression. `reason` says what's wrong with it.
+    ///
+    /// e.g. the empty list in `(+ 1 ())`, or the third operand in `(* 1 2 3)`
+    MalformedSexpr {
+        reason: String,
+        pos: Position
+    }
+}
+
+impl ParseError {
+    /// Whether the error is only because the source ended too soon, such
+    /// as after an operator or inside brackets, so that more input could
+    /// still complete the expression.
+    ///
+    /// ```
+    /// use simple_expression_parser::Parser;
+    ///
+    /// let incomplete = |source: &str| Parser::new(source.to_string()).parse().err().is_some_and(|err| err.is_incomplete());
+    /// assert!(incomplete("(1 +"));
+    /// assert!(incomplete("max(1, 2"));
+    /// assert!(incomplete("x > 0 ? 1"));
+    /// assert!(!incomplete("1 + * 2"));
+    /// assert!(!incomplete("1 )"));
+    /// ```
+    pub fn is_incomplete(&self) -> bool {
+        matches!(
+            self,
+            ParseError::UnclosedParenthesis { .. }
+                | ParseError::UnexpectedEof { .. }
+                | ParseError::UnterminatedComment { .. }
+        )
+    }
+
+    /// The snippet of source around the error, if it has one.
+    pub fn context(&self) -> Option<&str> {
+        match self {
+            ParseError::UnrecognisedChar { context, .. }
+            | ParseError::UnterminatedComment { context, .. }
+            | ParseError::UnfinishedFloat { context, .. }
+            | ParseError::UnfinishedExponent { context, .. }
+            | ParseError::MalformedNumber { context, .. }
+            | ParseError::LeadingZero { context, .. }
+            | ParseError::MisplacedDigitSeparator { context, .. }
+            | ParseError::UnfinishedRadixLiteral { context, .. }
+            | ParseError::InvalidDigit { context, .. }
+            | ParseError::LiteralOverflow { context, .. } => {
+                Some(context).filter(|context| !context.is_empty()).map(String::as_str)
+            },
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnrecognisedChar { char, pos, .. } => {
+                write!(f, "Unrecognised char '{}' at {}", char, pos)
+            },
+            ParseError::UnterminatedComment { pos, .. } => {
+                write!(f, "The comment opened at {} is never closed with '*/'", pos)
+            },
+            ParseError::ReadFailed { message, pos } => {
+                write!(f, "Failed to read the source at {}: {}", pos, message)
+            },
+            ParseError::UnfinishedFloat { value, pos, .. } => {
+                write!(f, "Unfinished FloatLiteral '{}' at {}", value, pos)
+            },
+            ParseError::UnfinishedExponent { value, pos, .. } => {
+                write!(f, "Unfinished exponent in FloatLiteral '{}' at {}", value, pos)
+            },
+            ParseError::MalformedNumber { value, pos, .. } => {
+                write!(f, "Malformed number literal '{}' at {}", value, pos)
+            },
+            ParseError::LeadingZero { value, pos, .. } => {
+                write!(f, "Leading zero in '{}' at {}, write '0o' before an octal literal", value, pos)
+            },
+            ParseError::MisplacedDigitSeparator { value, pos, .. } => {
+                write!(f, "Digit separator '_' must be between two digits in '{}' at {}", value, pos)
+            },
+            ParseError::UnfinishedRadixLiteral { value, pos, .. } => {
+                write!(f, "Expected digits after the prefix of '{}' at {}", value, pos)
+            },
+            ParseError::InvalidDigit { digit, radix, value, pos, .. } => {
+                write!(f, "Invalid digit '{}' for a base {} literal '{}' at {}", digit, radix, value, pos)
+            },
+            ParseError::LiteralOverflow { value, pos, .. } => {
+                write!(f, "Literal '{}' is too large at {}", value, pos)
+            },
+            ParseError::LoneEquals { pos } => {
+                write!(f, "Unexpected '=' at {}, did you mean '=='?", pos)
+            },
+            ParseError::AssignToConstant { name, pos } => {
+                write!(f, "Can't assign to the constant '{}' at {}", name, pos)
+            },
+            ParseError::UnclosedParenthesis { opened, .. } => {
+                write!(f, "Unclosed parenthesis opened at {}", opened)
+            },
+            ParseError::MismatchedBracket { opening, closing, opened, pos } => {
+                write!(f, "Mismatched '{}' at {} for the '{}' opened at {}", closing, pos, opening, opened)
+            },
+            ParseError::MisplacedRange { pos } => {
+                write!(f, "A range can only follow 'in', as in 'x in 1..10', but one was found at {}", pos)
+            },
+            ParseError::UnmatchedParenthesis { pos } => {
+                write!(f, "Unmatched closing parenthesis at {}", pos)
+            },
+            ParseError::InputTooLong { max_length } => {
+                write!(f, "Input is longer than the maximum of {} chars", max_length)
+            },
+            ParseError::EmptyInput => {
+                write!(f, "Input is empty, there's no expression to parse")
+            },
+            ParseError::TooDeeplyNested { max_depth, pos } => {
+                write!(f, "Expression is nested more than {} levels deep at {}", max_depth, pos)
+            },
+            ParseError::TrailingToken { value: Some(value), pos, .. } => {
+                write!(f, "Unexpected trailing token '{}' at {}", value, pos)
+            },
+            ParseError::TrailingToken { kind, value: None, pos } => {
+                write!(f, "Unexpected trailing token {:?} at {}", kind, pos)
+            },
+            ParseError::ExpectedToken { expected, found, pos } => {
+                write!(f, "Expected kind {:?}, got kind {:?} at {}", expected, found, pos)
+            },
+            ParseError::MistypedOperator { found, suggestions, pos } => {
+                let suggestions: Vec<String> = suggestions.iter()
+                    .map(|suggestion| format!("'{}'", suggestion))
+                    .collect();
+                write!(f, "Unexpected '{}' at {}, did you mean {}?", found, pos, suggestions.join(" or "))
+            },
+            ParseError::UnexpectedToken { kind, value: Some(value), pos } => {
+                write!(f, "Unexpected token {:?} '{}' at {}", kind, value, pos)
+            },
+            ParseError::UnexpectedToken { kind, value: None, pos } => {
+                write!(f, "Unexpected token {:?} at {}", kind, pos)
+            },
+            ParseError::UnexpectedEof { expected, pos } => {
+                write!(f, "Expected {}, but the input ended at {}", expected, pos)
+            },
+            ParseError::MalformedSexpr { reason, pos } => {
+                write!(f, "Malformed S-expression at {}: {}", pos, reason)
+            }
+        }?;
+        match self.context() {
+            Some(context) => write!(f, "\n{}", context),
+            None => Ok(())
+        }
+    }
+}
+
+impl core::error::Error for ParseError {}
+
+/// Every way in which evaluating a syntax tree can fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    /// The right hand side of a '/' or '%' evaluated to zero.
+    /// 
+    /// `pos` is the position of the operator.
+    DivByZero {
+        pos: Position
+    },
+    /// A function call named a function which doesn't exist.
+    UnknownFunction {
+        name: String
+    },
+    /// A function was called with the wrong number of arguments.
+    /// 
+    /// `max` is `None` for functions which take any number of
+    /// arguments above `min`.
+    WrongArgumentCount {
+        name: String,
+        min: usize,
+        max: Option<usize>,
+        found: usize
+    },
+    /// More nodes were evaluated than the environment's step limit,
+    /// see `Environment::set_step_limit()`.
+    StepLimitExceeded {
+        limit: usize
+    },
+    /// An expression depending on `var` has no derivative, such
+    /// as `x!`, `x & 1` or a call to a custom function.
+    NotDifferentiable {
+        expression: String,
+        var: String
+    },
+    /// `operation`, such as `sin()` or a fractional power, needs the
+    /// standard library, which isn't available without the `std` feature.
+    RequiresStd {
+        operation: String
+    },
+    /// A custom function returned an error.
+    FunctionFailed {
+        name: String,
+        message: String
+    },
+    /// A range was evaluated as a number. Ranges have no value of their
+    /// own, and the parser only produces them after `in`, so this only
+    /// happens for a tree which was built by hand.
+    RangeAsNumber {
+        expression: String
+    },
+    /// The right hand side of `in` wasn't a range, which again
+    /// only happens for a tree which was built by hand.
+    ExpectedRange {
+        expression: String
+    },
+    /// A variable was referenced which isn't bound in the environment.
+    UnboundVariable {
+        name: String
+    },
+    /// `op` isn't defined for the kinds of its operands. Only the
+    /// bitwise operators are restricted, to integers, while the result
+    /// of a comparison is a number which any other operator accepts.
+    TypeMismatch {
+        op: Op,
+        left_kind: ValueKind,
+        right_kind: ValueKind
+    },
+    /// A shift amount was negative or at least 64.
+    InvalidShift {
+        amount: Number
+    },
+    /// The bounds passed to `randint()` weren't integers, or
+    /// the lower bound was greater than the upper bound.
+    InvalidRange {
+        low: Number,
+        high: Number
+    },
+    /// The factorial of a negative or non-integer number was taken.
+    InvalidFactorial {
+        value: Number
+    },
+    /// The result of `expression` was too large to be represented.
+    Overflow {
+        expression: String
+    },
+    /// The value of `expression` isn't a fraction, such as `sqrt(2)` or
+    /// `pi`, so it can't be evaluated by `Node::evaluate_rational_with()`.
+    Inexact {
+        expression: String
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::DivByZero { pos } => {
+                write!(f, "Division by zero at {}", pos)
+            },
+            EvalError::UnknownFunction { name } => {
+                write!(f, "Unknown function '{}'", name)
+            },
+            EvalError::WrongArgumentCount { name, min, max: Some(max), found } if min == max => {
+                write!(f, "Function '{}' takes {} argument(s), but {} were given", name, min, found)
+            },
+            EvalError::WrongArgumentCount { name, min, max: Some(max), found } => {
+                write!(f, "Function '{}' takes {} to {} arguments, but {} were given", name, min, max, found)
+            },
+            EvalError::WrongArgumentCount { name, min, max: None, found } => {
+                write!(f, "Function '{}' takes at least {} argument(s), but {} were given", name, min, found)
+            },
+            EvalError::StepLimitExceeded { limit } => {
+                write!(f, "Evaluation took more than the limit of {} steps", limit)
+            },
+            EvalError::NotDifferentiable { expression, var } => {
+                write!(f, "Can't differentiate '{}' with respect to '{}'", expression, var)
+            },
+            EvalError::RequiresStd { operation } => {
+                write!(f, "'{}' requires the std feature", operation)
+            },
+            EvalError::RangeAsNumber { expression } => {
+                write!(f, "The range '{}' has no value, it can only follow 'in'", expression)
+            },
+            EvalError::ExpectedRange { expression } => {
+                write!(f, "'in' must be followed by a range such as '1..10', not '{}'", expression)
+            },
+            EvalError::FunctionFailed { name, message } => {
+                write!(f, "Function '{}' failed: {}", name, message)
+            },
+            EvalError::UnboundVariable { name } => {
+                write!(f, "Unbound variable '{}'", name)
+            },
+            EvalError::TypeMismatch { op, left_kind, right_kind } => {
+                write!(
+                    f, "'{}' is only defined for 64-bit integers, not {:?} and {:?}",
+                    op.symbol(), left_kind, right_kind
+                )
+            },
+            EvalError::InvalidShift { amount } => {
+                write!(f, "Shift amount must be an integer from 0 to 63, not {}", amount)
+            },
+            EvalError::InvalidRange { low, high } => {
+                write!(f, "randint() takes integer bounds with the lower bound first, not {} and {}", low, high)
+            },
+            EvalError::InvalidFactorial { value } => {
+                write!(f, "Factorial is only defined for non-negative integers, not {}", value)
+            },
+            EvalError::Overflow { expression } => {
+                write!(f, "The result of '{}' is too large to represent", expression)
+            },
+            EvalError::Inexact { expression } => {
+                write!(f, "'{}' has no exact value as a fraction", expression)
+            }
+        }
+    }
+}
+
+impl core::error::Error for EvalError {}
@@ -0,0 +1,169 @@
+//! Formatting the results of evaluation as text.
+
+use alloc::{
+    format,
+    string::{String, ToString}
+};
+
+use crate::ast::Number;
+use crate::math;
+
+/// The notation used by `format_result()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberFormat {
+    /// Scientific notation for values of at least `1e9` or below `1e-4`,
+    /// otherwise fixed notation, as on a calculator.
+    Auto,
+    /// Always scientific notation, such as `1.5e9`.
+    Scientific,
+    /// Always fixed notation, such as `1500000000`.
+    #[default]
+    Fixed
+}
+
+/// Formats `value` in the notation given by `mode`, using as few digits
+/// as are needed to read the same value back in.
+///
+/// Infinities and NaN are written as `inf`, `-inf` and `NaN` in every mode.
+///
+/// ```
+/// use simple_expression_parser::{format_result, NumberFormat};
+///
+/// assert_eq!(format_result(1.5e9, NumberFormat::Auto), "1.5e9");
+/// assert_eq!(format_result(0.00002, NumberFormat::Auto), "2e-5");
+/// assert_eq!(format_result(123.25, NumberFormat::Auto), "123.25");
+/// assert_eq!(format_result(0.0, NumberFormat::Auto), "0");
+///
+/// assert_eq!(format_result(1.5e9, NumberFormat::Scientific), "1.5e9");
+/// assert_eq!(format_result(0.00002, NumberFormat::Scientific), "2e-5");
+/// assert_eq!(format_result(0.0, NumberFormat::Scientific), "0e0");
+///
+/// assert_eq!(format_result(1.5e9, NumberFormat::Fixed), "1500000000");
+/// assert_eq!(format_result(0.00002, NumberFormat::Fixed), "0.00002");
+/// assert_eq!(format_result(0.0, NumberFormat::Fixed), "0");
+///
+/// assert_eq!(format_result(f64::NEG_INFINITY, NumberFormat::Scientific), "-inf");
+/// ```
+pub fn format_result(value: Number, mode: NumberFormat) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    let scientific = match mode {
+        NumberFormat::Auto => value != 0.0 && !(1e-4..1e9).contains(&value.abs()),
+        NumberFormat::Scientific => true,
+        NumberFormat::Fixed => false
+    };
+    if scientific {
+        format!("{:e}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// The fraction `numerator / denominator` in lowest terms which is equal to
+/// `value`, with a positive denominator of at most `max_denominator`, or
+/// `None` if there isn't one.
+///
+/// The fraction is found from the continued fraction of `value`, so it's
+/// the one with the smallest denominator. As floats can't hold most
+/// fractions exactly, a fraction is taken to be equal when it's within the
+/// rounding error of a few operations, so `1.0 / 3.0` and `2.0 / 3.0 + 1.0 / 6.0`
+/// are `1/3` and `5/6`. An irrational value, or one too close to irrational
+/// for its denominator to be small, has no fraction.
+///
+/// ```
+/// use simple_expression_parser::{evaluate, to_fraction};
+///
+/// assert_eq!(to_fraction(0.5, 100), Some((1, 2)));
+/// assert_eq!(to_fraction(0.25, 100), Some((1, 4)));
+/// assert_eq!(to_fraction(1.0 / 3.0, 100), Some((1, 3)));
+/// assert_eq!(to_fraction(2.0 / 3.0 + 1.0 / 6.0, 100), Some((5, 6)));
+/// assert_eq!(to_fraction(-0.75, 100), Some((-3, 4)));
+/// assert_eq!(to_fraction(7.0, 100), Some((7, 1)));
+/// assert_eq!(to_fraction(0.0, 100), Some((0, 1)));
+///
+/// // A different float, which is only 1/3 to three decimal places
+/// assert_eq!(to_fraction(0.333, 100), None);
+/// assert_eq!(to_fraction(0.333, 1000), Some((333, 1000)));
+///
+/// assert_eq!(to_fraction(evaluate("sqrt(2)").unwrap(), 1_000_000), None);
+/// assert_eq!(to_fraction(std::f64::consts::PI, 1_000_000), None);
+/// assert_eq!(to_fraction(f64::NAN, 100), None);
+/// ```
+pub fn to_fraction(value: Number, max_denominator: u32) -> Option<(i64, i64)> {
+    if !value.is_finite() {
+        return None;
+    }
+    let max_denominator = i64::from(max_denominator);
+    let tolerance = value.abs() * 4.0 * Number::EPSILON;
+
+    // Each convergent h/k of the continued fraction [a0; a1, a2, ...]
+    // is found from the two before it, as h = a h' + h'' and likewise
+    // for k, until one is the value itself.
+    let (mut h, mut h_previous) = (1i64, 0i64);
+    let (mut k, mut k_previous) = (0i64, 1i64);
+    let mut remainder = value.abs();
+    loop {
+        let term = math::floor(remainder);
+        if term > i64::MAX as Number {
+            return None;
+        }
+        let term = term as i64;
+        let next_h = term.checked_mul(h)?.checked_add(h_previous)?;
+        let next_k = term.checked_mul(k)?.checked_add(k_previous)?;
+        if next_k > max_denominator {
+            return None;
+        }
+        (h, h_previous) = (next_h, h);
+        (k, k_previous) = (next_k, k);
+
+        if (h as Number / k as Number - value.abs()).abs() <= tolerance {
+            return Some((if value < 0.0 { -h } else { h }, k));
+        }
+        let fraction = remainder - term as Number;
+        if fraction == 0.0 {
+            return None;
+        }
+        remainder = 1.0 / fraction;
+    }
+}
+
+/// Groups the digits of the integer part of a formatted number into
+/// thousands with `separator`, such as `1,000,000` for `1000000` with
+/// a comma. Any fractional part or exponent is left ungrouped.
+///
+/// This is only for display, as the parser doesn't read grouped digits
+/// back in; use `_` to separate digits in an expression instead.
+///
+/// ```
+/// use simple_expression_parser::{format_result, group_digits, NumberFormat};
+///
+/// assert_eq!(group_digits("7", ','), "7");
+/// assert_eq!(group_digits("999", ','), "999");
+/// assert_eq!(group_digits("1000", ','), "1,000");
+/// assert_eq!(group_digits("-1234567", ','), "-1,234,567");
+/// assert_eq!(group_digits("1234567.891011", ','), "1,234,567.891011");
+/// assert_eq!(group_digits("1234.5e10", ' '), "1 234.5e10");
+/// assert_eq!(group_digits("inf", ','), "inf");
+///
+/// let answer = format_result(2f64.powi(40), NumberFormat::Fixed);
+/// assert_eq!(group_digits(&answer, ','), "1,099,511,627,776");
+/// ```
+pub fn group_digits(number: &str, separator: char) -> String {
+    let (sign, unsigned) = match number.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", number)
+    };
+    let digits = unsigned.find(|c: char| !c.is_ascii_digit()).unwrap_or(unsigned.len());
+    let (integer, rest) = unsigned.split_at(digits);
+
+    let mut grouped = String::from(sign);
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped.push_str(rest);
+    grouped
+}
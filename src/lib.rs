@@ -0,0 +1,139 @@
+//! A recursive descent parser and evaluator for simple mathematical
+//! expressions.
+//!
+//! For one-off calculations use [`evaluate`]. To keep a parsed expression
+//! around, parse it into an [`Expression`]. To get the syntax tree of a
+//! single expression use [`parse_str`], or to parse many expressions in a
+//! row, construct a [`Parser`].
+//!
+//! The crate only needs `alloc`, so it can be used in `no_std` environments
+//! by disabling the default `std` feature. Without it, the parser can't read
+//! its source from a `Read`, and the built-in functions and powers which need
+//! the standard library's float functions, such as `sin()` or `2^0.5`, fail
+//! with `EvalError::RequiresStd`. Those functions can be defined with
+//! `Environment::define_function()` instead. The REPL also requires `std`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use core::{fmt, str::FromStr};
+use alloc::{
+    boxed::Box,
+    string::{String, ToString}
+};
+
+pub mod tokeniser;
+pub mod parser;
+pub mod ast;
+pub mod error;
+pub mod format;
+pub mod sexpr;
+#[cfg(feature = "rational")]
+pub mod rational;
+mod math;
+
+pub use parser::{Parser, Lint};
+pub use ast::{
+    Node, Visitor, FoldItem, PostfixItem, DisplayOptions, Number, Environment, EvalStep, DivisionMode, EvalStats, CustomFunction, Value, ValueKind, Op, Associativity, UnaryKind, BinOp, UnaryOp, PostfixOp, IntLiteral, FloatLiteral,
+    Constant, Variable, FunctionCall, Conditional, Range, Assignment, ComparisonChain
+};
+pub use error::{ParseError, EvalError};
+pub use sexpr::from_sexpr;
+pub use format::{format_result, group_digits, to_fraction, NumberFormat};
+#[cfg(feature = "rational")]
+pub use rational::Rational;
+pub use tokeniser::{Tokeniser, Token, TokenKind, Position, LocaleOptions, DecimalSeparator};
+
+/// Parses and evaluates an expression in one call.
+///
+/// Any parsing or evaluation failure is returned as its display string.
+///
+/// ```
+/// use simple_expression_parser::evaluate;
+///
+/// assert_eq!(evaluate("1 + 2 * 3"), Ok(7.0));
+/// assert_eq!(evaluate("1 +"), Err("Expected an operand, but the input ended at line 1, column 4".to_string()));
+/// ```
+pub fn evaluate(source: &str) -> Result<Number, String> {
+    let tree = parse_str(source).map_err(|err| err.to_string())?;
+    tree.evaluate().map_err(|err| err.to_string())
+}
+
+/// Parses an expression into its syntax tree in one call.
+///
+/// The parser's state is created and dropped within the call, so unlike a
+/// shared `Parser`, this can be called from any number of threads at once.
+///
+/// ```
+/// use simple_expression_parser::parse_str;
+///
+/// let handles: Vec<_> = ["1 + 2", "3 * 4", "2^10"].into_iter()
+///     .map(|source| std::thread::spawn(move || parse_str(source).unwrap().evaluate()))
+///     .collect();
+/// let results: Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+/// assert_eq!(results, [Ok(3.0), Ok(12.0), Ok(1024.0)]);
+/// ```
+pub fn parse_str(source: &str) -> Result<Box<dyn Node>, ParseError> {
+    Parser::new(source).parse()
+}
+
+/// A parsed expression, which owns its syntax tree.
+///
+/// An expression can be parsed with `str::parse()`, and displays
+/// as infix which parses back into the same expression.
+///
+/// ```
+/// use simple_expression_parser::{Expression, ParseError};
+///
+/// let expr: Expression = "1 + 2".parse()?;
+/// assert_eq!(expr.evaluate(), Ok(3.0));
+/// assert_eq!(expr.to_string(), "1 + 2");
+/// # Ok::<(), ParseError>(())
+/// ```
+#[derive(Clone)]
+pub struct Expression {
+    root: Box<dyn Node>
+}
+
+impl Expression {
+    /// Evaluates the expression without any variables bound.
+    pub fn evaluate(&self) -> Result<Number, EvalError> {
+        self.root.evaluate()
+    }
+
+    /// Evaluates the expression, looking up variables in `env`.
+    pub fn evaluate_with(&self, env: &Environment) -> Result<Number, EvalError> {
+        self.root.evaluate_with(env)
+    }
+
+    /// The root node of the syntax tree.
+    pub fn root(&self) -> &dyn Node {
+        self.root.as_ref()
+    }
+
+    /// Takes the syntax tree out of the expression.
+    pub fn into_root(self) -> Box<dyn Node> {
+        self.root
+    }
+}
+
+impl From<Box<dyn Node>> for Expression {
+    fn from(root: Box<dyn Node>) -> Self {
+        Self { root }
+    }
+}
+
+impl FromStr for Expression {
+    type Err = ParseError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        parse_str(source).map(Self::from)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.root.to_infix())
+    }
+}
@@ -1,31 +1,152 @@
-mod tokeniser;
-mod parser;
-mod ast;
-
 use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use simple_expression_parser::{
+    Parser, Tokeniser, TokenKind, Environment, EvalError, Number, NumberFormat, format_result, group_digits, to_fraction
+};
 
-use parser::Parser;
+/// The largest denominator of the fractions printed by `:fraction on`.
+const DEFAULT_MAX_DENOMINATOR: u32 = 10_000;
+
+/// How the REPL prints answers, set with the `:precision`,
+/// `:decimal`, `:notation`, `:group` and `:fraction` commands.
+#[derive(Default)]
+struct OutputFormat {
+    /// The number of significant digits to round answers to,
+    /// or `None` to print them in full.
+    precision: Option<usize>,
+    /// Whether whole numbers are printed with a trailing `.0`.
+    decimal: bool,
+    /// Whether answers are printed in scientific notation.
+    notation: NumberFormat,
+    /// Whether the digits of answers are grouped into thousands with commas.
+    grouped: bool,
+    /// The largest denominator for answers to be printed as a fraction,
+    /// such as `1/4`, or `None` to always print them as decimals.
+    max_denominator: Option<u32>
+}
+
+impl OutputFormat {
+    fn format(&self, value: Number) -> String {
+        if !value.is_finite() {
+            return value.to_string();
+        }
+        // Whole numbers are still printed as decimals.
+        let fraction = self.max_denominator.and_then(|max| to_fraction(value, max));
+        if let Some((numerator, denominator @ 2..)) = fraction {
+            return format!("{}/{}", numerator, denominator);
+        }
+        // Rounding via scientific notation rounds to significant digits
+        // at any magnitude, and parsing it back prints the shortest form,
+        // so `1/3` with a precision of 4 is `0.3333` rather than `0.3333000`.
+        let value = match self.precision {
+            Some(precision) => format!("{:.*e}", precision.saturating_sub(1), value)
+                .parse()
+                .expect("Formatted float should parse"),
+            None => value
+        };
+        let mut text = format_result(value, self.notation);
+        if self.decimal && value.fract() == 0.0 && !text.contains('e') {
+            text.push_str(".0");
+        }
+        if self.grouped {
+            text = group_digits(&text, ',');
+        }
+        text
+    }
+}
 
 fn main() {
     
     let mut parser = Parser::new(
         String::new()
     );
+
+    // The previous answer is bound to `ans`, like on a calculator.
+    let mut env = Environment::new();
+
+    // Seeded from the clock so that `rand()` differs between sessions.
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64);
+    env.set_seed(seed);
+
+    // The line buffer, the input buffer and the parser
+    // are all reused for every line.
+    let mut line = String::new();
+    // Lines are gathered here until they make up a complete input,
+    // so that an expression can be continued over several lines.
+    let mut input = String::new();
+
+    // Every input entered so far, listed by the `:history` command.
+    let mut history: Vec<String> = Vec::new();
+
+    let mut format = OutputFormat::default();
     
     loop {
-        print!("> ");
+        let continuing = !input.is_empty();
+        print!("{}", if continuing { "... " } else { "> " });
         io::stdout().flush()
             .expect("Failed to flush stdout");
         
-        let mut input = String::new();
+        line.clear();
+
+        let read = io::stdin().read_line(&mut line);
+        // Nothing more can be read once the input has ended, such
+        // as after Ctrl+D or at the end of a piped file.
+        if matches!(read, Ok(0)) {
+            println!();
+            break;
+        }
+
+        if read.is_ok() {
+            if !continuing {
+                // Lines starting with a colon are commands for the REPL,
+                // rather than expressions.
+                if let Some(command) = line.trim_start().strip_prefix(':') {
+                    run_command(command, &mut format, &history);
+                    continue;
+                }
+                // Nor is there anything to do for a line
+                // of only whitespace or a comment.
+                if is_blank(&line) {
+                    continue;
+                }
+            }
+
+            input.push_str(&line);
+            parser.reset(&input);
+
+            // Several expressions can be separated by semicolons,
+            // each of which is displayed and evaluated in turn.
+            let result = parser.parse_program();
 
-        if io::stdin().read_line(&mut input).is_ok() {
-            parser.set_source(input);
+            // An input which has only stopped early, such as after an
+            // operator or inside brackets, or whose line ends in a
+            // backslash, continues on the next line, unless that line
+            // is blank, which gives up on it.
+            let incomplete = result.as_ref().is_err_and(|err| err.is_incomplete())
+                || line.trim_end_matches(['\n', '\r']).ends_with('\\');
+            if incomplete && !(continuing && is_blank(&line)) {
+                continue;
+            }
+            history.push(input.trim_end().to_string());
+            input.clear();
 
-            match parser.parse() {
-                Ok(tree) => {
-                    println!("\n{}\n", tree.display(0));
-                    println!("answer = {}\n", tree.evaluate());
+            match result {
+                Ok(program) => {
+                    for tree in program {
+                        println!("\n{}\n", tree.display(0));
+                        match tree.evaluate_with(&env) {
+                            Ok(answer) => {
+                                println!("answer = {}\n", format.format(answer));
+                                env.insert("ans", answer);
+                            },
+                            Err(EvalError::UnboundVariable { name }) if name == "ans" => {
+                                println!("Failed to evaluate: 'ans' has no value until an expression has been evaluated\n")
+                            },
+                            Err(msg) => println!("Failed to evaluate: {}\n", msg)
+                        };
+                    }
                 },
                 Err(msg) => {
                     println!("Failed to parse: {}", msg);
@@ -38,3 +159,85 @@ fn main() {
     }
 
 }
+
+/// Whether `line` has nothing to evaluate, being
+/// only whitespace or a comment.
+fn is_blank(line: &str) -> bool {
+    Tokeniser::new(line).next_token()
+        .is_ok_and(|token| token.kind == TokenKind::EOF)
+}
+
+/// Runs a REPL command, given the line without its leading colon.
+/// 
+/// `:tokens <expr>` prints the token stream of `<expr>`.
+/// `:precision <digits>` rounds answers to that many significant
+/// digits, or prints them in full when no digits are given.
+/// `:decimal on` prints whole numbers with a trailing `.0`.
+/// `:notation auto|scientific|fixed` sets when answers are printed
+/// in scientific notation.
+/// `:group on` groups the digits of answers into thousands, e.g. `1,000`.
+/// `:fraction on` prints answers as fractions where they're exact, e.g.
+/// `1/4`, and `:fraction <max>` sets the largest denominator to print.
+/// `:history` lists every input entered so far.
+fn run_command(command: &str, format: &mut OutputFormat, history: &[String]) {
+    let (name, argument) = command.split_once(char::is_whitespace)
+        .unwrap_or((command.trim_end(), ""));
+
+    match name {
+        "tokens" => {
+            let mut tokeniser = Tokeniser::new(argument);
+            loop {
+                match tokeniser.next_token() {
+                    Ok(token) => {
+                        println!("{:?}", token);
+                        if token.kind == TokenKind::EOF {
+                            break;
+                        }
+                    },
+                    Err(msg) => {
+                        println!("Failed to tokenise: {}", msg);
+                        break;
+                    }
+                }
+            }
+        },
+        "precision" => match argument.trim() {
+            "" => format.precision = None,
+            digits => match digits.parse() {
+                Ok(digits) if digits > 0 => format.precision = Some(digits),
+                _ => println!("Expected a positive number of digits, got '{}'", digits)
+            }
+        },
+        "decimal" => match argument.trim() {
+            "on" => format.decimal = true,
+            "off" => format.decimal = false,
+            other => println!("Expected 'on' or 'off', got '{}'", other)
+        },
+        "notation" => match argument.trim() {
+            "auto" => format.notation = NumberFormat::Auto,
+            "scientific" => format.notation = NumberFormat::Scientific,
+            "fixed" => format.notation = NumberFormat::Fixed,
+            other => println!("Expected 'auto', 'scientific' or 'fixed', got '{}'", other)
+        },
+        "history" => {
+            // Continuation lines are indented to line up with the first.
+            for (number, input) in history.iter().enumerate() {
+                println!("{:>4}  {}", number + 1, input.replace('\n', "\n      "));
+            }
+        },
+        "group" => match argument.trim() {
+            "on" => format.grouped = true,
+            "off" => format.grouped = false,
+            other => println!("Expected 'on' or 'off', got '{}'", other)
+        },
+        "fraction" => match argument.trim() {
+            "on" => format.max_denominator = Some(DEFAULT_MAX_DENOMINATOR),
+            "off" => format.max_denominator = None,
+            max => match max.parse() {
+                Ok(max) if max > 1 => format.max_denominator = Some(max),
+                _ => println!("Expected 'on', 'off' or a largest denominator above 1, got '{}'", max)
+            }
+        },
+        _ => println!("Unknown command ':{}'", name)
+    }
+}
@@ -0,0 +1,270 @@
+//! The float functions used during evaluation.
+//!
+//! Most of them are only provided by the standard library, so without
+//! the `std` feature only those which can be written with basic arithmetic
+//! are available. The rest are reported as `EvalError::RequiresStd`, and
+//! can instead be defined with `Environment::define_function()`.
+
+use alloc::{string::String, vec::Vec};
+
+use crate::ast::Number;
+
+/// The built-in function `name` applied to `arguments`, or `None` if
+/// it isn't available without the `std` feature.
+///
+/// The number of arguments must already have been checked.
+#[cfg(feature = "std")]
+pub(crate) fn builtin(name: &str, arguments: &[Number]) -> Option<Number> {
+    let argument = arguments[0];
+
+    // Simple map to rust native functions
+    Some(match name {
+        "sin" => argument.sin(),
+        "cos" => argument.cos(),
+        "tan" => argument.tan(),
+        "asin" => argument.asin(),
+        "acos" => argument.acos(),
+        "atan" => argument.atan(),
+        "sinh" => argument.sinh(),
+        "cosh" => argument.cosh(),
+        "tanh" => argument.tanh(),
+        "sqrt" => argument.sqrt(),
+        "cbrt" => argument.cbrt(),
+        "abs" => argument.abs(),
+        "exp" => argument.exp(),
+        "ln" => argument.ln(),
+        "log2" => argument.log2(),
+        "log10" => argument.log10(),
+        "floor" => argument.floor(),
+        "ceil" => argument.ceil(),
+        "round" => argument.round(),
+        "atan2" => argument.atan2(arguments[1]),
+        "hypot" => argument.hypot(arguments[1]),
+        "min" => arguments.iter().copied().fold(Number::INFINITY, Number::min),
+        "max" => arguments.iter().copied().fold(Number::NEG_INFINITY, Number::max),
+        "sum" => arguments.iter().sum(),
+        "avg" => arguments.iter().sum::<Number>() / arguments.len() as Number,
+        _ => unreachable!("every function with an arity is evaluated")
+    })
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn builtin(name: &str, arguments: &[Number]) -> Option<Number> {
+    let argument = arguments[0];
+
+    match name {
+        "sqrt" => Some(sqrt(argument)),
+        "abs" => Some(argument.abs()),
+        "floor" => Some(floor(argument)),
+        "ceil" => Some(ceil(argument)),
+        "round" => Some(round(argument)),
+        "hypot" => Some(sqrt(argument * argument + arguments[1] * arguments[1])),
+        "min" => Some(arguments.iter().copied().fold(Number::INFINITY, Number::min)),
+        "max" => Some(arguments.iter().copied().fold(Number::NEG_INFINITY, Number::max)),
+        "sum" => Some(arguments.iter().sum()),
+        "avg" => Some(arguments.iter().sum::<Number>() / arguments.len() as Number),
+        _ => None
+    }
+}
+
+/// `base` raised to the power of `exponent`, or `None` if it isn't
+/// available without the `std` feature.
+#[cfg(feature = "std")]
+pub(crate) fn pow(base: Number, exponent: Number) -> Option<Number> {
+    Some(base.powf(exponent))
+}
+
+/// Without the `std` feature, only integer powers are available.
+#[cfg(not(feature = "std"))]
+pub(crate) fn pow(base: Number, exponent: Number) -> Option<Number> {
+    if fract(exponent) != 0.0 || !exponent.is_finite() {
+        return None;
+    }
+    // Exponentiation by squaring, one bit of the exponent at a time.
+    let mut result: Number = 1.0;
+    let mut base = base;
+    let mut remaining = exponent.abs();
+    while remaining >= 1.0 {
+        if fract(remaining / 2.0) != 0.0 {
+            result *= base;
+        }
+        base *= base;
+        remaining = trunc(remaining / 2.0);
+    }
+    Some(if exponent < 0.0 { 1.0 / result } else { result })
+}
+
+/// The value of the hexadecimal float `0x<integer>.<fraction>p<exponent>`,
+/// rounded to the nearest float with ties to even, as C's `strtod()` does.
+/// 
+/// The digits must all be hexadecimal, without separators.
+pub(crate) fn hex_float(integer: &str, fraction: &str, exponent: i64) -> Number {
+    // Any exponent beyond this is infinity or zero however many digits
+    // there are, and clamping it means the sums below can't overflow.
+    let mut exponent = exponent.clamp(-100_000, 100_000);
+
+    // The first 60 significant bits are kept exactly, more than a float
+    // holds. Of any bits after them it only matters whether they're all
+    // zero, which decides how a value half-way between floats is rounded.
+    let mut mantissa: u64 = 0;
+    let mut sticky = false;
+    let digits = integer.chars().map(|digit| (digit, false))
+        .chain(fraction.chars().map(|digit| (digit, true)));
+    for (digit, is_fraction) in digits {
+        let digit = digit.to_digit(16).expect("the digits should be hexadecimal") as u64;
+        if mantissa >> 56 == 0 {
+            mantissa = mantissa << 4 | digit;
+            if is_fraction {
+                exponent -= 4;
+            }
+        } else {
+            sticky |= digit != 0;
+            if !is_fraction {
+                exponent += 4;
+            }
+        }
+    }
+    if mantissa == 0 {
+        return 0.0;
+    }
+
+    // Shifts the leading one up to the top bit, so that the value is
+    // 1.xxx * 2^leading, then rounds it to as many bits as fit in a
+    // float of that size, which is fewer for subnormal floats.
+    let shift = mantissa.leading_zeros();
+    mantissa <<= shift;
+    let leading = exponent + 63 - shift as i64;
+    if leading > 1023 {
+        return Number::INFINITY;
+    }
+    let kept_bits = (leading + 1075).min(53);
+    if kept_bits < 0 {
+        return 0.0;
+    }
+    let dropped_bits = 64 - kept_bits as u32;
+    let kept = mantissa.checked_shr(dropped_bits).unwrap_or(0);
+    let remainder = mantissa & (u64::MAX >> kept_bits);
+    let half = 1u64 << (dropped_bits - 1);
+    let round_up = remainder > half || (remainder == half && (sticky || kept & 1 == 1));
+    let kept = kept + round_up as u64;
+
+    // Both factors are exact, so the product is only rounded
+    // if it's too large, which makes it infinity.
+    kept as Number * power_of_two(leading + 1 - kept_bits)
+}
+
+/// The value of the integer written as `digits` in `radix`, which must
+/// be 2, 8 or 16, rounded to the nearest float like `hex_float()`.
+/// 
+/// The digits must all be valid in the radix, without separators.
+pub(crate) fn radix_float(digits: &str, radix: u32) -> Number {
+    let bits_per_digit = radix.trailing_zeros();
+    let mut bits = Vec::with_capacity(digits.len() * bits_per_digit as usize);
+    for digit in digits.chars() {
+        let digit = digit.to_digit(radix).expect("the digits should be valid in the radix");
+        bits.extend((0..bits_per_digit).rev().map(|shift| digit >> shift & 1));
+    }
+    // Regrouped into hexadecimal digits from the last bit, so
+    // the first digit takes whatever bits are left over.
+    let mut hex = String::with_capacity(bits.len() / 4 + 1);
+    let leading = bits.len() % 4;
+    let groups = core::iter::once(&bits[..leading]).chain(bits[leading..].chunks(4));
+    for group in groups.filter(|group| !group.is_empty()) {
+        let digit = group.iter().fold(0, |digit, bit| digit << 1 | bit);
+        hex.push(char::from_digit(digit, 16).expect("four bits make a hexadecimal digit"));
+    }
+    hex_float(&hex, "", 0)
+}
+
+/// 2 raised to `exponent`, which must be at least the exponent of
+/// the smallest subnormal float and at most that of the largest float.
+fn power_of_two(exponent: i64) -> Number {
+    if exponent >= -1022 {
+        Number::from_bits(((exponent + 1023) as u64) << 52)
+    } else {
+        Number::from_bits(1u64 << (exponent + 1074))
+    }
+}
+
+/// Advances the generator `state` and returns a number in the range [0, 1).
+///
+/// This is SplitMix64, which is fast, has no dependencies, and
+/// generates well distributed numbers from any seed, including 0.
+/// It isn't suitable for cryptography.
+pub(crate) fn random(state: &mut u64) -> Number {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    // The top 53 bits fill the mantissa of a float exactly.
+    (z >> 11) as Number / (1u64 << 53) as Number
+}
+
+/// The fractional part of `value`, with the same sign as `value`.
+#[cfg(feature = "std")]
+pub(crate) fn fract(value: Number) -> Number {
+    value.fract()
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn fract(value: Number) -> Number {
+    value - trunc(value)
+}
+
+#[cfg(not(feature = "std"))]
+fn trunc(value: Number) -> Number {
+    // Floats of at least 2^52 have no fractional part, and
+    // may be too large to round trip through an i64.
+    if value.is_nan() || value.abs() >= 4_503_599_627_370_496.0 {
+        value
+    } else {
+        value as i64 as Number
+    }
+}
+
+/// The largest integer less than or equal to `value`.
+#[cfg(feature = "std")]
+pub(crate) fn floor(value: Number) -> Number {
+    value.floor()
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn floor(value: Number) -> Number {
+    let truncated = trunc(value);
+    if truncated > value { truncated - 1.0 } else { truncated }
+}
+
+#[cfg(not(feature = "std"))]
+fn ceil(value: Number) -> Number {
+    let truncated = trunc(value);
+    if truncated < value { truncated + 1.0 } else { truncated }
+}
+
+/// Rounds half-way cases away from zero, like `f64::round()`.
+#[cfg(not(feature = "std"))]
+fn round(value: Number) -> Number {
+    let truncated = trunc(value);
+    if (value - truncated).abs() >= 0.5 { truncated + value.signum() } else { truncated }
+}
+
+#[cfg(not(feature = "std"))]
+fn sqrt(value: Number) -> Number {
+    if value < 0.0 {
+        return Number::NAN;
+    }
+    if value == 0.0 || !value.is_finite() {
+        return value;
+    }
+    // Newton's method, starting above the root so that each guess
+    // is smaller than the last until it can't get any closer. The
+    // result may be one bit away from that of `f64::sqrt()`.
+    let mut guess = value.max(1.0);
+    loop {
+        let next = (guess + value / guess) / 2.0;
+        if next >= guess {
+            return guess;
+        }
+        guess = next;
+    }
+}
@@ -1,21 +1,129 @@
+use core::fmt;
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec
+};
+#[cfg(feature = "std")]
+use std::io::Read;
+
 use crate::{
-    tokeniser::{Tokeniser, TokenKind, Token},
-    ast::{Node, self}
+    tokeniser::{Tokeniser, TokenKind, Token, Position, LocaleOptions, DecimalSeparator},
+    ast::{Node, self},
+    error::ParseError
 };
 
 /// Generates a walkable & executable abstract syntax tree out of an expression.
 pub struct Parser {
     tokeniser: Tokeniser,
-    current_token: Token
+    current_token: Token,
+    // The kind of the most recently eaten token,
+    // used to detect implicit multiplication.
+    previous_kind: TokenKind,
+    // Where the most recently eaten token ends,
+    // used to find the end of each node's span.
+    previous_end: usize,
+    // Whether a '%' with no right hand side is a percentage.
+    percent_mode: bool,
+    // How many brackets, function calls, signs and exponents
+    // the parser is currently nested inside of.
+    depth: usize,
+    max_depth: usize,
+    max_input_length: Option<usize>,
+    locale: LocaleOptions,
+    // Each pair of brackets found by the last parse, used by `analyze()`.
+    brackets: Vec<Brackets>,
+    // Constants defined with `define_constant()`, which
+    // are resolved along with the built-in constants.
+    constants: BTreeMap<String, ast::Number>,
+    // Whether nodes are built, which they aren't by `validate()`.
+    building: bool
+}
+
+/// A warning about valid but questionable source, found by `Parser::analyze()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    pub message: String,
+    /// The chars the lint applies to, as the index of the
+    /// first and the index just past the last, like `Node::span()`.
+    pub start: usize,
+    pub end: usize
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// A pair of brackets found while parsing, see `Parser::analyze()`.
+#[derive(Debug, Clone, Copy)]
+struct Brackets {
+    // The char indices of the opening and closing brackets.
+    open: usize,
+    close: usize,
+    // The node inside the brackets, by its address, and its precedence.
+    node: usize,
+    precedence: u8,
+    // Whether the brackets follow a number, as in `2(3)`, and whether
+    // what's inside could be multiplied by the number without them, as
+    // in `2(x)`, but not `2(3)` or `2(-x)`.
+    after_number: bool,
+    multiplied: bool,
+    // The index of the opening bracket just before this one, if any.
+    enclosing: Option<usize>
 }
 
+/// The deepest an expression can be nested unless
+/// changed with `Parser::set_max_depth()`.
+pub const DEFAULT_MAX_DEPTH: usize = 100;
+
 impl Parser {
-    pub fn new(source: String) -> Self {
+    /// Creates a parser for `source`, which can be either a `String`,
+    /// which is used as is, or a `&str`, which is copied.
+    /// 
+    /// ```
+    /// use simple_expression_parser::Parser;
+    /// 
+    /// let source = String::from("1 + 2; 3 * 4");
+    /// let first = source.split(';').next().unwrap();
+    /// assert_eq!(Parser::new(first).parse().unwrap().evaluate(), Ok(3.0));
+    /// assert_eq!(Parser::new(source).parse_program().unwrap().len(), 2);
+    /// ```
+    pub fn new(source: impl Into<String>) -> Self {
+        Self::with_tokeniser(Tokeniser::new(source))
+    }
+
+    /// Creates a parser which reads its source from `reader` as it goes,
+    /// see `Tokeniser::from_reader()`.
+    /// 
+    /// Only available with the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: Read + 'static>(reader: R) -> Self {
+        Self::with_tokeniser(Tokeniser::from_reader(reader))
+    }
+
+    /// Creates a parser with the default settings,
+    /// which reads its tokens from `tokeniser`.
+    fn with_tokeniser(tokeniser: Tokeniser) -> Self {
         Parser {
-            tokeniser: Tokeniser::new(source),
+            tokeniser,
             // This empty token acts as a placeholder until the
             // tokeniser is actually invoked.
-            current_token: Token::empty()
+            current_token: Token::empty(),
+            previous_kind: TokenKind::Empty,
+            previous_end: 0,
+            percent_mode: false,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_input_length: None,
+            locale: LocaleOptions::default(),
+            brackets: Vec::new(),
+            constants: BTreeMap::new(),
+            building: true
         }
     }
 
@@ -24,6 +132,167 @@ impl Parser {
         // It's easier just to initialise a new tokensier than
         // to individually reset all of it's attributes.
         self.tokeniser = Tokeniser::new(source);
+        self.tokeniser.set_max_length(self.max_input_length);
+        self.tokeniser.set_locale(self.locale);
+    }
+
+    /// Replaces the source which needs to be parsed, reusing the
+    /// tokeniser's buffer rather than allocating a new one.
+    /// 
+    /// Prefer this over `set_source()` when parsing many short
+    /// expressions in a row, see `Tokeniser::reset()`.
+    pub fn reset(&mut self, source: &str) {
+        self.tokeniser.reset(source);
+        self.current_token = Token::empty();
+        self.previous_kind = TokenKind::Empty;
+        self.previous_end = 0;
+    }
+
+    /// Enables or disables percent mode, which is off by default.
+    /// 
+    /// In percent mode a '%' which isn't followed by an operand is a
+    /// percentage rather than a remainder, so `50%` is `0.5`. Adding
+    /// or subtracting a percentage applies it to the left hand side,
+    /// so `200 + 10%` is `220` and `200 - 10%` is `180`.
+    /// 
+    /// A '%' is a remainder when the token after it is a number, an
+    /// identifier or an opening bracket, as in `10 % 3`, and a
+    /// percentage otherwise, as in `10%` or `10% * 2`. This means
+    /// `10 % -3` is read as `10% - 3`; write `10 % (-3)` instead.
+    /// 
+    /// Adding a percentage makes one `BinOp` with `Op::AddPercent`, whose
+    /// right hand side is the percentage, so the left hand side is only
+    /// evaluated once and `to_infix()` writes the expression as it was.
+    /// 
+    /// ```
+    /// use std::{cell::Cell, rc::Rc};
+    /// use simple_expression_parser::{Parser, Environment};
+    /// 
+    /// let parse = |source: &str| {
+    ///     let mut parser = Parser::new(source);
+    ///     parser.set_percent_mode(true);
+    ///     parser.parse().unwrap()
+    /// };
+    /// assert_eq!(parse("200 + 10%").evaluate(), Ok(220.0));
+    /// assert_eq!(parse("200 - 10%").evaluate(), Ok(180.0));
+    /// assert_eq!(parse("10 % 3").evaluate(), Ok(1.0));
+    /// assert_eq!(parse("10%").evaluate(), Ok(0.1));
+    /// assert_eq!(parse("10% * 2 + 1").evaluate(), Ok(1.2));
+    /// 
+    /// // Each percentage is applied to the total so far
+    /// let source = format!("100{}", " + 1%".repeat(1000));
+    /// let long = parse(&source);
+    /// assert_eq!(long.to_infix(), source);
+    /// assert!((long.evaluate().unwrap() - 100.0 * 1.01f64.powi(1000)).abs() < 1e-6);
+    /// 
+    /// let calls = Rc::new(Cell::new(0));
+    /// let mut env = Environment::new();
+    /// let counter = Rc::clone(&calls);
+    /// env.define_function("price", move |_| {
+    ///     counter.set(counter.get() + 1);
+    ///     Ok(50.0)
+    /// });
+    /// assert_eq!(parse("price() - 20%").evaluate_with(&env), Ok(40.0));
+    /// assert_eq!(calls.get(), 1);
+    /// ```
+    pub fn set_percent_mode(&mut self, enabled: bool) {
+        self.percent_mode = enabled;
+    }
+
+    /// Sets how deeply an expression can be nested before it's rejected
+    /// with `ParseError::TooDeeplyNested`, which is 100 by default.
+    /// 
+    /// Each bracket, function call, sign and exponent adds a level, so
+    /// `-(1 + 2^3)` is nested three levels deep. The parser is recursive,
+    /// so without a limit a deeply nested input such as `((((...))))`
+    /// would overflow the stack rather than produce an error.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Parser, ParseError};
+    /// 
+    /// let depth = 100_000;
+    /// let source = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+    /// let result = Parser::new(source).parse();
+    /// assert!(matches!(result, Err(ParseError::TooDeeplyNested { max_depth: 100, .. })));
+    /// ```
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Sets how numbers and argument lists are written,
+    /// see `LocaleOptions`. The default uses a decimal point.
+    pub fn set_locale(&mut self, locale: LocaleOptions) {
+        self.locale = locale;
+        self.tokeniser.set_locale(locale);
+    }
+
+    /// Defines a constant which can be referred to by `name`, taking
+    /// priority over any built-in constant of the same name.
+    /// 
+    /// Unlike a variable, which is looked up in the `Environment` each
+    /// time the expression is evaluated, a constant is resolved into a
+    /// `Constant` node while parsing. This means it needs no environment,
+    /// is folded by `simplify()` and can't be assigned to.
+    /// 
+    /// Since another parser may not have the same definition, the tree
+    /// writes the constant as its value rather than its name, as in
+    /// `to_infix()`, which follows it with a comment naming it.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Expression, Parser, ParseError, from_sexpr};
+    /// 
+    /// let mut parser = Parser::new("g * 2");
+    /// parser.define_constant("g", 9.81);
+    /// let tree = parser.parse().unwrap();
+    /// assert_eq!(tree.evaluate(), Ok(19.62));
+    /// assert_eq!(tree.free_variables().len(), 0);
+    /// assert_eq!(tree.to_infix(), "9.81 /* g */ * 2");
+    /// assert_eq!(tree.to_sexpr(), "(* 9.81 2)");
+    /// 
+    /// // Each form reads back into an expression with the same value
+    /// let expression = Expression::from(tree.clone_box());
+    /// let infix: Expression = expression.to_string().parse().unwrap();
+    /// assert_eq!(infix.evaluate(), Ok(19.62));
+    /// assert_eq!(from_sexpr(&tree.to_sexpr()).unwrap().evaluate(), Ok(19.62));
+    /// assert_eq!(tree.simplify().to_infix(), "19.62");
+    /// 
+    /// // A negative value is bracketed where its sign needs it
+    /// let mut negative = Parser::new("2^k + k^2");
+    /// negative.define_constant("k", -1.5);
+    /// let tree = negative.parse().unwrap();
+    /// assert_eq!(tree.to_infix(), "2 ^ -1.5 /* k */ + (-1.5 /* k */) ^ 2");
+    /// assert_eq!(Parser::new(tree.to_infix()).parse().unwrap().evaluate(), tree.evaluate());
+    /// 
+    /// // Built-in constants are still written by name
+    /// assert_eq!(Parser::new("2 * pi").parse().unwrap().to_infix(), "2 * pi");
+    /// 
+    /// parser.reset("g = 10");
+    /// assert!(matches!(parser.parse(), Err(ParseError::AssignToConstant { .. })));
+    /// 
+    /// // Without the definition, `g` is a variable
+    /// let variable = Parser::new("g * 2").parse().unwrap();
+    /// assert!(variable.evaluate().is_err());
+    /// ```
+    pub fn define_constant(&mut self, name: impl Into<String>, value: ast::Number) {
+        self.constants.insert(name.into(), value);
+    }
+
+    /// Sets how many chars the source may contain, or `None` for
+    /// no limit, which is the default. Longer sources are rejected
+    /// with `ParseError::InputTooLong`, see `Tokeniser::set_max_length()`.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Parser, ParseError};
+    /// 
+    /// // A megabyte of `1+1+...`
+    /// let mut parser = Parser::new("1+".repeat(500_000) + "1");
+    /// parser.set_max_input_length(Some(1000));
+    /// 
+    /// assert_eq!(parser.parse().err(), Some(ParseError::InputTooLong { max_length: 1000 }));
+    /// ```
+    pub fn set_max_input_length(&mut self, max_length: Option<usize>) {
+        self.max_input_length = max_length;
+        self.tokeniser.set_max_length(max_length);
     }
 
     /// The parse() function is the entry point for the whole
@@ -31,25 +300,249 @@ impl Parser {
     /// 
     /// Grammar (modified bnf):
     /// 
-    /// ```
-    /// <expr> ::= <mult_expr> ((Add | Sub) <mult_expr>)*
+    /// ```text
+    /// <statement> ::= Ident Assign <expr> | <expr>
+    /// 
+    /// <expr> ::= <or_expr> (Question <expr> Colon <expr>)?
+    /// 
+    /// <or_expr> ::= <and_expr> (Or <and_expr>)*
+    /// 
+    /// <and_expr> ::= <not_expr> (And <not_expr>)*
+    /// 
+    /// <not_expr> ::= Not <not_expr> | <comparison>
     /// 
-    /// <mult_expr> ::= <entity> ((Mult | Div) <entity>)*
+    /// <comparison> ::= <bit_or> ((Equal | NotEqual | Less | LessEqual | Greater | GreaterEqual) <bit_or> | In <range>)*
     /// 
-    /// <entity> ::= IntLiteral | FloatLiteral | Sub <entity> | LParen <expr> RParen
+    /// <range> ::= <bit_or> DotDot <bit_or>
+    /// 
+    /// <bit_or> ::= <bit_xor> (BitOr <bit_xor>)*
+    /// 
+    /// <bit_xor> ::= <bit_and> (BitXor <bit_and>)*
+    /// 
+    /// <bit_and> ::= <shift> (BitAnd <shift>)*
+    /// 
+    /// <shift> ::= <add_expr> ((ShiftLeft | ShiftRight) <add_expr>)*
+    /// 
+    /// <add_expr> ::= <mult_expr> ((Add | Sub) <mult_expr>)*
+    /// 
+    /// <mult_expr> ::= <unary> ((Mult | Div | Mod) <unary>)*
+    /// 
+    /// <unary> ::= (Add | Sub) <unary> | <power>
+    /// 
+    /// <power> ::= <postfix> (Pow <unary>)?
+    /// 
+    /// <postfix> ::= <entity> Factorial*
+    /// 
+    /// <entity> ::= IntLiteral | FloatLiteral | Ident | Ident LParen <arguments> RParen
+    ///            | LParen <expr> RParen | LBracket <expr> RBracket | LBrace <expr> RBrace
+    ///            | BitOr <bit_xor> BitOr
+    /// 
+    /// <arguments> ::= (<expr> (Comma <expr>)*)?
     /// ```
     /// 
     /// The grammar is in order of scope, the highest covering the entire syntax,
     /// the lowest covering the most fundamental components of an expression.
     /// 
-    pub fn parse(&mut self) -> Result<Box<dyn Node>, String> {
+    /// The levels from `<or_expr>` down to `<power>` differ only in their
+    /// operators, so rather than a function each, they're all parsed by
+    /// `binary()` using the precedence of each operator.
+    /// 
+    pub fn parse(&mut self) -> Result<Box<dyn Node>, ParseError> {
         // Explicitly generate the first token.
+        self.previous_kind = TokenKind::Empty;
+        self.depth = 0;
+        self.brackets.clear();
         self.current_token = self.tokeniser.next_token()?;
+        // Otherwise an empty input would be reported
+        // as an unexpected EOF where an operand should be.
+        if self.current_token.kind == TokenKind::EOF {
+            return Err(ParseError::EmptyInput);
+        }
         // `expr` is the highest level variable defined in our grammar,
         // This means it covers every single case the parser is capable
         // of parsing.
-        let result = self.expr()?;
-        Ok(result)
+        let result = self.statement()?;
+
+        // The whole input must be consumed by the expression,
+        // otherwise something like `1 + 2)` would silently
+        // be parsed as `1 + 2`.
+        match self.current_token.kind {
+            TokenKind::EOF => Ok(result),
+            _ => Err(self.trailing_token_error())
+        }
+    }
+
+    /// Checks whether the source is a valid expression, without building its tree.
+    /// 
+    /// This runs exactly the same parse as `parse()`, so it fails with
+    /// exactly the same error, rather than a second grammar which could
+    /// drift out of step with the first. Only the nodes aren't built,
+    /// so however long the source is, no tree is allocated or dropped.
+    /// 
+    /// ```
+    /// use simple_expression_parser::Parser;
+    /// 
+    /// let source = format!("1{}", " + 1".repeat(200_000));
+    /// assert_eq!(Parser::new(source.as_str()).validate(), Ok(()));
+    /// 
+    /// for source in [format!("{} +", source), format!("{})", source), "x in 10".to_string()] {
+    ///     let error = Parser::new(source.as_str()).parse().err();
+    ///     assert!(error.is_some());
+    ///     assert_eq!(Parser::new(source).validate().err(), error);
+    /// }
+    /// ```
+    pub fn validate(&mut self) -> Result<(), ParseError> {
+        self.building = false;
+        let result = self.parse().map(drop);
+        self.building = true;
+        result
+    }
+
+    /// Parses the source as a program, see `parse_program()`, and finds
+    /// the redundant brackets in it, such as those in `(1) + 2` or the
+    /// outer pair in `((1+2)) * 3`, returning a lint spanning each pair
+    /// from the opening bracket to just past the closing one.
+    /// 
+    /// A pair of brackets is redundant when the source parses into the
+    /// same tree without them. That's decided from the precedence of the
+    /// node inside them and where it is in its parent, just as `to_infix()`
+    /// decides where brackets are needed, so `2 * (3 + 4)` and `(-2)^2`
+    /// need theirs. Where several pairs surround the same node, only the
+    /// innermost can be needed. Brackets right after a number, as in
+    /// `2(3)`, are needed unless they start with a name or another
+    /// bracket, as in `2(x)`, and function calls and `|x|` are
+    /// never flagged.
+    /// 
+    /// ```
+    /// use simple_expression_parser::Parser;
+    /// 
+    /// let lints = |source: &str| {
+    ///     let lints = Parser::new(source).analyze().unwrap();
+    ///     lints.iter().map(|lint| (lint.start, lint.end)).collect::<Vec<_>>()
+    /// };
+    /// assert_eq!(lints("(1) + 2"), [(0, 3)]);
+    /// assert_eq!(lints("((1+2))"), [(0, 7), (1, 6)]);
+    /// assert_eq!(lints("((1+2)) * 3"), [(0, 7)]);
+    /// assert_eq!(lints("2 * (3 + 4)"), []);
+    /// assert_eq!(lints("(-2)^2 + 2(3)"), []);
+    /// assert_eq!(lints("2(x) + 2(-x) + 2((3))"), [(1, 4), (16, 21)]);
+    /// assert_eq!(lints("1 - (2 + 3) + (4 * 5)"), [(14, 21)]);
+    /// assert_eq!(lints("(1 < 2) < 3; 2^(-1); max((1), 2)"), [(15, 19), (25, 28)]);
+    /// assert!(Parser::new("(1 + 2").analyze().is_err());
+    /// 
+    /// // Each pair is only looked at once, however long the source is
+    /// let source = format!("2{}", " + (1)".repeat(20_000));
+    /// assert_eq!(Parser::new(source).analyze().unwrap().len(), 20_000);
+    /// ```
+    pub fn analyze(&mut self) -> Result<Vec<Lint>, ParseError> {
+        let trees = self.parse_program()?;
+        // The lowest precedence each node could have without brackets
+        // around it, by its address. Whole statements never need them.
+        let mut needed = BTreeMap::new();
+        let mut stack: Vec<&dyn Node> = trees.iter().map(|tree| tree.as_ref()).collect();
+        while let Some(node) = stack.pop() {
+            let children = node.children();
+            let precedences = ast::operand_precedences(node, self.percent_mode);
+            for (i, child) in children.iter().enumerate() {
+                // A node not defined by this crate may need anything bracketed.
+                let precedence = precedences.as_ref().map_or(u8::MAX, |precedences| precedences[i]);
+                needed.insert(ast::address(*child), precedence);
+            }
+            stack.extend(children);
+        }
+
+        // A source read from a reader isn't kept, so can't be quoted.
+        let source: Option<Vec<char>> = self.tokeniser.source().map(|source| source.chars().collect());
+        // Pairs are found innermost first, as that's the order they're closed in.
+        let mut bracketed = BTreeSet::new();
+        let mut pairs: Vec<(&Brackets, bool)> = self.brackets.iter()
+            .map(|brackets| (brackets, bracketed.insert(brackets.node)))
+            .collect();
+        // Going from the outside in, removing a pair right after a number
+        // leaves the pair just inside it after the number instead, as in `2((3))`.
+        pairs.sort_unstable_by_key(|(brackets, _)| brackets.open);
+        let mut removed_after_number = BTreeSet::new();
+        let mut lints = Vec::new();
+        for (brackets, innermost) in pairs {
+            let after_number = brackets.after_number
+                || brackets.enclosing.is_some_and(|open| removed_after_number.contains(&open));
+            let precedence = needed.get(&brackets.node).copied().unwrap_or(0);
+            // Without them, what's inside would have to be multiplied by the number.
+            let separating = after_number && !brackets.multiplied;
+            if innermost && (separating || brackets.precedence < precedence) {
+                continue;
+            }
+            if after_number {
+                removed_after_number.insert(brackets.open);
+            }
+            let message = match &source {
+                Some(source) => {
+                    let contents: String = source[brackets.open + 1..brackets.close].iter().collect();
+                    format!("Redundant brackets around '{}'", contents.trim())
+                },
+                None => "Redundant brackets".to_string()
+            };
+            lints.push(Lint {
+                message,
+                start: brackets.open,
+                end: brackets.close + 1
+            });
+        }
+        Ok(lints)
+    }
+
+    /// Parses a program made up of any number of expressions
+    /// separated by semicolons, such as `1+1; 2*3; 4-1`.
+    /// 
+    /// ```text
+    /// <program> ::= <statement>? (Semicolon <statement>?)*
+    /// ```
+    /// 
+    /// Empty statements, such as a trailing `;`, are skipped,
+    /// so `1;;2;` produces two trees.
+    pub fn parse_program(&mut self) -> Result<Vec<Box<dyn Node>>, ParseError> {
+        self.previous_kind = TokenKind::Empty;
+        self.depth = 0;
+        self.brackets.clear();
+        self.current_token = self.tokeniser.next_token()?;
+
+        let mut statements = Vec::new();
+        loop {
+            while self.current_token.kind == TokenKind::Semicolon {
+                self.eat(TokenKind::Semicolon)?;
+            }
+            if self.current_token.kind == TokenKind::EOF {
+                return Ok(statements);
+            }
+
+            statements.push(self.statement()?);
+
+            // Each statement must be followed by a
+            // semicolon or the end of the program.
+            if !matches!(self.current_token.kind, TokenKind::Semicolon | TokenKind::EOF) {
+                return Err(self.trailing_token_error());
+            }
+        }
+    }
+
+    /// The error for a token which was found after a complete expression.
+    fn trailing_token_error(&self) -> ParseError {
+        match self.current_token.kind {
+            TokenKind::DotDot => ParseError::MisplacedRange {
+                pos: self.current_token.position()
+            },
+            TokenKind::Assign => ParseError::LoneEquals {
+                pos: self.current_token.position()
+            },
+            TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => ParseError::UnmatchedParenthesis {
+                pos: self.current_token.position()
+            },
+            _ => ParseError::TrailingToken {
+                kind: self.current_token.kind,
+                value: self.current_token.value.clone(),
+                pos: self.current_token.position()
+            }
+        }
     }
 
     /// Generates the next token.
@@ -59,151 +552,765 @@ impl Parser {
     /// 
     /// For example, if we want to eat an integer, but we get a bracket instead,
     /// we know there is an error in the expression.
-    fn eat(&mut self, expected_token_kind: TokenKind) -> Result<(), String> {
-        if self.current_token.kind != expected_token_kind {
-            Err(format!("Expected kind {:?}, got kind {:?}", expected_token_kind, self.current_token.kind))
+    fn eat(&mut self, expected_token_kind: TokenKind) -> Result<(), ParseError> {
+        if self.current_token.kind == TokenKind::Assign && expected_token_kind != TokenKind::Assign {
+            Err(ParseError::LoneEquals { pos: self.current_token.position() })
+        } else if self.current_token.kind == TokenKind::DotDot && expected_token_kind != TokenKind::DotDot {
+            Err(ParseError::MisplacedRange { pos: self.current_token.position() })
+        } else if self.current_token.kind == TokenKind::EOF && expected_token_kind != TokenKind::EOF {
+            Err(ParseError::UnexpectedEof {
+                expected: token_description(expected_token_kind),
+                pos: self.current_token.position()
+            })
+        } else if self.current_token.kind != expected_token_kind {
+            Err(ParseError::ExpectedToken {
+                expected: expected_token_kind,
+                found: self.current_token.kind,
+                pos: self.current_token.position()
+            })
         } else {
+            self.previous_kind = self.current_token.kind;
+            self.previous_end = self.current_token.end();
             self.current_token = self.tokeniser.next_token()?;
             Ok(())
         }
     }
 
+    /// Whether the token after the current token can begin an operand,
+    /// such as the `3` after the '%' in `10 % 3`.
+    fn operand_follows(&mut self) -> Result<bool, ParseError> {
+        Ok(matches!(self.tokeniser.peek_token()?.kind,
+            TokenKind::IntLiteral | TokenKind::FloatLiteral
+            | TokenKind::Ident | TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace))
+    }
+
+    /// Runs `parse` one level deeper into the expression,
+    /// erroring instead if that's deeper than the maximum depth.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, ParseError>) -> Result<T, ParseError> {
+        if self.depth >= self.max_depth {
+            return Err(ParseError::TooDeeplyNested {
+                max_depth: self.max_depth,
+                pos: self.current_token.position()
+            });
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Eats the closing bracket matching the `opening` bracket found at `opened`.
+    /// 
+    /// If the input ends first, the error points back at the opening
+    /// bracket, as that's usually more helpful than pointing at the end.
+    /// A different type of closing bracket, as in `(1]`, is also an error.
+    fn close_paren(&mut self, opening: TokenKind, opened: Position) -> Result<(), ParseError> {
+        let closing = match opening {
+            TokenKind::LParen => TokenKind::RParen,
+            TokenKind::LBracket => TokenKind::RBracket,
+            TokenKind::LBrace => TokenKind::RBrace,
+            TokenKind::BitOr => TokenKind::BitOr,
+            _ => unreachable!("{:?} isn't an opening bracket", opening)
+        };
+        match self.current_token.kind {
+            TokenKind::EOF => Err(ParseError::UnclosedParenthesis {
+                opened,
+                pos: self.current_token.position()
+            }),
+            kind @ (TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace) if kind != closing => Err(ParseError::MismatchedBracket {
+                opening: bracket_char(opening).unwrap_or_default(),
+                closing: bracket_char(kind).unwrap_or_default(),
+                opened,
+                pos: self.current_token.position()
+            }),
+            _ => self.eat(closing)
+        }
+    }
+
+    /// Represents the comma separated arguments of a function call,
+    /// such as the `1, 2, 3` in `max(1, 2, 3)`.
+    /// 
+    /// The list may be empty, as the number of arguments
+    /// is only checked against the function on evaluation.
+    /// With a decimal comma the arguments are separated by
+    /// semicolons instead, see `LocaleOptions`.
+    fn arguments(&mut self) -> Result<Vec<Box<dyn Node>>, ParseError> {
+        let mut arguments = Vec::new();
+        if self.current_token.kind == TokenKind::RParen {
+            return Ok(arguments);
+        }
+
+        let separator = match self.locale.decimal_separator {
+            DecimalSeparator::Point => TokenKind::Comma,
+            DecimalSeparator::Comma => TokenKind::Semicolon
+        };
+        arguments.push(self.expr()?);
+        while self.current_token.kind == separator {
+            self.eat(separator)?;
+            arguments.push(self.expr()?);
+        }
+        Ok(arguments)
+    }
+
+    /// The value of the current token, a literal written as `text`,
+    /// as a float. Errors if it's too large even for a float.
+    fn float_literal(&self, text: String) -> Result<ast::Number, ParseError> {
+        let value = text.parse::<ast::Number>()
+            .expect("the tokeniser should only produce valid number literals");
+        // Parsing a float never fails, it rounds to infinity instead.
+        if value.is_infinite() {
+            return Err(ParseError::LiteralOverflow {
+                value: text,
+                pos: self.current_token.position(),
+                context: self.tokeniser.context(self.current_token.position())
+            });
+        }
+        Ok(value)
+    }
+
     /// Represents any fundamental mathematical entity.
     /// 
     /// *Technically excluding a mathematical expression
     /// that contains no brackets but this is a limitation
     /// of existing terminology that defines groups of
     /// mathematical concepts. 
-    fn entity(&mut self) -> Result<Box<dyn Node>, String> {
+    fn entity(&mut self) -> Result<Box<dyn Node>, ParseError> {
         match self.current_token.kind {
             // Literals, things like '10' or '3.14'
             // Also referred to as constants.
+            // The text of a literal is converted into its value here,
+            // once, rather than every time the node is evaluated.
             TokenKind::IntLiteral => {
-                let ret = Box::new(ast::IntLiteral {
-                    value: self.current_token.value.clone().ok_or(0)
+                let text = self.current_token.value.clone().ok_or(0)
                     .expect("property `value` for a token of kind 
-                    `TokenKind::IntLiteral` should not be none")
-                });
+                    `TokenKind::IntLiteral` should not be none");
+                let start = self.current_token.position().index;
+                let end = self.current_token.end();
+                let ret: Box<dyn Node> = match text.parse::<i64>() {
+                    Ok(value) => self.build(ast::IntLiteral { value, start, end }),
+                    // Too large for an i64, so it's held as a float like `1e20`,
+                    // see `ast::IntLiteral`.
+                    Err(_) => self.build(ast::FloatLiteral { value: self.float_literal(text)?, start, end })
+                };
                 self.eat(TokenKind::IntLiteral)?;
                 Ok(ret)
             },
             TokenKind::FloatLiteral => {
-                let ret = Box::new(ast::FloatLiteral {
-                    value: self.current_token.value.clone().ok_or(0)
+                let text = self.current_token.value.clone().ok_or(0)
                     .expect("property `value` for a token of kind 
-                    `TokenKind::FloatLiteral` should not be none")
+                    `TokenKind::FloatLiteral` should not be none");
+                let ret = self.build(ast::FloatLiteral {
+                    value: self.float_literal(text)?,
+                    start: self.current_token.position().index,
+                    end: self.current_token.end()
                 });
                 self.eat(TokenKind::FloatLiteral)?;
                 Ok(ret)
             },
 
-            // All unary operations begin with a '-' symbol.
-            TokenKind::Sub => {
-                self.eat(TokenKind::Sub)?;
-                Ok(Box::new(ast::UnaryOp {
-                    right: self.entity()?,
-                    op: ast::Op::Sub
-                }))
-            }
+            // Identifiers either name a function, when followed by
+            // brackets, a constant which is resolved into its value here,
+            // or otherwise a variable which is looked up during evaluation.
+            TokenKind::Ident => {
+                let name = self.current_token.value.clone().ok_or(0)
+                    .expect("property `value` for a token of kind 
+                    `TokenKind::Ident` should not be none");
+                let start = self.current_token.position().index;
+                self.eat(TokenKind::Ident)?;
+
+                if self.current_token.kind == TokenKind::LParen {
+                    let opened = self.current_token.position();
+                    self.eat(TokenKind::LParen)?;
+                    let arguments = self.nested(Self::arguments)?;
+                    self.close_paren(TokenKind::LParen, opened)?;
+                    let end = self.previous_end;
+                    return Ok(self.build(ast::FunctionCall { name, arguments, start, end }));
+                }
+
+                let end = self.previous_end;
+                match self.constant(&name) {
+                    Some(value) => Ok(self.build(ast::Constant { name, value, start, end })),
+                    None => Ok(self.build(ast::Variable { name, start, end }))
+                }
+            },
 
             // Brackets aren't an object found on the syntax tree,
             // rather, it changes the structure of the tree to
             // represent the order defined by the brackets.
-            TokenKind::LParen => {
-                self.eat(TokenKind::LParen)?;
-                let expr = self.expr()?;
-                self.eat(TokenKind::RParen)?;
+            // Square brackets and braces behave exactly like
+            // parentheses, but must be closed by their own type.
+            opening @ (TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace) => {
+                let opened = self.current_token.position();
+                let after_number = self.implicit_mult();
+                let enclosing = matches!(self.previous_kind, TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace)
+                    .then(|| self.previous_end - 1);
+                self.eat(opening)?;
+                let multiplied = matches!(self.current_token.kind,
+                    TokenKind::Ident | TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace);
+                let expr = self.nested(Self::expr)?;
+                self.close_paren(opening, opened)?;
+                self.brackets.push(Brackets {
+                    open: opened.index,
+                    close: self.previous_end - 1,
+                    node: ast::address(expr.as_ref()),
+                    precedence: expr.precedence(),
+                    after_number,
+                    multiplied,
+                    enclosing
+                });
                 Ok(expr)
+            },
+
+            // Bars around an expression, such as `|x|`, take its absolute
+            // value, and are parsed as a call to `abs`. A bar where an operand
+            // is expected opens them, and any other bar is a bitwise or, so
+            // the contents are parsed without '|' or anything looser. This
+            // means the first bar after the contents always closes them,
+            // and bars can nest as long as each opening bar is where an
+            // operand is expected, as in `| |x| - 1|`. Note that `||` is
+            // always the `or` operator, so nested bars need a space between.
+            TokenKind::BitOr => {
+                let opened = self.current_token.position();
+                self.eat(TokenKind::BitOr)?;
+                let argument = self.nested(|parser| parser.binary(ast::Op::BitOr.precedence() + 1))?;
+                self.close_paren(TokenKind::BitOr, opened)?;
+                Ok(self.build(ast::FunctionCall {
+                    name: "abs".to_string(),
+                    arguments: vec![argument],
+                    start: opened.index,
+                    end: self.previous_end
+                }))
             }
 
+            TokenKind::Assign => Err(ParseError::LoneEquals { pos: self.current_token.position() }),
+
+            // The input ended too soon, such as after an operator,
+            // which more input could still complete.
+            TokenKind::EOF => Err(ParseError::UnexpectedEof {
+                expected: "an operand".to_string(),
+                pos: self.current_token.position()
+            }),
+
             // If we encounter any other type of token, this is unexpected so error.
-            _ => {
-                Err(format!("Unexpected token: {:?} at pos {:?}", self.current_token, self.tokeniser.char_pos))
+            kind => {
+                // An operator straight after another, such as in `1 */ 2`,
+                // is most likely a typo, so suggest what was meant.
+                let symbols = (arithmetic_symbol(self.previous_kind), arithmetic_symbol(kind));
+                if let (Some(first), Some(second)) = symbols {
+                    let suggestions = match (first, second) {
+                        ("*", "*") => vec!["^".to_string()],
+                        _ if first == second => vec![first.to_string()],
+                        _ => vec![first.to_string(), second.to_string()]
+                    };
+                    return Err(ParseError::MistypedOperator {
+                        found: format!("{}{}", first, second),
+                        suggestions,
+                        pos: self.current_token.position()
+                    });
+                }
+                Err(ParseError::UnexpectedToken {
+                    kind: self.current_token.kind,
+                    value: self.current_token.value.clone(),
+                    pos: self.current_token.position()
+                })
             }
         }
     }
 
-    /// Represents any mathematical expression containing two or more terms.
-    fn expr(&mut self) -> Result<Box<dyn Node>, String> {
-        // Get the left hand side of the expression.
-        let mut node = self.mult_expr()?;
-
-        // If the expression contains no relevant operators beyond this point,
-        // we just return the entity as it is.
-
-        // Else:
-        // While the operator is either a '*' or '/'
-        while self.current_token.kind == TokenKind::Add
-            || self.current_token.kind == TokenKind::Sub {
-                
-                // Eat the token and map the
-                // tokeniser::TokenKind to the matching ast::Op
-                let op = match self.current_token.kind {
-                    TokenKind::Add => {
-                        self.eat(TokenKind::Add)?;
-                        ast::Op::Add
-                    },
-                    TokenKind::Sub => {
-                        self.eat(TokenKind::Sub)?;
-                        ast::Op::Sub
-                    },
-                    _ => unreachable!()
-                };
+    /// Represents a statement, which is either an assignment
+    /// such as `x = 5`, or otherwise an expression.
+    /// 
+    /// An assignment binds the variable when it's evaluated, and evaluates
+    /// to the value assigned, so the REPL prints `5` for `x = 5`. As it can
+    /// only be the whole of a statement, it can't be used within another
+    /// expression, so `1 + (x = 5)` and `x = y = 5` are errors.
+    fn statement(&mut self) -> Result<Box<dyn Node>, ParseError> {
+        if self.current_token.kind != TokenKind::Ident
+            || self.tokeniser.peek_token()?.kind != TokenKind::Assign {
+            return self.expr();
+        }
 
-                // Create a binary operation object.
-                // As this code loops, `left` will become the BinOp
-                // from the previous iteration.
-                node = Box::new(ast::BinOp {
-                    left: node,
-                    right: self.mult_expr()?,
-                    op
-                })
+        let name = self.current_token.value.clone().ok_or(0)
+            .expect("property `value` for a token of kind 
+            `TokenKind::Ident` should not be none");
+        let pos = self.current_token.position();
+        if self.constant(&name).is_some() {
+            return Err(ParseError::AssignToConstant { name, pos });
+        }
+        self.eat(TokenKind::Ident)?;
+        self.eat(TokenKind::Assign)?;
+        let value = self.expr()?;
+        Ok(self.build(ast::Assignment { name, value, start: pos.index, end: self.previous_end }))
+    }
+
+    /// Represents any expression, including conditionals
+    /// such as `x > 0 ? x : -x`.
+    /// 
+    /// A conditional binds more loosely than any operator, and is
+    /// right-associative, so `a ? b : c ? d : e` is parsed as
+    /// `a ? b : (c ? d : e)`. Only the branch which is chosen by
+    /// the condition is evaluated.
+    fn expr(&mut self) -> Result<Box<dyn Node>, ParseError> {
+        let start = self.current_token.position().index;
+        // Every operator binds more tightly than a conditional.
+        let condition = self.binary(0)?;
+        if self.current_token.kind != TokenKind::Question {
+            return Ok(condition);
+        }
+
+        self.eat(TokenKind::Question)?;
+        let if_true = self.nested(Self::expr)?;
+        self.eat(TokenKind::Colon)?;
+        let if_false = self.nested(Self::expr)?;
+        let end = self.previous_end;
+        Ok(self.build(ast::Conditional { condition, if_true, if_false, start, end }))
+    }
+
+    /// Represents any chain of operators, such as `1 + 2 * -x`,
+    /// where every operator binds at least as tightly as `min_precedence`.
+    /// 
+    /// This is a precedence climbing parser: each operator's right hand
+    /// side is parsed by a recursive call which only accepts operators
+    /// binding more tightly than it, so `1 + 2 * 3` is `1 + (2 * 3)`
+    /// while `1 * 2 + 3` is `(1 * 2) + 3`. How tightly each operator
+    /// binds is given by `ast::Op::precedence()`, and which operator each
+    /// token stands for by `infix_op()`, so a new operator only needs a
+    /// row in each.
+    /// 
+    /// Operators of equal precedence are grouped as given by
+    /// `ast::Op::associativity()`. Most are left-associative, so `10 - 2 - 3`
+    /// is `(10 - 2) - 3`, while '^' is right-associative, so `2^3^2` is
+    /// `2^(3^2)`, and the comparisons chain, see `comparisons()`.
+    fn binary(&mut self, min_precedence: u8) -> Result<Box<dyn Node>, ParseError> {
+        // Every operation in the chain starts with the first operand,
+        // including any bracket before it.
+        let start = self.current_token.position().index;
+        let mut node = self.prefix(min_precedence)?;
+
+        loop {
+            let kind = self.current_token.kind;
+            // There's no token to eat for an implicit '*'
+            let implicit = self.implicit_mult();
+            let op = match infix_op(kind) {
+                _ if implicit => ast::Op::Mult,
+                Some(op) => op,
+                None => break
+            };
+            if op.precedence() < min_precedence {
+                break;
+            }
+            if op.is_comparison() {
+                node = self.comparisons(node, start)?;
+                continue;
+            }
+
+            let op_pos = self.current_token.position();
+            if !implicit {
+                self.eat(kind)?;
+            }
+            let right = match op {
+                // The exponent may be signed, as in `2^-1`, so it's
+                // parsed at the precedence of a sign.
+                ast::Op::Pow => self.nested(|parser| parser.binary(ast::UNARY_PRECEDENCE))?,
+                ast::Op::In => self.range()?,
+                // A right-associative operator accepts itself on its right.
+                _ if op.associativity() == ast::Associativity::Right => self.binary(op.precedence())?,
+                _ => self.binary(op.precedence() + 1)?
             };
 
+            // Create a binary operation object.
+            // As this code loops, `left` will become the BinOp
+            // from the previous iteration.
+            let op = op.with_percentage(right.as_ref());
+            node = self.build(ast::BinOp { left: node, right, op, op_pos, start, end: self.previous_end });
+        }
+
         Ok(node)
+    }
 
+    /// Represents an operand along with any signs or `not` before it,
+    /// such as `-3`, `+3`, `-+(1+2)` or `not x`.
+    /// 
+    /// Negation binds more loosely than exponentiation, so `-2^2`
+    /// is parsed as `-(2^2)` and evaluates to `-4`, matching the
+    /// usual mathematical convention. Use `(-2)^2` to square a
+    /// negative number. Unary plus doesn't change the value, but is
+    /// still kept in the tree so it reflects the source.
+    /// 
+    /// `not` binds more loosely than comparisons, so `not 1 < 2`
+    /// is `not (1 < 2)`. As it binds more loosely than arithmetic,
+    /// it can't be an operand of it, so `1 + not 0` is an error.
+    fn prefix(&mut self, min_precedence: u8) -> Result<Box<dyn Node>, ParseError> {
+        let kind = self.current_token.kind;
+        let op = match prefix_op(kind) {
+            Some(op) if op.precedence() >= min_precedence => op,
+            _ => return self.postfix()
+        };
+
+        let start = self.current_token.position().index;
+        self.eat(kind)?;
+        let right = self.nested(|parser| parser.binary(op.precedence()))?;
+        Ok(self.build(ast::UnaryOp { right, op, start, end: self.previous_end }))
     }
 
-    /// Represents any mathematical expression containing two or
-    /// more terms using only the '*' and '/' operators.
-    fn mult_expr(&mut self) -> Result<Box<dyn Node>, String> {
-        // Get the left hand side of the expression.
-        let mut node = self.entity()?;
+    /// Represents the range after `in`, such as the `1..10` in `x in 1..10`.
+    /// 
+    /// Each bound is parsed like the operand of a comparison. A range
+    /// has no value of its own, so this is the only place one can be,
+    /// and a '..' found anywhere else is a `ParseError::MisplacedRange`.
+    fn range(&mut self) -> Result<Box<dyn Node>, ParseError> {
+        let bound_precedence = ast::Op::In.precedence() + 1;
+        let start = self.current_token.position().index;
+        let low = self.binary(bound_precedence)?;
+        self.eat(TokenKind::DotDot)?;
+        let high = self.binary(bound_precedence)?;
+        Ok(self.build(ast::Range { low, high, start, end: self.previous_end }))
+    }
 
-        // If the expression contains no relevant operators beyond this point,
-        // we just return the entity as it is.
-
-        // Else:
-        // While the operator is either a '*' or '/'
-        while self.current_token.kind == TokenKind::Mult
-            || self.current_token.kind == TokenKind::Div {
-                
-                // Eat the token and map the
-                // tokeniser::TokenKind to the matching ast::Op
-                let op = match self.current_token.kind {
-                    TokenKind::Mult => {
-                        self.eat(TokenKind::Mult)?;
-                        ast::Op::Mult
-                    },
-                    TokenKind::Div => {
-                        self.eat(TokenKind::Div)?;
-                        ast::Op::Div
-                    },
-                    _ => unreachable!()
-                };
+    /// Represents a chain of comparisons after `left`, which
+    /// starts at `start`, such as the `<= 3` in `1 + 2 <= 3`.
+    /// 
+    /// Comparisons evaluate to 1 when true or 0 when false. Like in
+    /// maths, they can be chained, so `1 < x < 10` means `1 < x` and
+    /// `x < 10`, rather than comparing the result of `1 < x` with `10`.
+    /// A single comparison is a `BinOp`, and a chain of them is an
+    /// `ast::ComparisonChain`, which only evaluates `x` once.
+    fn comparisons(&mut self, left: Box<dyn Node>, start: usize) -> Result<Box<dyn Node>, ParseError> {
+        let mut operands = vec![left];
+        let mut ops = Vec::new();
+        // Where the first comparison is, in case it's the only one.
+        let op_pos = self.current_token.position();
 
-                // Create a binary operation object.
-                // As this code loops, `left` will become the BinOp
-                // from the previous iteration.
-                node = Box::new(ast::BinOp {
-                    left: node,
-                    right: self.entity()?,
-                    op
-                })
+        while let Some(op) = infix_op(self.current_token.kind).filter(ast::Op::is_comparison) {
+            self.eat(self.current_token.kind)?;
+            operands.push(self.binary(op.precedence() + 1)?);
+            ops.push(op);
+        }
+
+        if ops.len() == 1 {
+            let right = operands.pop().expect("a comparison has two operands");
+            let left = operands.pop().expect("a comparison has two operands");
+            let end = self.previous_end;
+            return Ok(self.build(ast::BinOp { left, right, op: ops[0], op_pos, start, end }));
+        }
+        Ok(self.build(ast::ComparisonChain { operands, ops, start, end: self.previous_end }))
+    }
+
+    /// Whether an implicit '*' sits between the previous token
+    /// and the current one, e.g. between the `2` and `x` in `2x`.
+    /// 
+    /// The implicit '*' has the same precedence as an explicit one,
+    /// so `2(3)^2` is `2 * 3^2 = 18` and `1/2x` is `(1/2) * x`. Only
+    /// a number may come first, so `(1)(2)` and `1 2` are still errors.
+    fn implicit_mult(&self) -> bool {
+        matches!(self.previous_kind, TokenKind::IntLiteral | TokenKind::FloatLiteral)
+            && matches!(self.current_token.kind,
+                TokenKind::Ident | TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace)
+    }
+
+    /// Represents an entity followed by any number of postfix
+    /// operators, such as `5!` or `(2+1)!!`.
+    /// 
+    /// Postfix operators bind more tightly than any other operator,
+    /// so `2^3!` is parsed as `2^(3!)` and `-3!` as `-(3!)`.
+    /// 
+    /// In percent mode a '%' may also be a postfix percentage,
+    /// see `set_percent_mode()`.
+    fn postfix(&mut self) -> Result<Box<dyn Node>, ParseError> {
+        let start = self.current_token.position().index;
+        let mut node = self.entity()?;
+
+        loop {
+            let kind = self.current_token.kind;
+            let op = match kind {
+                TokenKind::Factorial => ast::Op::Factorial,
+                TokenKind::Mod if self.percent_mode && !self.operand_follows()? => ast::Op::Percent,
+                _ => break
             };
+            self.eat(kind)?;
+            node = self.build(ast::PostfixOp {
+                left: node,
+                op,
+                start,
+                end: self.previous_end
+            });
+        }
 
         Ok(node)
     }
 
+    /// Boxes `node` to add to the tree, or while validating, when
+    /// there's no tree, a placeholder which doesn't need allocating.
+    fn build(&self, node: impl Node + 'static) -> Box<dyn Node> {
+        if self.building {
+            Box::new(node)
+        } else {
+            Box::new(ast::Placeholder)
+        }
+    }
+
+    /// The value of the constant called `name`, whether defined with
+    /// `define_constant()` or built in, or `None` if it names a variable.
+    fn constant(&self, name: &str) -> Option<ast::Number> {
+        self.constants.get(name).copied().or_else(|| constant(name))
+    }
+
+
+}
+
+/// The value of the constant called `name`, or `None` if it names a variable.
+/// 
+/// `inf` and `nan` are matched case-insensitively, so that every float
+/// written by `Display`, such as `NaN`, can be read back in.
+pub(crate) fn constant(name: &str) -> Option<ast::Number> {
+    match name {
+        "pi" => Some(core::f64::consts::PI as ast::Number),
+        "e" => Some(core::f64::consts::E as ast::Number),
+        "tau" => Some(core::f64::consts::TAU as ast::Number),
+        _ if name.eq_ignore_ascii_case("inf") => Some(ast::Number::INFINITY),
+        _ if name.eq_ignore_ascii_case("nan") => Some(ast::Number::NAN),
+        _ => None
+    }
+}
+
+/// How a token of `kind` is described when it was expected but the
+/// input ended, quoting it as written where it's a single symbol.
+fn token_description(kind: TokenKind) -> String {
+    match kind {
+        TokenKind::Colon => "':'".to_string(),
+        TokenKind::DotDot => "'..'".to_string(),
+        TokenKind::Assign => "'='".to_string(),
+        TokenKind::Comma => "','".to_string(),
+        kind => match bracket_char(kind) {
+            Some(bracket) => format!("'{}'", bracket),
+            None => format!("{:?}", kind)
+        }
+    }
+}
+
+/// The char of a bracket token, such as `]` for `TokenKind::RBracket`
+/// or `|` for an absolute value bar, or `None` for any other kind of token.
+fn bracket_char(kind: TokenKind) -> Option<char> {
+    match kind {
+        TokenKind::LParen => Some('('),
+        TokenKind::RParen => Some(')'),
+        TokenKind::LBracket => Some('['),
+        TokenKind::RBracket => Some(']'),
+        TokenKind::LBrace => Some('{'),
+        TokenKind::RBrace => Some('}'),
+        TokenKind::BitOr => Some('|'),
+        _ => None
+    }
+}
+
+/// The operation performed by a token found before its operand,
+/// such as the '-' in `-x`.
+fn prefix_op(kind: TokenKind) -> Option<ast::UnaryKind> {
+    match kind {
+        TokenKind::Sub => Some(ast::UnaryKind::Neg),
+        TokenKind::Add => Some(ast::UnaryKind::Pos),
+        TokenKind::Not => Some(ast::UnaryKind::Not),
+        _ => None
+    }
+}
 
-}
\ No newline at end of file
+/// The operation performed by a token found between two operands,
+/// such as the '*' in `2 * x`.
+fn infix_op(kind: TokenKind) -> Option<ast::Op> {
+    match kind {
+        // `||` and `&&` are the same as `or` and `and`.
+        TokenKind::Or => Some(ast::Op::Or),
+        TokenKind::And => Some(ast::Op::And),
+        TokenKind::Equal => Some(ast::Op::Equal),
+        TokenKind::NotEqual => Some(ast::Op::NotEqual),
+        TokenKind::Less => Some(ast::Op::Less),
+        TokenKind::LessEqual => Some(ast::Op::LessEqual),
+        TokenKind::Greater => Some(ast::Op::Greater),
+        TokenKind::GreaterEqual => Some(ast::Op::GreaterEqual),
+        TokenKind::In => Some(ast::Op::In),
+        // The bitwise operators require integer operands.
+        TokenKind::BitOr => Some(ast::Op::BitOr),
+        TokenKind::BitXor => Some(ast::Op::BitXor),
+        TokenKind::BitAnd => Some(ast::Op::BitAnd),
+        TokenKind::ShiftLeft => Some(ast::Op::ShiftLeft),
+        TokenKind::ShiftRight => Some(ast::Op::ShiftRight),
+        TokenKind::Add => Some(ast::Op::Add),
+        TokenKind::Sub => Some(ast::Op::Sub),
+        TokenKind::Mult => Some(ast::Op::Mult),
+        TokenKind::Div => Some(ast::Op::Div),
+        // '%' is the remainder of a truncating division, so the result
+        // has the same sign as the left hand side: `-7 % 3` is `-1`.
+        TokenKind::Mod => Some(ast::Op::Mod),
+        TokenKind::Pow => Some(ast::Op::Pow),
+        _ => None
+    }
+}
+
+/// The symbol of an arithmetic operator which can only appear between
+/// two operands, or `None` for any other kind of token.
+fn arithmetic_symbol(kind: TokenKind) -> Option<&'static str> {
+    match kind {
+        TokenKind::Mult => Some("*"),
+        TokenKind::Div => Some("/"),
+        TokenKind::Mod => Some("%"),
+        TokenKind::Pow => Some("^"),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Number;
+    use crate::error::EvalError;
+
+    fn evaluate(source: &str) -> Result<Number, EvalError> {
+        Parser::new(source).parse().unwrap().evaluate()
+    }
+
+    fn infix(source: &str) -> String {
+        Parser::new(source).parse().unwrap().to_infix()
+    }
+
+    #[test]
+    fn chained_exponents_are_right_associative() {
+        assert_eq!(evaluate("2 ^ 3 ^ 2"), Ok(512.0));
+        assert_eq!(evaluate("(2 ^ 3) ^ 2"), Ok(64.0));
+        assert_eq!(evaluate("2 ^ 2 ^ 2 ^ 2"), Ok(65536.0));
+        assert_eq!(infix("2 ^ (3 ^ 2)"), "2 ^ 3 ^ 2");
+        assert_eq!(infix("(2 ^ 3) ^ 2"), "(2 ^ 3) ^ 2");
+    }
+
+    #[test]
+    fn negation_binds_more_loosely_than_exponents() {
+        assert_eq!(evaluate("-2 ^ 2"), Ok(-4.0));
+        assert_eq!(evaluate("(-2) ^ 2"), Ok(4.0));
+        assert_eq!(evaluate("2 ^ -1"), Ok(0.5));
+        assert_eq!(infix("-2 ^ 2"), "-2 ^ 2");
+    }
+
+    #[test]
+    fn unary_plus_is_kept_in_the_tree() {
+        assert_eq!(evaluate("+5"), Ok(5.0));
+        assert_eq!(evaluate("-+-5"), Ok(5.0));
+        assert_eq!(evaluate("+-5"), Ok(-5.0));
+
+        let tree = Parser::new("-+-5").parse().unwrap();
+        let neg = tree.as_any().downcast_ref::<ast::UnaryOp>().unwrap();
+        assert_eq!(neg.op, ast::UnaryKind::Neg);
+        let pos = neg.right.as_any().downcast_ref::<ast::UnaryOp>().unwrap();
+        assert_eq!(pos.op, ast::UnaryKind::Pos);
+        let inner = pos.right.as_any().downcast_ref::<ast::UnaryOp>().unwrap();
+        assert_eq!(inner.op, ast::UnaryKind::Neg);
+        assert_eq!(tree.to_infix(), "-+-5");
+    }
+
+    #[test]
+    fn spans_cover_the_source_of_each_node() {
+        let tree = Parser::new("1 + 2").parse().unwrap();
+        assert_eq!(tree.span(), (0, 5));
+        let children = tree.children();
+        assert_eq!(children[0].span(), (0, 1));
+        assert_eq!(children[1].span(), (4, 5));
+
+        // Brackets around a node aren't part of its span,
+        // but brackets within it are.
+        let tree = Parser::new("(1 + 2) * 3").parse().unwrap();
+        assert_eq!(tree.span(), (0, 11));
+        assert_eq!(tree.children()[0].span(), (1, 6));
+        for source in ["2 * (1 + 2)", "-(1 + 2)", "(3)!", "(1) < (2) < (3)", "(1) ? (2) : (3)", "x in (1)..(2)"] {
+            let tree = Parser::new(source).parse().unwrap();
+            assert_eq!(tree.span(), (0, source.len()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn bars_take_the_absolute_value() {
+        assert_eq!(evaluate("|-3|"), Ok(3.0));
+        assert_eq!(evaluate("|2 - 5| + 1"), Ok(4.0));
+        assert_eq!(evaluate("-|-3|"), Ok(-3.0));
+
+        let tree = Parser::new("|x| + 1").parse().unwrap();
+        assert_eq!(tree.to_infix(), "abs(x) + 1");
+        let mut env = ast::Environment::new();
+        env.insert("x", -2.0);
+        assert_eq!(tree.evaluate_with(&env), Ok(3.0));
+    }
+
+    #[test]
+    fn nested_bars_need_a_space_between() {
+        assert_eq!(evaluate("| |-3| - 5|"), Ok(2.0));
+        assert_eq!(infix("| |x| - 1|"), "abs(abs(x) - 1)");
+        // `||` is always the `or` operator.
+        assert!(matches!(
+            Parser::new("||x| - 1|").parse(),
+            Err(ParseError::UnexpectedToken { kind: TokenKind::Or, .. })
+        ));
+    }
+
+    #[test]
+    fn implicit_multiplication_binds_like_an_explicit_one() {
+        assert_eq!(evaluate("2(3)^2"), Ok(18.0));
+        assert_eq!(evaluate("1/2(4)"), Ok(2.0));
+        assert_eq!(infix("2pi"), "2 * pi");
+    }
+
+    #[test]
+    fn decimal_integers_too_large_for_an_integer_are_floats() {
+        assert_eq!(evaluate("100000000000000000000"), Ok(1e20));
+        assert_eq!(infix("9223372036854775807"), "9223372036854775807");
+        assert_eq!(infix("9223372036854775808"), "9.223372036854776e18");
+    }
+
+    #[test]
+    fn brackets_are_closed_by_their_own_type() {
+        assert_eq!(evaluate("[1 + 2] * {3 - 1}"), evaluate("(1 + 2) * (3 - 1)"));
+        assert_eq!(evaluate("{[(1)] + 2}"), Ok(3.0));
+
+        let error = |source: &str| Parser::new(source).parse().err();
+        assert!(matches!(
+            error("(1]"),
+            Some(ParseError::MismatchedBracket { opening: '(', closing: ']', opened, pos })
+                if opened.index == 0 && pos.index == 2
+        ));
+        assert!(matches!(error("[1 + {2]}"), Some(ParseError::MismatchedBracket { opening: '{', closing: ']', .. })));
+        assert!(matches!(error("1 + 2}"), Some(ParseError::UnmatchedParenthesis { .. })));
+    }
+
+    #[test]
+    fn a_source_without_an_expression_is_empty() {
+        assert_eq!(Parser::new("").parse().err(), Some(ParseError::EmptyInput));
+        assert_eq!(Parser::new("  # nothing here").parse().err(), Some(ParseError::EmptyInput));
+        assert!(Parser::new("").parse_program().is_ok_and(|program| program.is_empty()));
+    }
+
+    #[test]
+    fn tokens_after_a_complete_expression_are_trailing() {
+        let trailing = |source: &str| match Parser::new(source).parse().err() {
+            Some(ParseError::TrailingToken { kind, value, pos }) => Some((kind, value, pos.index)),
+            _ => None
+        };
+        assert_eq!(trailing("1 2"), Some((TokenKind::IntLiteral, Some("2".to_string()), 2)));
+        assert_eq!(trailing("1 + 2 3"), Some((TokenKind::IntLiteral, Some("3".to_string()), 6)));
+        // Only a number is implicitly multiplied by the bracket after it.
+        assert_eq!(trailing("(1)(2)"), Some((TokenKind::LParen, None, 3)));
+    }
+
+    #[test]
+    fn unexpected_eof_says_what_was_expected() {
+        let error = |source: &str| Parser::new(source).parse().err().unwrap();
+        let expected = |source: &str| match error(source) {
+            ParseError::UnexpectedEof { expected, .. } => Some(expected),
+            _ => None
+        };
+        assert_eq!(expected("1 +").as_deref(), Some("an operand"));
+        assert_eq!(expected("-").as_deref(), Some("an operand"));
+        assert_eq!(expected("x > 0 ? 1").as_deref(), Some("':'"));
+        assert_eq!(expected("x in 1").as_deref(), Some("'..'"));
+        assert!(error("2 *").is_incomplete());
+        assert_eq!(error("1 +").to_string(), "Expected an operand, but the input ended at line 1, column 4");
+
+        // A hard syntax error, which more input can't fix.
+        assert!(matches!(error("1 + )"), ParseError::UnexpectedToken { .. }));
+    }
+}
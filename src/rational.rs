@@ -0,0 +1,213 @@
+//! Exact arithmetic on fractions, used by `Node::evaluate_rational_with()`.
+//!
+//! Only available with the `rational` feature.
+
+use core::{fmt, ops};
+use alloc::format;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Pow, Signed, ToPrimitive, Zero};
+
+use crate::ast::Number;
+
+/// The most bits the numerator or denominator of a power or factorial
+/// may need, which is about 20,000 decimal digits.
+///
+/// A power or factorial estimated to need more fails with
+/// `EvalError::Overflow` rather than being calculated, which for
+/// `2^(10^9)` or `(10^6)!` would take far too long and too much memory.
+///
+/// ```
+/// use simple_expression_parser::{Parser, EvalError};
+///
+/// let rational = |source: &str| Parser::new(source).parse().unwrap().evaluate_rational();
+/// assert!(rational("2^10000").is_ok_and(|value| value.numer().bits() == 10_001));
+/// assert!(rational("(1/3)^-5000").is_ok());
+/// assert!(rational("1000!").is_ok());
+/// assert_eq!(rational("1^(10^12)").map(|value| value.to_f64()), Ok(1.0));
+/// assert_eq!(rational("2^(10^9)"), Err(EvalError::Overflow { expression: "2 ^ 10 ^ 9".to_string() }));
+/// assert_eq!(rational("(10^6)!"), Err(EvalError::Overflow { expression: "(10 ^ 6)!".to_string() }));
+/// ```
+pub const MAX_BITS: u64 = 1 << 16;
+
+/// A fraction of arbitrary precision, always kept in its lowest terms.
+///
+/// ```
+/// use simple_expression_parser::Rational;
+///
+/// let third = Rational::new(1, 3);
+/// assert_eq!(third.clone() + third.clone() + third, Rational::from(1));
+/// assert_eq!(Rational::new(2, -4).to_string(), "-1/2");
+/// assert_eq!(Rational::new(1, 4).to_f64(), 0.25);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rational(BigRational);
+
+impl Rational {
+    /// The fraction `numer / denom`.
+    ///
+    /// Panics if `denom` is zero.
+    pub fn new(numer: i64, denom: i64) -> Self {
+        Rational(BigRational::new(numer.into(), denom.into()))
+    }
+
+    /// The numerator, which carries the sign of the fraction.
+    pub fn numer(&self) -> &BigInt {
+        self.0.numer()
+    }
+
+    /// The denominator, which is always positive.
+    pub fn denom(&self) -> &BigInt {
+        self.0.denom()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.0.is_integer()
+    }
+
+    /// The nearest float to the fraction, such as for display.
+    /// Fractions too large for a float become infinite.
+    pub fn to_f64(&self) -> Number {
+        self.0.to_f64().unwrap_or(Number::NAN)
+    }
+
+    /// The fraction written by the shortest decimal which reads back as
+    /// `value`, so `0.1` is exactly `1/10` rather than the binary fraction
+    /// closest to it. Infinities and NaN have no fraction.
+    pub(crate) fn from_number(value: Number) -> Option<Self> {
+        if !value.is_finite() {
+            return None;
+        }
+        // Displaying a float never uses an exponent, so
+        // this is always just digits and a decimal point.
+        let decimal = format!("{}", value);
+        let (whole, fraction) = decimal.split_once('.').unwrap_or((&decimal, ""));
+        let numer: BigInt = format!("{}{}", whole, fraction).parse().ok()?;
+        let denom = BigInt::from(10).pow(fraction.len() as u32);
+        Some(Rational(BigRational::new(numer, denom)))
+    }
+
+    /// Converts the result of a comparison into a fraction,
+    /// 1 for true and 0 for false.
+    pub(crate) fn truth(value: bool) -> Self {
+        Rational::from(value as i64)
+    }
+
+    pub(crate) fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    /// The fraction as an i64, if it's an integer in range.
+    pub(crate) fn to_i64(&self) -> Option<i64> {
+        if self.is_integer() { self.numer().to_i64() } else { None }
+    }
+
+    /// The fraction raised to the integer power `exponent`, or `None` if
+    /// the result could need more than `MAX_BITS` bits. Panics if the
+    /// fraction is zero and `exponent` is negative.
+    pub(crate) fn checked_pow(&self, exponent: i64) -> Option<Self> {
+        // Only 0, 1 and -1 keep the same size whatever the exponent.
+        let bits = match self.is_integer() && self.numer().bits() <= 1 {
+            true => 0,
+            false => self.numer().bits().max(self.denom().bits())
+        };
+        if bits.saturating_mul(exponent.unsigned_abs()) > MAX_BITS {
+            return None;
+        }
+        Some(Rational(Pow::pow(&self.0, exponent)))
+    }
+
+    /// The factorial of `n`, or `None` if it could need more than `MAX_BITS` bits.
+    pub(crate) fn checked_factorial(n: u64) -> Option<Self> {
+        // n! is less than n^n, which needs n times as many bits as n.
+        let bits = u64::from(u64::BITS - n.leading_zeros());
+        if n.saturating_mul(bits) > MAX_BITS {
+            return None;
+        }
+        let factorial = (2..=n).fold(BigInt::from(1), |result, n| result * n);
+        Some(Rational(BigRational::from_integer(factorial)))
+    }
+
+    pub(crate) fn abs(&self) -> Self {
+        Rational(self.0.abs())
+    }
+
+    pub(crate) fn floor(&self) -> Self {
+        Rational(self.0.floor())
+    }
+
+    pub(crate) fn ceil(&self) -> Self {
+        Rational(self.0.ceil())
+    }
+
+    /// Rounds half-way cases away from zero, like `f64::round()`.
+    pub(crate) fn round(&self) -> Self {
+        Rational(self.0.round())
+    }
+}
+
+impl From<i64> for Rational {
+    fn from(value: i64) -> Self {
+        Rational(BigRational::from_integer(value.into()))
+    }
+}
+
+/// Integers are written on their own, and
+/// other fractions as `numer/denom`.
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl ops::Add for Rational {
+    type Output = Rational;
+
+    fn add(self, other: Rational) -> Rational {
+        Rational(self.0 + other.0)
+    }
+}
+
+impl ops::Sub for Rational {
+    type Output = Rational;
+
+    fn sub(self, other: Rational) -> Rational {
+        Rational(self.0 - other.0)
+    }
+}
+
+impl ops::Mul for Rational {
+    type Output = Rational;
+
+    fn mul(self, other: Rational) -> Rational {
+        Rational(self.0 * other.0)
+    }
+}
+
+/// Panics if `other` is zero.
+impl ops::Div for Rational {
+    type Output = Rational;
+
+    fn div(self, other: Rational) -> Rational {
+        Rational(self.0 / other.0)
+    }
+}
+
+/// The remainder has the same sign as `self`, like `%` on floats.
+/// Panics if `other` is zero.
+impl ops::Rem for Rational {
+    type Output = Rational;
+
+    fn rem(self, other: Rational) -> Rational {
+        Rational(self.0 % other.0)
+    }
+}
+
+impl ops::Neg for Rational {
+    type Output = Rational;
+
+    fn neg(self) -> Rational {
+        Rational(-self.0)
+    }
+}
@@ -0,0 +1,366 @@
+//! Reading syntax trees written as S-expressions, such as `(+ 1 (* 2 3))`,
+//! the format written by `Node::to_sexpr()`.
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec
+};
+
+use crate::{
+    tokeniser::{self, Position},
+    ast::{self, Node, Op, UnaryKind},
+    error::ParseError,
+    parser::{self, DEFAULT_MAX_DEPTH}
+};
+
+/// The operations written as a list with two operands, such as `(+ 1 2)`.
+const BINARY_OPS: [Op; 20] = [
+    Op::Add, Op::Sub, Op::Mult, Op::Div, Op::Mod, Op::Pow,
+    Op::Equal, Op::NotEqual, Op::Less, Op::LessEqual, Op::Greater, Op::GreaterEqual,
+    Op::BitAnd, Op::BitOr, Op::BitXor, Op::ShiftLeft, Op::ShiftRight,
+    Op::In, Op::And, Op::Or
+];
+/// The operations written as a list with one operand, such as `(- x)`.
+const UNARY_OPS: [UnaryKind; 3] = [UnaryKind::Neg, UnaryKind::Pos, UnaryKind::Not];
+const POSTFIX_OPS: [Op; 2] = [Op::Factorial, Op::Percent];
+
+/// Builds the syntax tree written as the S-expression `source`.
+///
+/// Each operation or function call is a list of its operator or function
+/// name followed by its operands, see `Node::to_sexpr()`. Anything else is
+/// an atom: an integer, a float, a constant such as `pi`, or a variable.
+/// Atoms and lists are separated by whitespace or brackets.
+///
+/// The spans of the nodes are the chars they were read from, so an error
+/// found when evaluating the tree points into `source`.
+///
+/// ```
+/// use simple_expression_parser::{Parser, ParseError, from_sexpr};
+///
+/// let tree = from_sexpr("(+ 1 (* 2 3))").unwrap();
+/// assert!(tree.eq_node(Parser::new("1 + 2 * 3").parse().unwrap().as_ref()));
+/// assert_eq!(tree.to_infix(), "1 + 2 * 3");
+/// assert_eq!(from_sexpr("(- (max 1.5 e x))").unwrap().to_infix(), "-max(1.5, e, x)");
+/// assert_eq!(from_sexpr("(? (in x (.. 1 10)) (! x) 0)").unwrap().to_infix(), "x in 1..10 ? x! : 0");
+/// assert_eq!(from_sexpr("(* 2 99999999999999999999)").unwrap().evaluate(), Ok(2e20));
+/// assert_eq!(from_sexpr("(< 1 x 10 <= 10)").unwrap().to_infix(), "1 < x < 10 <= 10");
+/// // A percentage is applied to what it's added to, as in percent mode
+/// assert_eq!(from_sexpr("(+ 200 (% 10))").unwrap().evaluate(), Ok(220.0));
+///
+/// let error = |source: &str| from_sexpr(source).err().unwrap();
+/// assert_eq!(error("  "), ParseError::EmptyInput);
+/// assert!(matches!(error("(+ 1 (* 2 3)"), ParseError::UnclosedParenthesis { .. }));
+/// assert!(matches!(error("(+ 1 2))"), ParseError::UnmatchedParenthesis { .. }));
+/// assert!(matches!(error("(= pi 3)"), ParseError::AssignToConstant { .. }));
+/// assert!(matches!(error("(+ (.. 1 2) 3)"), ParseError::MisplacedRange { .. }));
+/// assert!(matches!(error(&"9".repeat(400)), ParseError::LiteralOverflow { .. }));
+/// assert!(matches!(error("(in x 5)"), ParseError::MalformedSexpr { .. }));
+/// assert!(matches!(error("1 2"), ParseError::MalformedSexpr { .. }));
+/// assert!(matches!(error("(< 1 <= 2)"), ParseError::MalformedSexpr { .. }));
+/// assert_eq!(
+///     error("(< 1 2 <=)").to_string(),
+///     "Malformed S-expression at line 1, column 8: '<=' must be followed by an operand"
+/// );
+/// assert_eq!(
+///     error("(== 1)").to_string(),
+///     "Malformed S-expression at line 1, column 2: '==' takes 2 or more operands, but 1 were given"
+/// );
+/// assert_eq!(
+///     error("(* 1 2 3)").to_string(),
+///     "Malformed S-expression at line 1, column 2: '*' takes 2 operands, but 3 were given"
+/// );
+/// assert_eq!(
+///     error("(+ 1 ())").to_string(),
+///     "Malformed S-expression at line 1, column 6: an empty list has no value"
+/// );
+/// ```
+pub fn from_sexpr(source: &str) -> Result<Box<dyn Node>, ParseError> {
+    let mut reader = Reader {
+        source,
+        chars: source.chars().collect(),
+        pos: Position::default()
+    };
+    reader.skip_whitespace();
+    if reader.peek().is_none() {
+        return Err(ParseError::EmptyInput);
+    }
+    let sexpr = reader.read(0)?;
+    reader.skip_whitespace();
+    match reader.peek() {
+        Some(')') => return Err(ParseError::UnmatchedParenthesis { pos: reader.pos }),
+        Some(_) => return Err(malformed("expected a single expression, but more followed it", reader.pos)),
+        None => {}
+    }
+    reader.node(&sexpr, false)
+}
+
+/// An S-expression which has been read, but not yet made into a node.
+enum Sexpr {
+    Atom {
+        text: String,
+        pos: Position,
+        end: usize
+    },
+    List {
+        items: Vec<Sexpr>,
+        pos: Position,
+        end: usize
+    }
+}
+
+impl Sexpr {
+    fn pos(&self) -> Position {
+        match self {
+            Sexpr::Atom { pos, .. } | Sexpr::List { pos, .. } => *pos
+        }
+    }
+}
+
+struct Reader<'s> {
+    source: &'s str,
+    chars: Vec<char>,
+    pos: Position
+}
+
+impl Reader<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos.index).copied()
+    }
+
+    fn advance(&mut self) {
+        if self.peek() == Some('\n') {
+            self.pos.line += 1;
+            self.pos.column = 1;
+        } else {
+            self.pos.column += 1;
+        }
+        self.pos.index += 1;
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.advance();
+        }
+    }
+
+    /// Reads the atom or list starting at the current char,
+    /// which must not be whitespace or the end of the input.
+    fn read(&mut self, depth: usize) -> Result<Sexpr, ParseError> {
+        let pos = self.pos;
+        match self.peek() {
+            Some('(') => {
+                if depth >= DEFAULT_MAX_DEPTH {
+                    return Err(ParseError::TooDeeplyNested { max_depth: DEFAULT_MAX_DEPTH, pos });
+                }
+                self.advance();
+                let mut items = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        None => return Err(ParseError::UnclosedParenthesis { opened: pos, pos: self.pos }),
+                        Some(')') => break,
+                        Some(_) => items.push(self.read(depth + 1)?)
+                    }
+                }
+                self.advance();
+                Ok(Sexpr::List { items, pos, end: self.pos.index })
+            },
+            Some(')') => Err(ParseError::UnmatchedParenthesis { pos }),
+            _ => {
+                let mut text = String::new();
+                while let Some(char) = self.peek().filter(|c| !c.is_whitespace() && *c != '(' && *c != ')') {
+                    text.push(char);
+                    self.advance();
+                }
+                Ok(Sexpr::Atom { text, pos, end: self.pos.index })
+            }
+        }
+    }
+
+    /// Makes `sexpr` into a node. Only the right hand side of `in`
+    /// may be a range, so `range` is whether one is allowed.
+    fn node(&self, sexpr: &Sexpr, range: bool) -> Result<Box<dyn Node>, ParseError> {
+        let (items, pos, end) = match sexpr {
+            Sexpr::Atom { text, pos, end } => return self.atom(text, *pos, *end),
+            Sexpr::List { items, pos, end } => (items, *pos, *end)
+        };
+        let (start, end) = (pos.index, end);
+        let (head, operands) = match items.split_first() {
+            Some((Sexpr::Atom { text, pos, .. }, operands)) => ((text.as_str(), *pos), operands),
+            Some((list, _)) => return Err(malformed("a list must start with an operator or function name", list.pos())),
+            None => return Err(malformed("an empty list has no value", pos))
+        };
+        let (symbol, head_pos) = head;
+        let operand = |sexpr: &Sexpr| self.node(sexpr, false);
+
+        let unary = UNARY_OPS.into_iter().find(|op| op.symbol() == symbol);
+        let postfix = POSTFIX_OPS.into_iter().find(|op| op.symbol() == symbol);
+        let binary = BINARY_OPS.into_iter().find(|op| op.symbol() == symbol);
+        if let Some(op) = binary.filter(|op| op.is_comparison() && operands.len() > 2) {
+            return self.comparisons(op, operands, start, end);
+        }
+        match (operands, unary, postfix, binary) {
+            ([right], Some(op), _, _) => {
+                return Ok(Box::new(ast::UnaryOp { right: operand(right)?, op, start, end }));
+            },
+            ([left], _, Some(op), _) => {
+                return Ok(Box::new(ast::PostfixOp { left: operand(left)?, op, start, end }));
+            },
+            ([left, right], _, _, Some(op)) => {
+                if op == Op::In && !matches!(right, Sexpr::List { items, .. } if is_head(items, "..")) {
+                    return Err(malformed("'in' must be followed by a range, as in (in x (.. 1 10))", right.pos()));
+                }
+                let left = operand(left)?;
+                let right = self.node(right, op == Op::In)?;
+                return Ok(Box::new(ast::BinOp {
+                    left,
+                    op: op.with_percentage(right.as_ref()),
+                    right,
+                    op_pos: head_pos,
+                    start,
+                    end
+                }));
+            },
+            _ => {}
+        }
+
+        match (symbol, operands) {
+            ("?", [condition, if_true, if_false]) => Ok(Box::new(ast::Conditional {
+                condition: operand(condition)?,
+                if_true: operand(if_true)?,
+                if_false: operand(if_false)?,
+                start,
+                end
+            })),
+            ("=", [target, value]) => {
+                let name = match target {
+                    Sexpr::Atom { text, .. } if is_name(text) => text.clone(),
+                    _ => return Err(malformed("only a variable can be assigned to", target.pos()))
+                };
+                if parser::constant(&name).is_some() {
+                    return Err(ParseError::AssignToConstant { name, pos: target.pos() });
+                }
+                Ok(Box::new(ast::Assignment { name, value: operand(value)?, start, end }))
+            },
+            ("..", [low, high]) => {
+                if !range {
+                    return Err(ParseError::MisplacedRange { pos });
+                }
+                Ok(Box::new(ast::Range { low: operand(low)?, high: operand(high)?, start, end }))
+            },
+            _ if is_operator(symbol) => Err(malformed(
+                &format!("'{}' takes {}, but {} were given", symbol, operand_counts(symbol), operands.len()),
+                head_pos
+            )),
+            _ if is_name(symbol) && parser::constant(symbol).is_none() => {
+                let arguments = operands.iter().map(operand).collect::<Result<Vec<_>, _>>()?;
+                Ok(Box::new(ast::FunctionCall { name: symbol.to_string(), arguments, start, end }))
+            },
+            _ => Err(malformed(&format!("'{}' isn't an operator or function name", symbol), head_pos))
+        }
+    }
+
+    /// Makes a chain of comparisons starting with `first`, such as
+    /// `(< 1 x <= 10)`, into a node. Each operand after the first two is
+    /// compared with the one before it by the comparison written just
+    /// before it, or if there isn't one, by the last comparison written.
+    fn comparisons(&self, first: Op, items: &[Sexpr], start: usize, end: usize) -> Result<Box<dyn Node>, ParseError> {
+        let mut operands = Vec::new();
+        let mut ops = Vec::new();
+        let mut op = first;
+        let mut items = items.iter();
+        while let Some(mut item) = items.next() {
+            let comparison = match item {
+                Sexpr::Atom { text, .. } if operands.len() >= 2 => {
+                    BINARY_OPS.into_iter().find(|op| op.is_comparison() && op.symbol() == text)
+                },
+                _ => None
+            };
+            if let Some(comparison) = comparison {
+                op = comparison;
+                item = items.next().ok_or_else(|| malformed(
+                    &format!("'{}' must be followed by an operand", op.symbol()),
+                    item.pos()
+                ))?;
+            }
+            if !operands.is_empty() {
+                ops.push(op);
+            }
+            operands.push(self.node(item, false)?);
+        }
+        Ok(Box::new(ast::ComparisonChain { operands, ops, start, end }))
+    }
+
+    /// Makes the atom `text` into a number, constant or variable.
+    fn atom(&self, text: &str, pos: Position, end: usize) -> Result<Box<dyn Node>, ParseError> {
+        let start = pos.index;
+        if let Ok(value) = text.parse::<i64>() {
+            return Ok(Box::new(ast::IntLiteral { value, start, end }));
+        }
+        // Including an integer too large for an i64, which the
+        // parser also reads as a float.
+        let digits = text.strip_prefix(['-', '+']).unwrap_or(text);
+        if digits.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+            if let Ok(value) = text.parse::<ast::Number>() {
+                if value.is_infinite() {
+                    return Err(ParseError::LiteralOverflow {
+                        value: text.to_string(),
+                        pos,
+                        context: tokeniser::context(self.source, pos)
+                    });
+                }
+                return Ok(Box::new(ast::FloatLiteral { value, start, end }));
+            }
+        }
+        if is_operator(text) {
+            return Err(malformed(&format!("'{}' is an operator, so it must start a list", text), pos));
+        }
+        if !is_name(text) {
+            return Err(malformed(&format!("'{}' isn't a number or a name", text), pos));
+        }
+        Ok(match parser::constant(text) {
+            Some(value) => Box::new(ast::Constant { name: text.to_string(), value, start, end }),
+            None => Box::new(ast::Variable { name: text.to_string(), start, end })
+        })
+    }
+}
+
+fn malformed(reason: &str, pos: Position) -> ParseError {
+    ParseError::MalformedSexpr { reason: reason.to_string(), pos }
+}
+
+/// Whether `text` could be written in an expression as a variable
+/// or function name, as the tokeniser reads identifiers.
+fn is_name(text: &str) -> bool {
+    text.starts_with(char::is_alphabetic)
+        && text.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && !is_operator(text)
+}
+
+fn is_head(items: &[Sexpr], symbol: &str) -> bool {
+    matches!(items.first(), Some(Sexpr::Atom { text, .. }) if text == symbol)
+}
+
+fn is_operator(symbol: &str) -> bool {
+    !operand_counts(symbol).is_empty()
+}
+
+/// How many operands the operator `symbol` takes, as written in an
+/// error message, or an empty string if it isn't an operator.
+fn operand_counts(symbol: &str) -> &'static str {
+    let unary = UNARY_OPS.iter().any(|op| op.symbol() == symbol)
+        || POSTFIX_OPS.iter().any(|op| op.symbol() == symbol);
+    let binary = BINARY_OPS.iter().any(|op| op.symbol() == symbol);
+    let comparison = BINARY_OPS.iter().any(|op| op.is_comparison() && op.symbol() == symbol);
+    match symbol {
+        "?" => "3 operands",
+        "=" | ".." => "2 operands",
+        _ if comparison => "2 or more operands",
+        _ if unary && binary => "1 or 2 operands",
+        _ if unary => "1 operand",
+        _ if binary => "2 operands",
+        _ => ""
+    }
+}
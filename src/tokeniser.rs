@@ -1,169 +1,1260 @@
 
+use core::fmt;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec
+};
+#[cfg(feature = "std")]
+use std::io::{self, BufReader, Read};
+
+use crate::error::ParseError;
+use crate::math;
+
 /// Represents the different types of tokens found within an expression.
-#[derive(Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TokenKind {
     IntLiteral,
     FloatLiteral,
+    Ident,
     Add,
     Sub,
     Mult,
     Div,
+    Mod,
+    Pow,
+    Factorial,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Assign,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    And,
+    Or,
+    Not,
     LParen,
     RParen,
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
+    Semicolon,
+    Comma,
+    Question,
+    Colon,
+    DotDot,
+    In,
     EOF,
     Empty
 }
 
+/// The char which begins a comment, e.g. `2 * 3 # six`.
+pub const COMMENT_MARKER: char = '#';
+
+/// A location within the source of an expression.
+/// 
+/// `index` counts chars from the start of the source, while `line`
+/// and `column` both start at 1 and are intended for error messages.
+/// 
+/// All positions are measured in chars rather than bytes, so a
+/// multi-byte char such as `é` only advances the position by one.
+/// Use `str::char_indices()` to convert an index into a byte offset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub index: usize,
+    pub line: usize,
+    pub column: usize
+}
+
+/// The position of the first char of the source.
+impl Default for Position {
+    fn default() -> Self {
+        Self { index: 0, line: 1, column: 1 }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// How many chars either side of an error are shown by `context()`.
+pub const CONTEXT_WIDTH: usize = 10;
+
+/// Shows where `pos` is within `source`, as the surrounding chars of
+/// its line with a caret under the char at `pos`.
+/// 
+/// At most `CONTEXT_WIDTH` chars are shown either side of `pos`.
+/// 
+/// ```
+/// use simple_expression_parser::tokeniser::{context, Position};
+/// 
+/// let pos = Position { index: 6, line: 1, column: 7 };
+/// assert_eq!(context("1 + 2 $ 3", pos), "1 + 2 $ 3\n      ^");
+/// ```
+pub fn context(source: &str, pos: Position) -> String {
+    let chars: Vec<char> = source.chars().collect();
+    let index = pos.index.min(chars.len());
+    let line_start = chars[..index].iter()
+        .rposition(|c| *c == '\n')
+        .map_or(0, |newline| newline + 1);
+    let line_end = chars[index..].iter()
+        .position(|c| *c == '\n')
+        .map_or(chars.len(), |newline| index + newline);
+
+    let start = line_start.max(index.saturating_sub(CONTEXT_WIDTH));
+    let end = line_end.min(index + CONTEXT_WIDTH + 1);
+    // Tabs are replaced so that the caret lines up with the char above it.
+    let snippet: String = chars[start..end].iter()
+        .map(|c| if *c == '\t' { ' ' } else { *c })
+        .collect();
+    format!("{}\n{}^", snippet, " ".repeat(index - start))
+}
+
 /// A Token is an individual component of an expression.
 /// 
 /// For example, a token could be a number or mathematical symbol.
-#[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub kind: TokenKind,
     pub value: Option<String>,
-    pos: usize
+    pos: usize,
+    line: usize,
+    column: usize,
+    end: usize
 }
 
 impl Token {
+    fn new(kind: TokenKind, value: Option<String>, start: Position) -> Self {
+        Self {
+            kind,
+            value,
+            pos: start.index,
+            line: start.line,
+            column: start.column,
+            // Filled in by `next_token()` once the token is complete
+            end: start.index
+        }
+    }
+
     pub fn empty() -> Self {
         Self {
             kind: TokenKind::Empty,
             value: None,
-            pos: 0
+            pos: 0,
+            line: 1,
+            column: 1,
+            end: 0
         }
     }
+
+    /// The type of the token.
+    pub fn kind(&self) -> TokenKind {
+        self.kind
+    }
+
+    /// The text of the token, only present for literals and identifiers.
+    /// 
+    /// Together with `kind()` and `position()`, this lets tools such as
+    /// syntax highlighters inspect each token without matching on its fields.
+    /// 
+    /// ```
+    /// use simple_expression_parser::{Tokeniser, TokenKind};
+    /// 
+    /// let tokens: Vec<_> = Tokeniser::new("x + 12").map(Result::unwrap).collect();
+    /// assert_eq!(tokens[0].kind(), TokenKind::Ident);
+    /// assert_eq!(tokens[0].text(), Some("x"));
+    /// assert_eq!(tokens[1].kind(), TokenKind::Add);
+    /// assert_eq!(tokens[1].text(), None);
+    /// assert_eq!(tokens[2].text(), Some("12"));
+    /// assert_eq!(tokens[2].position().index, 4);
+    /// assert_eq!(tokens[2].position().column, 5);
+    /// ```
+    pub fn text(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+
+    /// The text of the token, only present for literals and identifiers.
+    #[deprecated(note = "renamed to `text()`")]
+    pub fn value(&self) -> Option<&str> {
+        self.text()
+    }
+
+    /// The index just past the last char of the token.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Where the first char of the token is found in the source.
+    pub fn position(&self) -> Position {
+        Position {
+            index: self.pos,
+            line: self.line,
+            column: self.column
+        }
+    }
+}
+
+/// Decodes the UTF-8 bytes of a reader into chars, one at a time.
+#[cfg(feature = "std")]
+struct Utf8Chars<R: Read> {
+    bytes: io::Bytes<R>
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Utf8Chars<R> {
+    fn invalid() -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Iterator for Utf8Chars<R> {
+    type Item = io::Result<char>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = match self.bytes.next()? {
+            Ok(byte) => byte,
+            Err(err) => return Some(Err(err))
+        };
+        // The leading byte of a char says how many bytes it's made up of.
+        let width = match first {
+            0x00..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => return Some(Err(Self::invalid()))
+        };
+        let mut buffer = [first, 0, 0, 0];
+        for byte in buffer.iter_mut().take(width).skip(1) {
+            *byte = match self.bytes.next() {
+                Some(Ok(byte)) => byte,
+                Some(Err(err)) => return Some(Err(err)),
+                None => return Some(Err(Self::invalid()))
+            };
+        }
+        Some(std::str::from_utf8(&buffer[..width]).ok()
+            .and_then(|decoded| decoded.chars().next())
+            .ok_or_else(Self::invalid))
+    }
+}
+
+/// The parts of the syntax which differ between locales,
+/// set with `Parser::set_locale()` or `Tokeniser::set_locale()`.
+/// 
+/// With a decimal comma, as is usual in much of Europe, `3,14` is a
+/// number and function arguments are separated by `;` instead, as in
+/// `max(1; 2,5)`. Outside of a function call `;` still separates the
+/// statements of a program, so `1,5; 2` is two statements. A comma is
+/// only part of a number when a digit follows it, and a decimal point
+/// is still accepted, so `3.14` is the same as `3,14`.
+/// 
+/// ```
+/// use simple_expression_parser::{Parser, LocaleOptions, DecimalSeparator};
+/// 
+/// let european = LocaleOptions { decimal_separator: DecimalSeparator::Comma };
+/// let evaluate = |source: &str| {
+///     let mut parser = Parser::new(source);
+///     parser.set_locale(european);
+///     parser.parse().unwrap().evaluate()
+/// };
+/// assert_eq!(evaluate("3,14 + 1"), Ok(3.14 + 1.0));
+/// assert_eq!(evaluate("max(1; 2,5)"), Ok(2.5));
+/// assert_eq!(evaluate("2,5 * 2"), evaluate("2.5 * 2"));
+/// 
+/// let mut parser = Parser::new("1,5; 2");
+/// parser.set_locale(european);
+/// assert_eq!(parser.parse_program().unwrap().len(), 2);
+/// 
+/// // With the default locale the comma separates arguments.
+/// assert_eq!(Parser::new("max(1, 2,5)").parse().unwrap().evaluate(), Ok(5.0));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LocaleOptions {
+    pub decimal_separator: DecimalSeparator
+}
+
+/// The char separating the whole and fractional parts of a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DecimalSeparator {
+    /// `3.14`, with arguments separated by `,`.
+    #[default]
+    Point,
+    /// `3,14`, with arguments separated by `;`.
+    Comma
+}
+
+/// Where a tokeniser pulls its chars from.
+enum Source {
+    // A source held in memory, along with the byte offset of the
+    // next char to be read. The buffer is reused by `reset()`.
+    Text {
+        text: String,
+        offset: usize
+    },
+    // Chars are pulled from a reader lazily, so only the current
+    // char and the one after it are held in memory at a time.
+    #[cfg(feature = "std")]
+    Reader(Box<dyn Iterator<Item = io::Result<char>>>)
 }
 
 /// Splits up an expression into it's fundamental parts, creating a token stream.
+/// 
+/// The Unicode signs `×`, `÷` and `−` (U+2212) are read as the
+/// operators `*`, `/` and `-`, so pasted expressions work as written.
+/// 
+/// ```
+/// use simple_expression_parser::Parser;
+/// 
+/// let unicode = Parser::new("2 × 3 ÷ 6 − 1".to_string()).parse().unwrap();
+/// let ascii = Parser::new("2 * 3 / 6 - 1".to_string()).parse().unwrap();
+/// assert!(unicode.eq_node(ascii.as_ref()));
+/// assert_eq!(unicode.evaluate(), Ok(0.0));
+/// ```
+/// 
+/// A backslash at the end of a line continues the expression onto the
+/// next line, and is skipped like whitespace. Positions after it are
+/// still counted from the start of the new line.
+/// 
+/// ```
+/// use simple_expression_parser::{Parser, Tokeniser};
+/// 
+/// let continued = Parser::new("1 + \\\n 2").parse().unwrap();
+/// let single = Parser::new("1 + 2").parse().unwrap();
+/// assert!(continued.eq_node(single.as_ref()));
+/// assert_eq!(continued.evaluate(), Ok(3.0));
+/// 
+/// let two = Tokeniser::new("1 + \\\r\n 2").nth(2).unwrap().unwrap();
+/// assert_eq!(two.text(), Some("2"));
+/// assert_eq!((two.position().line, two.position().column), (2, 2));
+/// ```
+/// 
+/// Floats can be written in hexadecimal as in C, such as `0x1.8p3`,
+/// which is `0x1.8` (1.5) times 2 to the power of 3. This is useful for
+/// writing an exact float, as every hexadecimal float is converted
+/// without any rounding error, unless it has more digits than fit.
+/// 
+/// ```
+/// use simple_expression_parser::{evaluate, Parser, ParseError};
+/// 
+/// assert_eq!(evaluate("0x1p4"), Ok(16.0));
+/// assert_eq!(evaluate("0x1.8p3"), Ok(12.0));
+/// assert_eq!(evaluate("0x.8"), Ok(0.5));
+/// assert_eq!(evaluate("0xAp-2"), Ok(2.5));
+/// assert_eq!(evaluate("0x1.fffffffffffffp1023"), Ok(f64::MAX));
+/// assert_eq!(evaluate("0x1p-1074"), Ok(f64::from_bits(1)));
+/// assert_eq!(evaluate("0x1.99999999999999999p-4"), Ok(0.1));
+/// 
+/// let error = |source: &str| Parser::new(source).parse().err();
+/// assert!(matches!(error("0x1p"), Some(ParseError::UnfinishedExponent { .. })));
+/// assert!(matches!(error("0x1."), Some(ParseError::UnfinishedFloat { .. })));
+/// assert!(matches!(error("0x1.gp1"), Some(ParseError::InvalidDigit { digit: 'g', .. })));
+/// assert!(matches!(error("0x1p1024"), Some(ParseError::LiteralOverflow { .. })));
+/// ```
+/// 
+/// Each char is read once, so tokenising takes time in proportion to
+/// the length of the source, even for a long expression. The `parse`
+/// benchmark times this for expressions of increasing length.
+/// 
+/// ```
+/// use simple_expression_parser::Tokeniser;
+/// 
+/// let source = "1 + ".repeat(2_500) + "1";
+/// let tokens: Vec<_> = Tokeniser::new(source).map(Result::unwrap).collect();
+/// assert_eq!(tokens.len(), 5_001);
+/// assert_eq!(tokens[5_000].position().index, 10_000);
+/// ```
 pub struct Tokeniser {
-    source: String,
+    source: Source,
+    current: char,
+    next: char,
+    // The char after `next`, so that a number can
+    // look past the sign of its exponent.
+    after_next: char,
+    // The message of the error which reading the source
+    // failed with, reported in place of the `EOF` token.
+    read_error: Option<String>,
+    // The result of scanning the next token, if it's been peeked at.
+    peeked: Option<Result<Token, ParseError>>,
+    /// Index just past the last token returned by `next_token()`,
+    /// counted in chars rather than bytes. Peeking at a token
+    /// doesn't move it.
     pub char_pos: usize,
+    // Index of the current char, counted in chars rather than bytes.
+    index: usize,
+    line: usize,
+    column: usize,
+    // Set once the iterator has yielded its last item.
+    finished: bool,
+    // The most chars the source may contain, and how many have been
+    // read so far. Once there are too many, no more are read.
+    max_length: Option<usize>,
+    chars_read: usize,
+    too_long: bool,
+    locale: LocaleOptions
 }
 
 impl Tokeniser {
-    pub fn new(source: String) -> Self {
-        Self {
+    /// Creates a tokeniser for `source`, which can be either a `String`,
+    /// which is used as is, or a `&str`, which is copied.
+    pub fn new(source: impl Into<String>) -> Self {
+        Self::from_source(Source::Text { text: source.into(), offset: 0 })
+    }
+
+    /// Creates a tokeniser which reads its source from `reader` as it goes,
+    /// so a large file never has to be loaded into memory all at once.
+    /// 
+    /// The source must be UTF-8. If it isn't, or reading fails part way
+    /// through, the error is returned by `next_token()` once every
+    /// char before it has been tokenised.
+    /// 
+    /// Only available with the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: Read + 'static>(reader: R) -> Self {
+        Self::from_source(Source::Reader(Box::new(Utf8Chars { bytes: BufReader::new(reader).bytes() })))
+    }
+
+    fn from_source(source: Source) -> Self {
+        let mut tokeniser = Self {
             source,
-            char_pos: 0
+            current: '\0',
+            next: '\0',
+            after_next: '\0',
+            read_error: None,
+            peeked: None,
+            char_pos: 0,
+            index: 0,
+            line: 1,
+            column: 1,
+            finished: false,
+            max_length: None,
+            chars_read: 0,
+            too_long: false,
+            locale: LocaleOptions::default()
+        };
+        tokeniser.current = tokeniser.read_char();
+        tokeniser.next = tokeniser.read_char();
+        tokeniser.after_next = tokeniser.read_char();
+        tokeniser
+    }
+
+    /// Starts tokenising `source` from the beginning, as if the
+    /// tokeniser had just been created with `Tokeniser::new()`.
+    /// 
+    /// The buffer holding the previous source is reused, so resetting
+    /// doesn't allocate unless `source` is longer than any before it.
+    pub fn reset(&mut self, source: &str) {
+        match &mut self.source {
+            Source::Text { text, offset } => {
+                text.clear();
+                text.push_str(source);
+                *offset = 0;
+            },
+            #[cfg(feature = "std")]
+            Source::Reader(_) => {
+                self.source = Source::Text { text: source.to_string(), offset: 0 };
+            }
         }
+        self.read_error = None;
+        self.peeked = None;
+        self.char_pos = 0;
+        self.index = 0;
+        self.line = 1;
+        self.column = 1;
+        self.finished = false;
+        self.chars_read = 0;
+        self.check_length();
+        self.current = self.read_char();
+        self.next = self.read_char();
+        self.after_next = self.read_char();
     }
 
-    /// Retrieves the current char without incrementing char_pos
-    fn current_char(&self) -> char {
-        match self.source.chars().nth(self.char_pos) {
-            Some(c) => c,
-            None => '\0'
+    /// Limits how many chars the source may contain, after which
+    /// `next_token()` returns `ParseError::InputTooLong`.
+    /// 
+    /// A source held in memory is checked straight away, so an oversized
+    /// input is rejected before any of it is tokenised. A source read from
+    /// a reader is checked as it's read, so no more than `max_length`
+    /// chars are ever read from it.
+    pub fn set_max_length(&mut self, max_length: Option<usize>) {
+        self.max_length = max_length;
+        self.check_length();
+    }
+
+    /// Sets how numbers are written, see `LocaleOptions`.
+    pub fn set_locale(&mut self, locale: LocaleOptions) {
+        self.locale = locale;
+    }
+
+    /// Whether the current char separates the whole and fractional parts
+    /// of a number. A decimal comma must be followed by a digit, so that
+    /// a comma after a number can still be a `TokenKind::Comma`.
+    fn at_decimal_separator(&self) -> bool {
+        match self.current_char() {
+            // Unless it begins a '..', as in `1..10`.
+            '.' => self.peek_char() != '.',
+            ',' => self.locale.decimal_separator == DecimalSeparator::Comma && self.peek_char().is_ascii_digit(),
+            _ => false
         }
     }
 
-    /// Retrieves the current char and increments char_pos
-    fn next_char(&mut self) -> char {
-        self.char_pos += 1;
-        match self.source.chars().nth(self.char_pos) {
-            Some(c) => {
-                c
+    /// Checks the length of a source held in memory all at once.
+    fn check_length(&mut self) {
+        self.too_long = match (&self.source, self.max_length) {
+            (Source::Text { text, .. }, Some(max_length)) => text.chars().count() > max_length,
+            _ => false
+        };
+    }
+
+    /// Pulls the next char out of the source,
+    /// or '\0' if there are none left.
+    fn read_char(&mut self) -> char {
+        if self.too_long {
+            return '\0';
+        }
+        let char = self.read_source_char();
+        if char != '\0' {
+            self.chars_read += 1;
+            if self.max_length.is_some_and(|max_length| self.chars_read > max_length) {
+                self.too_long = true;
+                return '\0';
+            }
+        }
+        char
+    }
+
+    /// Pulls the next char out of the source without checking its length.
+    fn read_source_char(&mut self) -> char {
+        if self.read_error.is_some() {
+            return '\0';
+        }
+        match &mut self.source {
+            Source::Text { text, offset } => match text[*offset..].chars().next() {
+                Some(char) => {
+                    *offset += char.len_utf8();
+                    char
+                },
+                None => '\0'
             },
-            None => '\0'
+            #[cfg(feature = "std")]
+            Source::Reader(source) => match source.next() {
+                Some(Ok(char)) => char,
+                Some(Err(err)) => {
+                    self.read_error = Some(err.to_string());
+                    '\0'
+                },
+                None => '\0'
+            }
         }
     }
 
-    /// Gets a sequence of consectuive numbers
-    fn number_sequence(&mut self) -> String {
+    /// The position of the current char.
+    fn current_position(&self) -> Position {
+        Position {
+            index: self.index,
+            line: self.line,
+            column: self.column
+        }
+    }
+
+    /// The whole source, or `None` if it's read from a reader,
+    /// which only holds on to the chars it's currently reading.
+    pub(crate) fn source(&self) -> Option<&str> {
+        match &self.source {
+            Source::Text { text, .. } => Some(text),
+            #[cfg(feature = "std")]
+            Source::Reader(_) => None
+        }
+    }
+
+    /// The context of `pos` within the source, see `context()`.
+    /// 
+    /// This is empty for a source read from a reader,
+    /// as the chars already read aren't kept.
+    pub(crate) fn context(&self, pos: Position) -> String {
+        match &self.source {
+            Source::Text { text, .. } => context(text, pos),
+            #[cfg(feature = "std")]
+            Source::Reader(_) => String::new()
+        }
+    }
+
+    /// Retrieves the current char without moving to the next
+    fn current_char(&self) -> char {
+        self.current
+    }
+
+    /// Moves to the next char and retrieves it
+    fn next_char(&mut self) -> char {
+        // Moving past a newline starts the next line.
+        if self.current_char() == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        self.index += 1;
+        self.current = self.next;
+        self.next = self.after_next;
+        self.after_next = self.read_char();
+        self.current
+    }
+
+    /// Skips a block comment, such as `/* note */`, which may span
+    /// several lines. Block comments don't nest, so the comment ends at
+    /// the first "*/", as in C. Errors if the source ends first.
+    /// 
+    /// Expects the current char to be the '/' of the opening "/*".
+    fn block_comment(&mut self) -> Result<(), ParseError> {
+        let start = self.current_position();
+        self.next_char();
+        self.next_char();
+        loop {
+            match self.current_char() {
+                '\0' => return Err(ParseError::UnterminatedComment {
+                    pos: start,
+                    context: self.context(start)
+                }),
+                '*' if self.peek_char() == '/' => {
+                    self.next_char();
+                    self.next_char();
+                    return Ok(());
+                },
+                _ => {
+                    self.next_char();
+                }
+            }
+        }
+    }
+
+    /// Whether the current char is a backslash ending the line,
+    /// including a line ending in "\r\n".
+    fn at_line_continuation(&self) -> bool {
+        self.current_char() == '\\' && matches!(self.peek_char(), '\n' | '\r')
+    }
+
+    /// Retrieves the char after the current char without moving to it
+    fn peek_char(&self) -> char {
+        self.next
+    }
+
+    /// Retrieves the char two after the current char without moving to it
+    fn peek_second_char(&self) -> char {
+        self.after_next
+    }
+
+    /// Whether the current 'e' begins the exponent of a number, being
+    /// followed by its digits, optionally after a sign. Otherwise the
+    /// number ends before the 'e', so `2e` is `2` followed by `e`.
+    fn at_exponent(&self) -> bool {
+        match self.peek_char() {
+            '+' | '-' => self.peek_second_char().is_ascii_digit(),
+            next => next.is_ascii_digit()
+        }
+    }
+
+    /// Gets a sequence of consecutive digits and underscores, exactly as written.
+    fn raw_number_sequence(&mut self) -> String {
         let mut char = self.current_char();
         let mut number_string = String::new();
-        while char.is_numeric() {
+        // Only ASCII digits are accepted, other numeric chars
+        // such as '²' or '½' can't be parsed into a number.
+        while char.is_ascii_digit() || char == '_' {
             number_string.push(char);
             char = self.next_char();
         }
         number_string
     }
 
-    /// Generates the next token in the stream.
+    /// Gets a sequence of consecutive digits.
     /// 
-    /// Errors if invalid character sequence is found.
-    pub fn next_token(&mut self) -> Result<Token, String> {
+    /// Digits may be separated by single underscores for readability,
+    /// e.g. `1_000_000`, which are removed from the returned string.
+    /// Errors if an underscore isn't between two digits.
+    fn number_sequence(&mut self) -> Result<String, ParseError> {
+        let start = self.current_position();
+        let number_string = self.raw_number_sequence();
+        if number_string.starts_with('_')
+            || number_string.ends_with('_')
+            || number_string.contains("__") {
+            return Err(ParseError::MisplacedDigitSeparator {
+                value: number_string,
+                pos: start,
+                context: self.context(start)
+            });
+        }
+        Ok(number_string.replace('_', ""))
+    }
 
-        // Skip whitespace
-        while self.current_char().is_whitespace() {
+    /// The error for a number which continues past where it should have
+    /// ended, such as `1.2.3`. The rest of the run of digits and decimal
+    /// points is consumed so that the error covers all of it.
+    fn malformed_number(&mut self, mut value: String, start: Position) -> ParseError {
+        while self.current_char().is_ascii_digit() || self.current_char() == '_' || self.at_decimal_separator() {
+            value.push(self.current_char());
             self.next_char();
         }
+        ParseError::MalformedNumber { value, pos: start, context: self.context(start) }
+    }
+
+    /// Gets an integer written with a radix prefix, such as `0xFF`,
+    /// `0o17` or `0b1010`, and converts it into a decimal IntLiteral,
+    /// or a FloatLiteral if it's too large, see `ast::IntLiteral`.
+    /// 
+    /// Expects the current char to be the leading '0' of the prefix.
+    fn radix_literal(&mut self, start: Position) -> Result<Token, ParseError> {
+        let prefix = self.next_char();
+        let radix = match prefix.to_ascii_lowercase() {
+            'x' => 16,
+            'o' => 8,
+            'b' => 2,
+            _ => unreachable!()
+        };
+        self.next_char();
+
+        let digits = self.radix_digits(radix);
+        let value = format!("0{}{}", prefix, digits);
+
+        // A point or binary exponent makes it a hexadecimal float,
+        // though not a point starting a range, as in `0x1..0xF`.
+        let point = self.current_char() == '.' && self.peek_char() != '.';
+        if radix == 16 && (point || matches!(self.current_char(), 'p' | 'P')) {
+            return self.hex_float(value, digits, start);
+        }
+
+        if digits.is_empty() {
+            let context = self.context(start);
+            return Err(ParseError::UnfinishedRadixLiteral { value, pos: start, context });
+        }
+        self.check_radix_digits(&digits, radix, &value, start)?;
+
+        let digits = digits.replace('_', "");
+        if let Ok(decimal) = i64::from_str_radix(&digits, radix) {
+            return Ok(Token::new(TokenKind::IntLiteral, Some(decimal.to_string()), start));
+        }
+        let float = math::radix_float(&digits, radix);
+        if float.is_infinite() {
+            return Err(ParseError::LiteralOverflow { value, pos: start, context: self.context(start) });
+        }
+        // Printed with as many digits as it takes to read back the same float.
+        Ok(Token::new(TokenKind::FloatLiteral, Some(format!("{:?}", float)), start))
+    }
+
+    /// Gets the digits of a literal with a radix prefix, exactly as written.
+    /// 
+    /// Every alphanumeric char is consumed so that an invalid digit,
+    /// such as the 'G' in `0xG1`, is reported rather than being
+    /// mistaken for the start of an identifier. The exception is the
+    /// 'p' starting the exponent of a hexadecimal float.
+    fn radix_digits(&mut self, radix: u32) -> String {
+        let mut digits = String::new();
+        loop {
+            let char = self.current_char();
+            let exponent = radix == 16 && matches!(char, 'p' | 'P');
+            if !(char.is_ascii_alphanumeric() || char == '_') || exponent {
+                return digits;
+            }
+            digits.push(char);
+            self.next_char();
+        }
+    }
+
+    /// Errors if `digits` contains a digit not valid in the radix,
+    /// or a digit separator which isn't between two digits.
+    fn check_radix_digits(&self, digits: &str, radix: u32, value: &str, start: Position) -> Result<(), ParseError> {
+        let value = value.to_string();
+        if let Some(digit) = digits.chars().find(|c| *c != '_' && !c.is_digit(radix)) {
+            let context = self.context(start);
+            return Err(ParseError::InvalidDigit { digit, radix, value, pos: start, context });
+        }
+        if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+            let context = self.context(start);
+            return Err(ParseError::MisplacedDigitSeparator { value, pos: start, context });
+        }
+        Ok(())
+    }
+
+    /// Gets a hexadecimal float such as `0x1.8p3`, which is 1.8 in
+    /// hexadecimal times 2 to the power of 3, and converts it into a
+    /// decimal FloatLiteral holding exactly the same float.
+    /// 
+    /// Both the point and the exponent are optional, though one of them
+    /// must be present, and the exponent is written in decimal.
+    /// Expects the current char to be the point or the 'p'
+    /// following `integer`, the digits before the point.
+    fn hex_float(&mut self, mut value: String, integer: String, start: Position) -> Result<Token, ParseError> {
+        let mut fraction = String::new();
+        let point = self.current_char() == '.';
+        if point {
+            value.push('.');
+            self.next_char();
+            fraction = self.radix_digits(16);
+            value.push_str(&fraction);
+        }
+        if integer.is_empty() && fraction.is_empty() {
+            let context = self.context(start);
+            return Err(ParseError::UnfinishedRadixLiteral { value, pos: start, context });
+        }
+        for digits in [&integer, &fraction] {
+            if !digits.is_empty() {
+                self.check_radix_digits(digits, 16, &value, start)?;
+            }
+        }
+
+        let mut exponent: i64 = 0;
+        if matches!(self.current_char(), 'p' | 'P') {
+            // Add the 'p' and the optional sign
+            value.push(self.current_char());
+            self.next_char();
+            let negative = self.current_char() == '-';
+            if matches!(self.current_char(), '+' | '-') {
+                value.push(self.current_char());
+                self.next_char();
+            }
+            let exponent_sequence = self.number_sequence()?;
+            if exponent_sequence.is_empty() {
+                let context = self.context(start);
+                return Err(ParseError::UnfinishedExponent { value, pos: start, context });
+            }
+            value.push_str(&exponent_sequence);
+            // An exponent too large for an i64 is certainly too large
+            // for a float, so it's reported as an overflow below.
+            exponent = exponent_sequence.parse().unwrap_or(i64::MAX);
+            if negative {
+                exponent = -exponent;
+            }
+        } else if fraction.is_empty() {
+            let context = self.context(start);
+            return Err(ParseError::UnfinishedFloat { value, pos: start, context });
+        }
+
+        let float = math::hex_float(&integer.replace('_', ""), &fraction.replace('_', ""), exponent);
+        if float.is_infinite() {
+            return Err(ParseError::LiteralOverflow { value, pos: start, context: self.context(start) });
+        }
+        // Printed with as many digits as it takes to read back the same float.
+        Ok(Token::new(TokenKind::FloatLiteral, Some(format!("{:?}", float)), start))
+    }
+
+    /// Gets a sequence of letters, digits and underscores
+    /// making up a name, such as `pi` or `x_2`.
+    fn identifier(&mut self) -> String {
+        let mut char = self.current_char();
+        let mut identifier = String::new();
+        while char.is_alphanumeric() || char == '_' {
+            identifier.push(char);
+            char = self.next_char();
+        }
+        identifier
+    }
+
+    /// Generates the next token in the stream.
+    /// 
+    /// Errors if invalid character sequence is found. The position
+    /// reported by an error is always the first char of the
+    /// offending sequence.
+    pub fn next_token(&mut self) -> Result<Token, ParseError> {
+        let token = match self.peeked.take() {
+            Some(result) => result?,
+            None => self.scan_token()?
+        };
+        self.char_pos = token.end;
+        Ok(token)
+    }
+
+    /// Returns the next token without consuming it, so the following
+    /// call to `next_token()` returns the same token, or the same error.
+    /// 
+    /// Only one token is looked ahead, so peeking repeatedly
+    /// returns the same token each time.
+    pub fn peek_token(&mut self) -> Result<&Token, ParseError> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.scan_token());
+        }
+        match self.peeked.as_ref() {
+            Some(Ok(token)) => Ok(token),
+            Some(Err(err)) => Err(err.clone()),
+            None => unreachable!()
+        }
+    }
+
+    /// Reads the chars making up the next token, see `next_token()`.
+    fn scan_token(&mut self) -> Result<Token, ParseError> {
+        let mut token = self.scan_chars()?;
+        token.end = self.index;
+        Ok(token)
+    }
+
+    /// Matches the chars at the current position against each kind of token.
+    fn scan_chars(&mut self) -> Result<Token, ParseError> {
+
+        // Skip whitespace and comments. A comment runs from a '#'
+        // to the end of the line, or from a "/*" to the next "*/",
+        // so it can be followed by more whitespace or even another
+        // comment. A backslash at the end of a line continues the
+        // expression onto the next one, so it's skipped along with
+        // the newline.
+        loop {
+            while self.current_char().is_whitespace() || self.at_line_continuation() {
+                self.next_char();
+            }
+            if self.current_char() == '/' && self.peek_char() == '*' {
+                self.block_comment()?;
+            } else if self.current_char() == COMMENT_MARKER {
+                while !matches!(self.current_char(), '\n' | '\0') {
+                    self.next_char();
+                }
+            } else {
+                break;
+            }
+        }
+
+        if self.too_long {
+            return Err(ParseError::InputTooLong {
+                max_length: self.max_length.unwrap_or_default()
+            });
+        }
+
+        let start = self.current_position();
 
         match self.current_char() {
 
-            // End of file
-            '\0' => {
-                Ok(Token {
-                    kind: TokenKind::EOF,
-                    value: None,
-                    pos: self.char_pos
-                })
+            // End of file, which may have been reached early
+            // because the source couldn't be read.
+            '\0' => match self.read_error.take() {
+                Some(message) => Err(ParseError::ReadFailed {
+                    message,
+                    pos: start
+                }),
+                None => Ok(Token::new(TokenKind::EOF, None, start))
             }
 
-            // Numbers
-            c if c.is_numeric() => {
-                let starting_char_pos = self.char_pos;
-                let mut number_sequence = self.number_sequence();
+            // Integers written in hexadecimal, octal or binary,
+            // as well as hexadecimal floats.
+            '0' if matches!(self.peek_char(), 'x' | 'X' | 'o' | 'O' | 'b' | 'B') => {
+                self.radix_literal(start)
+            }
+
+            // Numbers, which may begin with a decimal point, e.g. `.5`
+            c if c.is_ascii_digit() || (self.at_decimal_separator() && self.peek_char().is_ascii_digit()) => {
+                let mut number_sequence = self.number_sequence()?;
+                let mut kind = TokenKind::IntLiteral;
+
+                // A leading zero could be mistaken for an octal prefix,
+                // as in C, so `010` is an error rather than ten.
+                if number_sequence.len() > 1 && number_sequence.starts_with('0') {
+                    return Err(ParseError::LeadingZero {
+                        value: number_sequence,
+                        pos: start,
+                        context: self.context(start)
+                    });
+                }
+
                 // If the character is a decimal point,
                 // we are dealing with a FloatLiteral.
-                if self.current_char() == '.' {
-                    // Add the decimal point
+                if self.at_decimal_separator() {
+                    // Add the decimal point, which is always a '.'
+                    // in the value whichever separator was used.
                     number_sequence.push('.');
                     self.next_char();
                     // Get the decimal portion
-                    let decimal_sequence = self.number_sequence();
-                    if decimal_sequence.len() == 0 {
-                        Err(
-                            format!("Unfinished FloatLiteral '{}' at position {}", number_sequence, self.char_pos)
-                        )
+                    let decimal_sequence = self.number_sequence()?;
+                    if decimal_sequence.is_empty() && self.at_decimal_separator() {
+                        return Err(self.malformed_number(number_sequence, start));
                     }
-                    else {
-                        // Add the decimal portion to the string value
-                        number_sequence.push_str(&decimal_sequence);
-    
-                        Ok(Token {
-                            kind: TokenKind::FloatLiteral,
-                            value: Some(number_sequence),
-                            pos: starting_char_pos
-                        })
+                    if decimal_sequence.is_empty() {
+                        return Err(ParseError::UnfinishedFloat {
+                            value: number_sequence,
+                            pos: start,
+                            context: self.context(start)
+                        });
+                    }
+                    // Add the decimal portion to the string value
+                    number_sequence.push_str(&decimal_sequence);
+                    kind = TokenKind::FloatLiteral;
+                }
+
+                // Scientific notation, such as `1.5e3` or `2E-4`,
+                // also produces a FloatLiteral.
+                if matches!(self.current_char(), 'e' | 'E') && self.at_exponent() {
+                    // Add the 'e' and the optional sign
+                    number_sequence.push(self.current_char());
+                    self.next_char();
+                    if matches!(self.current_char(), '+' | '-') {
+                        number_sequence.push(self.current_char());
+                        self.next_char();
                     }
+                    // `at_exponent()` has checked there's at least one digit
+                    let exponent_sequence = self.number_sequence()?;
+                    number_sequence.push_str(&exponent_sequence);
+                    kind = TokenKind::FloatLiteral;
                 }
-                // Else just return a normal IntLiteral
-                else {
-                    Ok(Token {
-                        kind: TokenKind::IntLiteral,
-                        value: Some(number_sequence),
-                        pos: starting_char_pos
-                    })
+
+                // A number can only have one decimal point, and none after
+                // its exponent, so `1.2.3` and `1e5.5` are errors.
+                if self.at_decimal_separator() {
+                    return Err(self.malformed_number(number_sequence, start));
+                }
+
+                Ok(Token::new(kind, Some(number_sequence), start))
+            }
+
+            // Digit separators must come between two digits,
+            // so a number can't begin with one, e.g. `_10`.
+            '_' if self.peek_char().is_ascii_digit() => {
+                Err(ParseError::MisplacedDigitSeparator {
+                    value: self.raw_number_sequence(),
+                    pos: start,
+                    context: self.context(start)
+                })
+            }
+
+            // Identifiers, these must start with a letter
+            // but can contain digits after the first char.
+            // The logical operators are keywords, so they
+            // can't be used as identifiers.
+            c if c.is_alphabetic() => {
+                let identifier = self.identifier();
+                match identifier.as_str() {
+                    "and" => Ok(Token::new(TokenKind::And, None, start)),
+                    "or" => Ok(Token::new(TokenKind::Or, None, start)),
+                    "not" => Ok(Token::new(TokenKind::Not, None, start)),
+                    "in" => Ok(Token::new(TokenKind::In, None, start)),
+                    _ => Ok(Token::new(TokenKind::Ident, Some(identifier), start))
                 }
             }
 
-            // Single char tokens
-            '+' | '-' | '/' | '*' |
-            '(' | ')' 
+            // Comparison operators. These may be two chars long,
+            // so the next char is peeked to decide which one we have.
+            // '!' is only a comparison when followed by '=', otherwise
+            // it's a factorial.
+            '=' | '<' | '>' | '!' if self.peek_char() == '=' => {
+                let token_kind = match self.current_char() {
+                    '=' => TokenKind::Equal,
+                    '!' => TokenKind::NotEqual,
+                    '<' => TokenKind::LessEqual,
+                    '>' => TokenKind::GreaterEqual,
+                    _ => unreachable!()
+                };
+                self.next_char();
+                self.next_char();
+                Ok(Token::new(token_kind, None, start))
+            }
+
+            // Operators made up of a doubled char. A single '^'
+            // is exponentiation, while '^^' is a bitwise xor.
+            // '**' is another way of writing exponentiation, while
+            // '&&' and '||' are the logical `and` and `or`, and
+            // '..' separates the bounds of a range.
+            '<' | '>' | '^' | '*' | '&' | '|' | '.' if self.peek_char() == self.current_char() => {
+                let token_kind = match self.current_char() {
+                    '<' => TokenKind::ShiftLeft,
+                    '>' => TokenKind::ShiftRight,
+                    '^' => TokenKind::BitXor,
+                    '*' => TokenKind::Pow,
+                    '&' => TokenKind::And,
+                    '|' => TokenKind::Or,
+                    '.' => TokenKind::DotDot,
+                    _ => unreachable!()
+                };
+                self.next_char();
+                self.next_char();
+                Ok(Token::new(token_kind, None, start))
+            }
+
+            // Single char tokens, including the Unicode multiplication,
+            // division and minus signs, which are often pasted in from
+            // documents, as another way of writing '*', '/' and '-'.
+            '+' | '-' | '/' | '*' | '×' | '÷' | '−' | '%' | '^' | '!' |
+            '<' | '>' | '=' | '&' | '|' | '(' | ')' | '[' | ']' | '{' | '}' | ';' | ',' | '?' | ':' 
             => {
                 // This syntax may look strange, but it massively reduces
                 // code length compared having one match statement for
                 // each single char token.
                 let token_kind = match self.current_char() {
                     '+' => TokenKind::Add,
-                    '-' => TokenKind::Sub,
-                    '/' => TokenKind::Div,
-                    '*' => TokenKind::Mult,
+                    '-' | '−' => TokenKind::Sub,
+                    '/' | '÷' => TokenKind::Div,
+                    '*' | '×' => TokenKind::Mult,
+                    '%' => TokenKind::Mod,
+                    '^' => TokenKind::Pow,
+                    '!' => TokenKind::Factorial,
+                    '<' => TokenKind::Less,
+                    '>' => TokenKind::Greater,
+                    '=' => TokenKind::Assign,
+                    '&' => TokenKind::BitAnd,
+                    '|' => TokenKind::BitOr,
                     '(' => TokenKind::LParen,
                     ')' => TokenKind::RParen,
+                    '[' => TokenKind::LBracket,
+                    ']' => TokenKind::RBracket,
+                    '{' => TokenKind::LBrace,
+                    '}' => TokenKind::RBrace,
+                    ';' => TokenKind::Semicolon,
+                    ',' => TokenKind::Comma,
+                    '?' => TokenKind::Question,
+                    ':' => TokenKind::Colon,
                     _ => unreachable!()
                 };
                 self.next_char();
-                Ok(Token {
-                    kind: token_kind,
-                    value: None,
-                    // next_char() increments char_pos so we undo that here.
-                    pos: self.char_pos-1
-                })
+                Ok(Token::new(token_kind, None, start))
             }
 
-            _ => Err(format!("Unrecognised char '{}' at postion {}", self.current_char(), self.char_pos))
+            c => Err(ParseError::UnrecognisedChar {
+                char: c,
+                pos: start,
+                context: self.context(start)
+            })
+        }
+    }
+}
+
+/// Iterates over the token stream, stopping before the `EOF` token.
+/// 
+/// If an error is encountered it's yielded as the final item, as the
+/// tokeniser can't continue past an invalid char sequence.
+impl Iterator for Tokeniser {
+    type Item = Result<Token, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
         }
+        match self.next_token() {
+            Ok(token) if token.kind == TokenKind::EOF => {
+                self.finished = true;
+                None
+            },
+            Ok(token) => Some(Ok(token)),
+            Err(err) => {
+                self.finished = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+
+    #[test]
+    fn peeked_tokens_are_returned_by_next_token() {
+        let mut tokeniser = Tokeniser::new("12 + x");
+
+        assert_eq!(tokeniser.peek_token().unwrap().kind(), TokenKind::IntLiteral);
+        assert_eq!(tokeniser.peek_token().unwrap().text(), Some("12"));
+        // Peeking doesn't consume anything.
+        assert_eq!(tokeniser.char_pos, 0);
+
+        let first = tokeniser.next_token().unwrap();
+        assert_eq!((first.kind(), first.text()), (TokenKind::IntLiteral, Some("12")));
+        assert_eq!(first.position().index, 0);
+        assert_eq!(tokeniser.char_pos, 2);
+
+        let second = tokeniser.next_token().unwrap();
+        assert_eq!(second.kind(), TokenKind::Add);
+        assert_eq!(second.position().index, 3);
+        assert_eq!(tokeniser.char_pos, 4);
+
+        assert_eq!(tokeniser.peek_token().unwrap().position().index, 5);
+        assert_eq!(tokeniser.char_pos, 4);
+        let third = tokeniser.next_token().unwrap();
+        assert_eq!((third.kind(), third.text()), (TokenKind::Ident, Some("x")));
+        assert_eq!((third.position().index, third.position().column), (5, 6));
+        assert_eq!(tokeniser.char_pos, 6);
+
+        assert_eq!(tokeniser.peek_token().unwrap().kind(), TokenKind::EOF);
+        assert_eq!(tokeniser.next_token().unwrap().kind(), TokenKind::EOF);
+    }
+
+    #[test]
+    fn peeked_errors_are_returned_by_next_token() {
+        let mut tokeniser = Tokeniser::new("1 $");
+        tokeniser.next_token().unwrap();
+        let peeked = tokeniser.peek_token().unwrap_err();
+        assert_eq!(tokeniser.next_token().unwrap_err(), peeked);
+        assert_eq!(tokeniser.char_pos, 1);
+    }
+
+    fn parse(source: &str) -> Result<Box<dyn crate::ast::Node>, ParseError> {
+        crate::parser::Parser::new(source).parse()
+    }
+
+    fn evaluate(source: &str) -> Result<crate::ast::Number, crate::error::EvalError> {
+        parse(source).unwrap().evaluate()
+    }
+
+    #[test]
+    fn block_comments_are_skipped() {
+        assert_eq!(evaluate("1 + /* note */ 2"), Ok(3.0));
+        assert_eq!(evaluate("2 /* the base */ ^ /* to the */ 3"), Ok(8.0));
+        assert_eq!(evaluate("/* a note\n   over lines */ 4 *\n/**/ 5"), Ok(20.0));
+        // Block comments don't nest, so this one ends at the first `*/`.
+        assert_eq!(evaluate("/* a /* b */ 6"), Ok(6.0));
+
+        let error = parse("1 + \n  /* note\n 2").err().unwrap();
+        let start = Position { index: 7, line: 2, column: 3 };
+        assert!(matches!(&error, ParseError::UnterminatedComment { pos, .. } if *pos == start));
+        assert!(error.is_incomplete());
+    }
+
+    #[test]
+    fn e_only_begins_an_exponent_before_a_digit() {
+        let e = core::f64::consts::E;
+        assert_eq!(evaluate("2e"), Ok(2.0 * e));
+        assert_eq!(evaluate("2e+1"), Ok(20.0));
+        assert_eq!(evaluate("2e + 1"), Ok(2.0 * e + 1.0));
+        assert_eq!(evaluate("1.5E-1"), Ok(0.15));
+        assert!(matches!(parse("0x1p+").err(), Some(ParseError::UnfinishedExponent { .. })));
+    }
+
+    #[test]
+    fn numbers_have_at_most_one_point() {
+        assert!(matches!(parse("1.2.3").err(), Some(ParseError::MalformedNumber { value, .. }) if value == "1.2.3"));
+        assert!(matches!(parse("1.2.3.4").err(), Some(ParseError::MalformedNumber { value, .. }) if value == "1.2.3.4"));
+        assert!(matches!(parse("1e5.5").err(), Some(ParseError::MalformedNumber { .. })));
+        assert!(matches!(parse("010").err(), Some(ParseError::LeadingZero { .. })));
+        assert!(parse(".5 + 0.5").is_ok());
+    }
+
+    #[test]
+    fn literals_too_large_for_a_float_overflow() {
+        assert_eq!(evaluate(&"9".repeat(40)), Ok(1e40));
+        assert!(matches!(parse(&"9".repeat(400)).err(), Some(ParseError::LiteralOverflow { .. })));
+        assert!(matches!(parse("1e400").err(), Some(ParseError::LiteralOverflow { .. })));
+        assert!(matches!(parse("0x1p1024").err(), Some(ParseError::LiteralOverflow { .. })));
+        assert!(matches!(parse(&format!("0x1{}", "0".repeat(256))).err(), Some(ParseError::LiteralOverflow { .. })));
+        assert!(matches!(parse(&format!("0b1{}", "0".repeat(1024))).err(), Some(ParseError::LiteralOverflow { .. })));
+    }
+
+    #[test]
+    fn integers_too_large_for_an_integer_are_floats_in_every_radix() {
+        let two_to_the_64 = 18446744073709551616.0;
+        assert_eq!(evaluate("18446744073709551616"), Ok(two_to_the_64));
+        assert_eq!(evaluate("0x1_0000_0000_0000_0000"), Ok(two_to_the_64));
+        assert_eq!(evaluate("0o2_000_000_000_000_000_000_000"), Ok(two_to_the_64));
+        assert_eq!(evaluate(&format!("0b1{}", "0".repeat(64))), Ok(two_to_the_64));
+        assert_eq!(evaluate("0xFFFFFFFFFFFFFFFFF"), Ok(295147905179352825855.0));
+        assert_eq!(evaluate(&format!("0b{}{}", "1".repeat(53), "0".repeat(971))), Ok(f64::MAX));
+        // Rounds up to 2^1024, which is too large.
+        assert!(matches!(parse(&format!("0b{}", "1".repeat(1024))).err(), Some(ParseError::LiteralOverflow { .. })));
+        assert_eq!(evaluate("0o777777777777777777777"), Ok(9223372036854775807.0));
+
+        // The largest integers are still exact.
+        let max = parse("0x7FFF_FFFF_FFFF_FFFF").unwrap();
+        assert_eq!(max.evaluate_exact(), Ok(crate::ast::Value::Int(i64::MAX)));
+        let next = parse("0x8000_0000_0000_0000").unwrap();
+        assert_eq!(next.evaluate_exact(), Ok(crate::ast::Value::Float(9223372036854775808.0)));
     }
 }